@@ -1,10 +1,9 @@
 use clap::Parser;
-use fluxdb::net::protocol::{Request, Response};
+use fluxdb::client::{Client, ClientOptions};
+use fluxdb::net::protocol::{Framing, Request};
 use futures::future::join_all;
 use serde_json::json;
 use std::time::Instant;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::TcpStream;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "FluxDB Network Benchmarker")]
@@ -36,23 +35,50 @@ struct Args {
     /// Run a mixed workload (writes, reads, patches, deletes)
     #[arg(short, long, default_value_t = false)]
     mixed: bool,
+
+    /// Use length-prefixed MessagePack framing instead of line-delimited JSON
+    #[arg(short, long, default_value_t = false)]
+    binary: bool,
+
+    /// Deflate-compress binary frames (needs --binary)
+    #[arg(long, default_value_t = false, requires = "binary")]
+    compress: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
-    println!("Starting network benchmark on {}...", args.addr);
+    let framing = match (args.binary, args.compress) {
+        (true, true) => Framing::MsgpackDeflate,
+        (true, false) => Framing::Msgpack,
+        _ => Framing::Json,
+    };
+
+    println!(
+        "Starting network benchmark on {} ({:?} framing)...",
+        args.addr, framing
+    );
+
+    // one pooled connection per concurrent task. The client refuses to start if the server
+    // doesn't grant the framing, so numbers are never silently measured on the wrong one
+    let client = Client::connect_with(ClientOptions {
+        framing,
+        client_name: "fluxdb-bench".to_string(),
+        pool_size: args.concurrency,
+        ..ClientOptions::tcp(&args.addr)
+    })
+    .await?;
 
     if args.mixed {
-        run_mixed_benchmark(&args.addr, args.writes, args.concurrency).await?;
+        run_mixed_benchmark(&client, args.writes, args.concurrency).await?;
         return Ok(());
     }
 
     if args.writes > 0 {
         bench_op(
             "SET",
-            &args.addr,
+            &client,
             args.writes,
             args.concurrency,
             |i| {
@@ -68,7 +94,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let write_count = args.writes.max(1);
         bench_op(
             "GET",
-            &args.addr,
+            &client,
             args.reads,
             args.concurrency,
             move |i| {
@@ -83,7 +109,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let write_count = args.writes.max(1);
         bench_op(
             "PATCH",
-            &args.addr,
+            &client,
             args.patches,
             args.concurrency,
             move |i| {
@@ -99,7 +125,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let write_count = args.writes.max(1);
         bench_op(
             "DELETE",
-            &args.addr,
+            &client,
             args.deletes,
             args.concurrency,
             move |i| {
@@ -114,7 +140,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 async fn run_mixed_benchmark(
-    addr: &str,
+    client: &Client,
     total_ops: usize,
     concurrency: usize,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -128,7 +154,7 @@ async fn run_mixed_benchmark(
     let mut tasks = Vec::new();
 
     for c in 0..concurrency {
-        let addr = addr.to_string();
+        let client = client.clone();
         let start_idx = c * ops_per_conn;
         let end_idx = if c == concurrency - 1 {
             total_ops
@@ -138,12 +164,6 @@ async fn run_mixed_benchmark(
 
         tasks.push(tokio::spawn(async move {
             let mut durations = Vec::new();
-            let stream = TcpStream::connect(addr).await.map_err(|e| e.to_string())?;
-            stream.set_nodelay(true).map_err(|e| e.to_string())?;
-
-            let (read_half, mut write_half) = stream.into_split();
-            let mut reader = BufReader::new(read_half);
-            let mut line = String::new();
 
             for i in start_idx..end_idx {
                 let op_start = Instant::now();
@@ -161,25 +181,7 @@ async fn run_mixed_benchmark(
                     _ => Request::Del { key },
                 };
 
-                let mut req_bytes = serde_json::to_vec(&req).map_err(|e| e.to_string())?;
-                req_bytes.push(b'\n');
-
-                write_half
-                    .write_all(&req_bytes)
-                    .await
-                    .map_err(|e| e.to_string())?;
-
-                line.clear();
-                let n = reader
-                    .read_line(&mut line)
-                    .await
-                    .map_err(|e| e.to_string())?;
-                if n == 0 {
-                    return Err("Connection closed by server".to_string());
-                }
-
-                let _resp: Response =
-                    serde_json::from_str(line.trim()).map_err(|e| e.to_string())?;
+                client.request(req).await.map_err(|e| e.to_string())?;
                 durations.push(op_start.elapsed());
             }
 
@@ -212,7 +214,7 @@ async fn run_mixed_benchmark(
 
 async fn bench_op<F>(
     name: &str,
-    addr: &str,
+    client: &Client,
     total_ops: usize,
     concurrency: usize,
     req_fn: F,
@@ -230,7 +232,7 @@ where
     let mut tasks = Vec::new();
 
     for c in 0..concurrency {
-        let addr = addr.to_string();
+        let client = client.clone();
         let start_idx = c * ops_per_conn;
         let end_idx = if c == concurrency - 1 {
             total_ops
@@ -240,34 +242,11 @@ where
 
         tasks.push(tokio::spawn(async move {
             let mut durations = Vec::new();
-            let stream = TcpStream::connect(addr).await.map_err(|e| e.to_string())?;
-            stream.set_nodelay(true).map_err(|e| e.to_string())?;
-            let (read_half, mut write_half) = stream.into_split();
-            let mut reader = BufReader::new(read_half);
-            let mut line = String::new();
 
             for i in start_idx..end_idx {
                 let op_start = Instant::now();
                 let req = req_fn(i);
-                let mut req_bytes = serde_json::to_vec(&req).map_err(|e| e.to_string())?;
-                req_bytes.push(b'\n');
-
-                write_half
-                    .write_all(&req_bytes)
-                    .await
-                    .map_err(|e| e.to_string())?;
-
-                line.clear();
-                let n = reader
-                    .read_line(&mut line)
-                    .await
-                    .map_err(|e| e.to_string())?;
-                if n == 0 {
-                    return Err("Connection closed by server".to_string());
-                }
-
-                let _resp: Response =
-                    serde_json::from_str(line.trim()).map_err(|e| e.to_string())?;
+                client.request(req).await.map_err(|e| e.to_string())?;
                 durations.push(op_start.elapsed());
             }
 
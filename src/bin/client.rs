@@ -1,18 +1,29 @@
 use clap::{Parser, Subcommand};
-use std::{io::Write, sync::Arc};
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::TcpStream,
-    sync::{mpsc, oneshot, Mutex},
-};
+use std::io::Write;
+use tokio::io::{AsyncBufReadExt, BufReader};
 
-use fluxdb::net::protocol::{Request, Response};
+use fluxdb::{
+    client::{Client, ClientOptions, ConnectArgs},
+    net::protocol::{Framing, Request, Response},
+};
 
 #[derive(Parser, Debug)] // Parser - converts command line arguments into this struct
 #[command(author, version, about = "FluxDB TCP client")]
 struct Cli {
-    #[arg(long, default_value = "127.0.0.1:7000")]
-    addr: String,
+    #[command(flatten)]
+    connect: ConnectArgs,
+
+    /// Use length-prefixed MessagePack framing instead of line-delimited JSON
+    #[arg(long, default_value_t = false)]
+    binary: bool,
+
+    /// Deflate-compress binary frames (needs --binary)
+    #[arg(long, default_value_t = false, requires = "binary")]
+    compress: bool,
+
+    /// Trace id the server tags the request's log lines with (not used by shell or subscribe)
+    #[arg(long)]
+    trace_id: Option<String>,
 
     #[command(subcommand)]
     command: Command,
@@ -25,6 +36,19 @@ enum Command {
     Del { key: String },
     Patch { key: String, delta: String },
     Snapshot,
+    Stats,
+    Info,
+    #[command(name = "dbsize")]
+    DbSize,
+    /// Key names matching a glob (every key without one), sorted
+    Keys {
+        pattern: Option<String>,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Delete every key
+    #[command(name = "flushall")]
+    FlushAll,
     Shell,
     Subscribe { key: String },
 }
@@ -33,125 +57,75 @@ enum Command {
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
+    let framing = match (cli.binary, cli.compress) {
+        (true, true) => Framing::MsgpackDeflate,
+        (true, false) => Framing::Msgpack,
+        _ => Framing::Json,
+    };
+    // one connection is plenty for a cli; the client library reconnects (and re-subscribes)
+    // if the server goes away
+    let options = ClientOptions {
+        framing,
+        pool_size: 1,
+        ..cli.connect.client_options("fluxdb-client")?
+    };
+
     // Two modes:
-    // - run_once: one command over one connection
+    // - run_once: one command, then exit (subscribe keeps printing events)
     // - run_shell: persistent interactive session
-    match cli.command {
-        Command::Shell => run_shell(&cli.addr).await?,
-        other => run_once(&cli.addr, &other).await?,
+    match &cli.command {
+        Command::Shell => {
+            let name = cli.connect.target();
+            let options = ClientOptions {
+                client_name: "fluxdb-shell".to_string(),
+                ..options
+            };
+            run_shell(Client::connect_with(options).await?, &name).await?
+        }
+        other => run_once(Client::connect_with(options).await?, other, cli.trace_id.clone()).await?,
     }
 
     Ok(())
 }
 
-async fn run_once(addr: &str, command: &Command) -> Result<(), Box<dyn std::error::Error>> {
-    let req = build_request(command)?;
-    let stream = TcpStream::connect(addr).await?;
-    stream.set_nodelay(true)?;
-    let (read_half, mut write_half) = stream.into_split();
-    let mut reader = BufReader::new(read_half);
-
-    // Line-delimited JSON request.
-    let line = serde_json::to_string(&req)?;
-    write_half.write_all(line.as_bytes()).await?;
-    write_half.write_all(b"\n").await?;
-
-    match command {
-        Command::Subscribe { .. } => {
-            // Subscribe is a long-running stream, so keep printing until disconnect.
-            let mut line = String::new();
-            loop {
-                line.clear();
-                let n = reader.read_line(&mut line).await?;
-                if n == 0 {
-                    break;
-                }
-                println!("{}", line.trim());
+async fn run_once(
+    client: Client,
+    command: &Command,
+    trace_id: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match build_request(command)? {
+        Request::Subscribe { key } => {
+            // Subscribe is a long-running stream, so keep printing until the client gives up.
+            // Printed as json whatever the wire framing is.
+            let mut events = client.subscribe(key.clone()).await?;
+            println!("{}", serde_json::to_string(&Response::Subscribed { key })?);
+            while let Some(event) = events.recv().await {
+                println!("{}", serde_json::to_string(&Response::Event { event })?);
             }
         }
-        _ => {
-            let mut line = String::new();
-            let n = reader.read_line(&mut line).await?;
-            if n == 0 {
-                return Err("server closed without response".into());
+        req => {
+            let resp = match trace_id {
+                Some(trace_id) => client.request_traced(req, trace_id).await?,
+                None => client.request(req).await?,
+            };
+            match resp {
+                // a report for people, printed as json whatever the wire framing is
+                Response::Info { info } => println!("{}", serde_json::to_string_pretty(&info)?),
+                resp => println!("{resp:?}"),
             }
-            let resp: Response = serde_json::from_str(line.trim())?;
-            println!("{resp:?}");
         }
     }
-
     Ok(())
 }
 
-async fn run_shell(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let stream = TcpStream::connect(addr).await?;
-    stream.set_nodelay(true)?;
-    let (read_half, mut write_half) = stream.into_split();
-    let mut socket_reader = BufReader::new(read_half);
-
-    // pending holds the responder for the single in-flight command.
-    // No request_id yet, so we intentionally allow one outstanding request at a time.
-    let pending: Arc<Mutex<Option<oneshot::Sender<Response>>>> = Arc::new(Mutex::new(None));
-
-    // event_tx/event_rx carries asynchronous subscription events to a dedicated printer task.
-    let (event_tx, mut event_rx) = mpsc::channel::<Response>(128);
-
-    let pending_for_reader = pending.clone();
-    tokio::spawn(async move {
-        // Socket reader loop: parse each server line and route it.
-        let mut line = String::new();
-        loop {
-            line.clear();
-            let n = match socket_reader.read_line(&mut line).await {
-                Ok(n) => n,
-                Err(e) => {
-                    eprintln!("read error: {e}");
-                    break;
-                }
-            };
-            if n == 0 {
-                break;
-            }
-
-            let resp: Response = match serde_json::from_str(line.trim()) {
-                Ok(r) => r,
-                Err(e) => {
-                    eprintln!("invalid response json: {e}");
-                    continue;
-                }
-            };
-
-            match resp {
-                Response::Event { .. } => {
-                    // Events are stream messages; forward to event queue.
-                    let _ = event_tx.send(resp).await;
-                }
-                other => {
-                    // Non-event is treated as the reply for current pending command.
-                    if let Some(tx) = pending_for_reader.lock().await.take() {
-                        let _ = tx.send(other);
-                    } else {
-                        // Useful signal if server sends a non-event without a waiting request.
-                        println!("unsolicited: {other:?}");
-                    }
-                }
-            }
-        }
-    });
-
-    tokio::spawn(async move {
-        // Event printer loop. Runs independently so shell input stays usable.
-        while let Some(ev) = event_rx.recv().await {
-            println!("[event] {ev:?}");
-        }
-    });
-
-    println!("shell connected to {addr}");
-    println!("commands: set/get/del/patch/snapshot/subscribe, type 'exit' to quit");
+async fn run_shell(client: Client, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    println!("shell connected to {name}");
+    println!("commands: set/get/del/patch/snapshot/stats/info/dbsize/keys/flushall/subscribe, type 'exit' to quit");
 
     let stdin = tokio::io::stdin();
     let mut stdin_reader = BufReader::new(stdin);
     let mut input = String::new();
+    let mut next_id: u64 = 1;
 
     loop {
         // Interactive shell loop.
@@ -180,30 +154,29 @@ async fn run_shell(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
             }
         };
 
-        // oneshot pair = one response promise for this one command.
-        let (tx, rx) = oneshot::channel();
-        {
-            let mut slot = pending.lock().await;
-            if slot.is_some() {
-                println!("request already in-flight, wait");
-                continue;
-            }
-            *slot = Some(tx);
-        }
-
-        // Convert shell command to wire request JSON.
-        let line = serde_json::to_string(&req)?;
-        write_half.write_all(line.as_bytes()).await?;
-        write_half.write_all(b"\n").await?;
-
-        // Wait until socket-reader routes the matching non-event response.
-        match rx.await {
-            Ok(resp) => println!("{resp:?}"),
-            Err(_) => {
-                println!("connection closed before response");
-                break;
+        // numbers the replies, they may come back out of order since the client pipelines
+        let id = next_id;
+        next_id += 1;
+
+        // Don't block the prompt: the reply (and any events) are printed whenever they arrive.
+        let client = client.clone();
+        tokio::spawn(async move {
+            match req {
+                Request::Subscribe { key } => match client.subscribe(key.clone()).await {
+                    Ok(mut events) => {
+                        println!("[{id}] {:?}", Response::Subscribed { key });
+                        while let Some(event) = events.recv().await {
+                            println!("[event] {:?}", Response::Event { event });
+                        }
+                    }
+                    Err(e) => println!("[{id}] {e}"),
+                },
+                req => match client.request(req).await {
+                    Ok(resp) => println!("[{id}] {resp:?}"),
+                    Err(e) => println!("[{id}] {e}"),
+                },
             }
-        }
+        });
     }
 
     Ok(())
@@ -223,6 +196,14 @@ fn build_request(command: &Command) -> Result<Request, Box<dyn std::error::Error
             delta: serde_json::from_str(delta)?,
         },
         Command::Snapshot => Request::Snapshot,
+        Command::Stats => Request::Stats,
+        Command::Info => Request::Info,
+        Command::DbSize => Request::DbSize,
+        Command::Keys { pattern, limit } => Request::Keys {
+            pattern: pattern.clone(),
+            limit: *limit,
+        },
+        Command::FlushAll => Request::FlushAll,
         Command::Subscribe { key } => Request::Subscribe { key: key.clone() },
         Command::Shell => {
             return Err("shell is interactive; no single request mapping".into());
@@ -297,6 +278,42 @@ fn parse_shell_request(input: &str) -> Result<Request, String> {
             }
             Ok(Request::Snapshot)
         }
+        "stats" => {
+            if !rest.is_empty() {
+                return Err("usage: stats".to_string());
+            }
+            Ok(Request::Stats)
+        }
+        "info" => {
+            if !rest.is_empty() {
+                return Err("usage: info".to_string());
+            }
+            Ok(Request::Info)
+        }
+        "dbsize" => {
+            if !rest.is_empty() {
+                return Err("usage: dbsize".to_string());
+            }
+            Ok(Request::DbSize)
+        }
+        "keys" => {
+            let mut p = rest.split_whitespace();
+            let pattern = p.next().map(str::to_string);
+            let limit = match p.next() {
+                Some(n) => Some(n.parse().map_err(|_| "usage: keys [pattern] [limit]".to_string())?),
+                None => None,
+            };
+            if p.next().is_some() {
+                return Err("usage: keys [pattern] [limit]".to_string());
+            }
+            Ok(Request::Keys { pattern, limit })
+        }
+        "flushall" => {
+            if !rest.is_empty() {
+                return Err("usage: flushall".to_string());
+            }
+            Ok(Request::FlushAll)
+        }
         "subscribe" => {
             if rest.is_empty() {
                 return Err("usage: subscribe <key>".to_string());
@@ -306,7 +323,8 @@ fn parse_shell_request(input: &str) -> Result<Request, String> {
             })
         }
         _ => Err(
-            "unknown command. use: set/get/del/patch/snapshot/subscribe/exit".to_string(),
+            "unknown command. use: set/get/del/patch/snapshot/stats/info/dbsize/keys/flushall/subscribe/exit"
+                .to_string(),
         ),
     }
 }
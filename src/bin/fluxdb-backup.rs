@@ -0,0 +1,92 @@
+use std::{path::PathBuf, time::Duration};
+
+use clap::{Parser, Subcommand};
+use fluxdb::{
+    client::{blocking::Client, ClientOptions, ConnectArgs},
+    engine::backup::{self, Manifest},
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Take, check and restore FluxDB backups")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Ask a running server for a backup (admin). The directory is relative to the server's
+    /// --backup-root, on the server's machine
+    Create {
+        dest_dir: String,
+
+        #[command(flatten)]
+        connect: ConnectArgs,
+
+        /// Seconds to wait for the server to finish copying
+        #[arg(long, default_value_t = 600)]
+        timeout: u64,
+    },
+    /// Check every file of a backup against its manifest
+    Verify { dir: PathBuf },
+    /// Verify a backup and swap it in as a server's data dir (server stopped)
+    Restore {
+        backup_dir: PathBuf,
+
+        #[arg(long, default_value = "./fluxdb")]
+        data_dir: PathBuf,
+
+        /// Restore over an existing data dir (it is moved aside, not deleted)
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+}
+
+//   fluxdb-backup create fluxdb-2024-06-01 --user admin   # server: --backup-root /var/backups
+//   fluxdb-backup verify /var/backups/fluxdb-2024-06-01
+//   fluxdb-backup restore /var/backups/fluxdb-2024-06-01 --data-dir ./fluxdb --force
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    match Cli::parse().command {
+        Command::Create {
+            dest_dir,
+            connect,
+            timeout,
+        } => {
+            let options = ClientOptions {
+                request_timeout: Duration::from_secs(timeout),
+                ..connect.client_options("fluxdb-backup")?
+            };
+            let manifest = Client::connect_with(options)?.backup(dest_dir.clone())?;
+            println!("backup written to {dest_dir} under the server's backup root");
+            print_manifest(&manifest);
+        }
+        Command::Verify { dir } => {
+            let manifest = backup::verify(&dir)?;
+            println!("{} is a complete backup", dir.display());
+            print_manifest(&manifest);
+        }
+        Command::Restore {
+            backup_dir,
+            data_dir,
+            force,
+        } => {
+            let (manifest, previous) = backup::restore(&backup_dir, &data_dir, force)?;
+            if let Some(previous) = previous {
+                println!("previous data moved to {}", previous.display());
+            }
+            println!("restored {} into {}", backup_dir.display(), data_dir.display());
+            print_manifest(&manifest);
+        }
+    }
+    Ok(())
+}
+
+fn print_manifest(manifest: &Manifest) {
+    let bytes: u64 = manifest.files.iter().map(|f| f.bytes).sum();
+    println!(
+        "  snapshot at {}, wal up to {}, {} files, {bytes} bytes",
+        manifest.snapshot_lsn,
+        manifest.end_lsn,
+        manifest.files.len()
+    );
+}
@@ -0,0 +1,112 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use clap::Parser;
+use futures::future::try_join_all;
+use fluxdb::{
+    client::{Client, ConnectArgs},
+    dump::{self, Format, Record},
+    engine::config::Recovery,
+    logging::{self, LogFormat},
+    store::glob,
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Export FluxDB documents as JSON Lines or CSV")]
+struct Cli {
+    /// Read a data dir directly (snapshot + WAL) instead of asking a server
+    #[arg(long, conflicts_with_all = ["unix", "user", "tls"])]
+    data_dir: Option<PathBuf>,
+
+    /// With --data-dir: skip damaged WAL records (logged, their writes left out) instead of
+    /// failing on damage that isn't a torn tail
+    #[arg(long, requires = "data_dir")]
+    recover_best_effort: bool,
+
+    /// Only keys starting with this
+    #[arg(long, default_value = "")]
+    prefix: String,
+
+    /// jsonl or csv (flat documents only)
+    #[arg(long, default_value = "jsonl")]
+    format: Format,
+
+    /// Write here instead of stdout
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+
+    /// Gets in flight at once (pipelined over the connection)
+    #[arg(long, default_value_t = 256, conflicts_with = "data_dir")]
+    batch: usize,
+
+    #[command(flatten)]
+    connect: ConnectArgs,
+}
+
+//   fluxdb-dump --prefix user: > users.jsonl
+//   fluxdb-dump --data-dir ./fluxdb --format csv -o all.csv
+//   fluxdb-dump --data-dir ./fluxdb --recover-best-effort > salvaged.jsonl
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    if cli.batch == 0 {
+        return Err("--batch must be at least 1".into());
+    }
+
+    let out: Box<dyn Write> = match &cli.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let mut writer = dump::Writer::new(out, cli.format);
+
+    match &cli.data_dir {
+        Some(dir) => {
+            let recovery = if cli.recover_best_effort {
+                // each skipped record is logged, on stderr next to the summary
+                logging::init("error", LogFormat::Text)?;
+                Recovery::BestEffort
+            } else {
+                Recovery::Strict
+            };
+            for record in dump::read_data_dir(dir, &cli.prefix, recovery)? {
+                writer.push(record)?;
+            }
+        }
+        None => from_server(&cli, &mut writer).await?,
+    }
+
+    let dumped = writer.finish()?;
+    eprintln!("dumped {dumped} documents");
+    Ok(())
+}
+
+// keys, then their gets pipelined --batch at a time, each batch written as it arrives. Not a
+// point in time copy: a key written meanwhile may show its newer value, and one deleted
+// meanwhile is left out
+async fn from_server(cli: &Cli, writer: &mut dump::Writer<impl Write>) -> Result<(), Box<dyn std::error::Error>> {
+    let options = cli.connect.client_options("fluxdb-dump")?;
+    let client = Client::connect_with(options).await?;
+
+    let pattern = format!("{}*", glob::escape(&cli.prefix));
+    let (keys, _) = client.keys(Some(pattern), None).await?;
+    for batch in keys.chunks(cli.batch) {
+        let gets = batch.iter().map(|key| {
+            let client = &client;
+            async move {
+                let doc = client.get(key.clone()).await.map_err(|e| format!("{key}: {e}"))?;
+                Ok::<_, String>(doc.map(|doc| Record {
+                    key: key.clone(),
+                    value: doc.value,
+                    version: doc.version,
+                }))
+            }
+        });
+        for record in try_join_all(gets).await?.into_iter().flatten() {
+            writer.push(record)?;
+        }
+    }
+    Ok(())
+}
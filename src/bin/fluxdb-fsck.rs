@@ -0,0 +1,73 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use fluxdb::engine::fsck;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Check (and repair) a FluxDB data dir while the server is stopped")]
+struct Args {
+    /// Server data dir
+    #[arg(default_value = "./fluxdb")]
+    data_dir: PathBuf,
+
+    /// Truncate a torn tail, and quarantine stray files and a snapshot the wal can replace.
+    /// Mid-log corruption, missing segments and version regressions are only reported
+    #[arg(long, default_value_t = false)]
+    repair: bool,
+}
+
+// exit codes: 0 clean (or everything found was repaired), 1 problems left, 2 could not check
+//
+//   fluxdb-fsck ./fluxdb
+//   fluxdb-fsck ./fluxdb --repair
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("fluxdb-fsck: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+// true when nothing is left to fix
+fn run(args: &Args) -> Result<bool, String> {
+    let mut report = fsck::check(&args.data_dir)?;
+    print_report(&args.data_dir, &report);
+
+    if args.repair {
+        let done = fsck::repair(&args.data_dir, &report)?;
+        if !done.is_empty() {
+            println!();
+            for action in &done {
+                println!("repaired: {action}");
+            }
+            // what is left, from the files as they are now
+            report = fsck::check(&args.data_dir)?;
+            println!();
+            print_report(&args.data_dir, &report);
+        }
+    } else if report.problems.iter().any(|p| p.repairable()) {
+        println!("run again with --repair to fix the problems marked [repairable]");
+    }
+    Ok(report.problems.is_empty())
+}
+
+fn print_report(data_dir: &std::path::Path, report: &fsck::Report) {
+    let snapshot = report.snapshot_lsn.map_or("none".to_string(), |lsn| lsn.to_string());
+    println!(
+        "{}: {} segments, {} records, snapshot at {snapshot}",
+        data_dir.display(),
+        report.segments.len(),
+        report.records
+    );
+    for problem in &report.problems {
+        let tag = if problem.repairable() { " [repairable]" } else { "" };
+        println!("  {problem}{tag}");
+    }
+    if report.problems.is_empty() {
+        println!("  ok");
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use clap::Parser;
+use futures::future::try_join_all;
+use fluxdb::{
+    client::{Client, ConnectArgs},
+    dump::{self, Format, Record},
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Import JSON Lines or CSV made by fluxdb-dump into a FluxDB server")]
+struct Cli {
+    /// File to load, stdin when left out
+    input: Option<PathBuf>,
+
+    /// jsonl or csv
+    #[arg(long, default_value = "jsonl")]
+    format: Format,
+
+    /// Writes in flight at once (pipelined over the connection)
+    #[arg(long, default_value_t = 256)]
+    batch: usize,
+
+    #[command(flatten)]
+    connect: ConnectArgs,
+}
+
+//   fluxdb-load users.jsonl --addr 10.0.0.5:7000
+//   fluxdb-dump --addr old:7000 | fluxdb-load --addr new:7000
+//
+// every document is a set, so the server gives it a fresh version; the dumped one is ignored.
+// Stops at the first bad line or failed write, what was loaded before it stays
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    if cli.batch == 0 {
+        return Err("--batch must be at least 1".into());
+    }
+
+    let input: Box<dyn Read> = match &cli.input {
+        Some(path) => Box::new(File::open(path)?),
+        None => Box::new(io::stdin()),
+    };
+    let records = dump::read(input, cli.format)?;
+
+    let options = cli.connect.client_options("fluxdb-load")?;
+    let client = Client::connect_with(options).await?;
+
+    let mut loaded = 0;
+    let mut batch: Vec<Record> = Vec::with_capacity(cli.batch);
+    for record in records {
+        batch.push(record?);
+        if batch.len() == cli.batch {
+            loaded += write_batch(&client, &mut batch).await?;
+        }
+    }
+    loaded += write_batch(&client, &mut batch).await?;
+    eprintln!("loaded {loaded} documents");
+    Ok(())
+}
+
+// the writes of a batch go out together, so the server fsyncs them as one group
+async fn write_batch(client: &Client, batch: &mut Vec<Record>) -> Result<usize, Box<dyn std::error::Error>> {
+    let writes = batch.drain(..).map(|record| {
+        let key = record.key.clone();
+        async move {
+            client
+                .set(record.key, record.value)
+                .await
+                .map_err(|e| format!("{key}: {e}"))
+        }
+    });
+    Ok(try_join_all(writes).await?.len())
+}
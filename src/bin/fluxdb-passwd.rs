@@ -0,0 +1,43 @@
+use std::io::{BufRead, Write};
+
+use clap::Parser;
+use fluxdb::net::auth::{generate_token, hash_secret};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Hash a FluxDB password or API token for the server auth file")]
+struct Args {
+    /// User name for the [[users]] entry
+    user: String,
+
+    /// Generate a random API token instead of reading a password from stdin
+    #[arg(long, default_value_t = false)]
+    generate: bool,
+}
+
+// prints a [[users]] entry for the file passed to `server --auth-file`:
+//
+//   fluxdb-passwd alice >> auth.toml            (password read from stdin)
+//   fluxdb-passwd ci-bot --generate >> auth.toml (token printed to stderr, only the hash is kept)
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let secret = if args.generate {
+        let token = generate_token();
+        eprintln!("token for {} (shown once, store it now): {token}", args.user);
+        token
+    } else {
+        eprint!("secret for {}: ", args.user);
+        std::io::stderr().flush()?;
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+        let secret = line.trim_end_matches(['\r', '\n']).to_string();
+        if secret.is_empty() {
+            return Err("empty secret".into());
+        }
+        secret
+    };
+
+    let hash = hash_secret(&secret)?;
+    println!("[[users]]\nname = \"{}\"\nhash = \"{hash}\"", args.user);
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use std::{fs, path::PathBuf, process::ExitCode};
+
+use clap::{Parser, Subcommand};
+use fluxdb::{
+    event::{Event, EventOp},
+    store::wal::{
+        lsn::Lsn,
+        replay::{segment_ids, Damage, WalIterator, WalRecord},
+    },
+};
+use serde_json::{json, Value};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Inspect a FluxDB write-ahead log (read only)")]
+struct Cli {
+    /// Server data dir, the one holding wal/
+    #[arg(long, default_value = "./fluxdb")]
+    data_dir: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Segments with their size, record count and how they end
+    Segments,
+    /// Records with their lsn, oldest first
+    Events {
+        /// Only this key
+        #[arg(long, conflicts_with = "prefix")]
+        key: Option<String>,
+
+        /// Only keys starting with this
+        #[arg(long)]
+        prefix: Option<String>,
+
+        /// First lsn to show, segment:offset
+        #[arg(long)]
+        from: Option<Lsn>,
+
+        /// Stop before this lsn, segment:offset
+        #[arg(long)]
+        to: Option<Lsn>,
+
+        /// One json object per line instead of text
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Every version of one key, with the record that made it
+    Timeline { key: String },
+    /// Report torn and corrupt records. Exits 1 when there are any
+    Check,
+}
+
+//   fluxdb-wal --data-dir ./fluxdb segments
+//   fluxdb-wal events --prefix user: --from 3:0
+//   fluxdb-wal timeline user:42
+//   fluxdb-wal check
+//
+// nothing is opened for writing, so it can run next to a live server. A record the server is in
+// the middle of appending shows up as a torn tail
+fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    let ids = segment_ids(&cli.data_dir)
+        .map_err(|e| format!("{}: no readable wal/ ({e})", cli.data_dir.display()))?;
+    let (Some(&first), Some(&last)) = (ids.first(), ids.last()) else {
+        return Err(format!("no wal segments in {}", cli.data_dir.display()).into());
+    };
+    let open = |segment: u64| WalIterator::open(&cli.data_dir, Lsn::new(segment.max(first), 0));
+
+    match cli.command {
+        Command::Segments => {
+            let mut stats: Vec<SegmentStats> = ids.iter().map(|&id| SegmentStats::new(id)).collect();
+            let mut wal = open(first)?;
+            while let Some(record) = wal.next_record()? {
+                if let Some(s) = stats.iter_mut().find(|s| s.id == record.lsn.segment) {
+                    s.add(&record);
+                }
+            }
+            println!("{:>8}  {:>12}  {:>9}  status", "segment", "bytes", "records");
+            for s in &stats {
+                let path = cli.data_dir.join("wal").join(format!("{}.log", s.id));
+                let bytes = fs::metadata(path)?.len();
+                let active = if s.id == last { " (active)" } else { "" };
+                println!("{:>8}  {bytes:>12}  {:>9}  {}{active}", s.id, s.records, s.status());
+            }
+        }
+
+        Command::Events {
+            key,
+            prefix,
+            from,
+            to,
+            json,
+        } => {
+            let mut wal = open(from.map_or(first, |lsn| lsn.segment))?;
+            while let Some(record) = wal.next_record()? {
+                let at = (record.lsn.segment, record.lsn.offset);
+                if from.is_some_and(|from| at < (from.segment, from.offset)) {
+                    continue;
+                }
+                if to.is_some_and(|to| at >= (to.segment, to.offset)) {
+                    break;
+                }
+                // damage is shown whatever the filter, there is no telling whose record it was
+                if let Ok(event) = &record.entry {
+                    let wanted = match (&key, &prefix) {
+                        (Some(key), _) => event.op == EventOp::FlushAll || event.key == *key,
+                        (_, Some(prefix)) => event.op == EventOp::FlushAll || event.key.starts_with(prefix),
+                        _ => true,
+                    };
+                    if !wanted {
+                        continue;
+                    }
+                }
+                if json {
+                    println!("{}", record_json(&record));
+                } else {
+                    println!("{}", record_line(&record));
+                }
+            }
+        }
+
+        Command::Timeline { key } => {
+            let mut wal = open(first)?;
+            let mut exists = false;
+            let mut versions = 0;
+            while let Some(record) = wal.next_record()? {
+                let Ok(event) = &record.entry else {
+                    println!("{:>6}  {}", "!!", record_line(&record));
+                    continue;
+                };
+                if event.op == EventOp::FlushAll {
+                    if exists {
+                        println!("{:>6}  {:<10}  flush_all", "-", record.lsn.to_string());
+                        exists = false;
+                    }
+                } else if event.key == key {
+                    versions += 1;
+                    exists = !event.new.is_null();
+                    let change = if exists { event.new.to_string() } else { "deleted".to_string() };
+                    println!("{:>6}  {:<10}  {change}", format!("v{}", event.version), record.lsn.to_string());
+                }
+            }
+            if versions == 0 {
+                eprintln!("{key} is not in the wal (it may only be in snapshot.json)");
+            }
+        }
+
+        Command::Check => {
+            let mut wal = open(first)?;
+            let (mut records, mut damaged) = (0, 0);
+            // good records after the first damage: with any, the server won't start on this wal
+            // (it is not a torn tail) unless it is told to --recover-best-effort
+            let mut after_damage = 0;
+            let mut damaged_segments = std::collections::BTreeSet::new();
+            while let Some(record) = wal.next_record()? {
+                match &record.entry {
+                    Ok(_) => {
+                        records += 1;
+                        if damaged > 0 {
+                            after_damage += 1;
+                        }
+                    }
+                    Err(damage) => {
+                        damaged += 1;
+                        damaged_segments.insert(record.lsn.segment);
+                        let note = match damage {
+                            Damage::Torn { .. } if record.lsn.segment == last => "torn: a write cut short",
+                            Damage::Torn { .. } => "torn record in a sealed segment",
+                            Damage::TooLong { .. } => "the rest of the segment can't be read",
+                            Damage::Checksum { .. } | Damage::Corrupt { .. } => "skipped over",
+                        };
+                        println!("{}  ({note})", record_line(&record));
+                    }
+                }
+            }
+            println!("{records} records in {} segments, {damaged} damaged", ids.len());
+            if after_damage > 0 || damaged_segments.len() > 1 {
+                println!(
+                    "{after_damage} good records come after damage: not a torn tail, the server won't \
+                     start without --recover-best-effort, see fluxdb-fsck"
+                );
+            } else if damaged > 0 {
+                println!("nothing good follows the damage, the server cuts it off as a torn tail on start");
+            }
+            if damaged > 0 {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+struct SegmentStats {
+    id: u64,
+    records: u64,
+    corrupt: u64,
+    torn: Option<(Lsn, u64, u64)>, // where, needed, available
+}
+
+impl SegmentStats {
+    fn new(id: u64) -> Self {
+        Self {
+            id,
+            records: 0,
+            corrupt: 0,
+            torn: None,
+        }
+    }
+
+    fn add(&mut self, record: &WalRecord) {
+        match &record.entry {
+            Ok(_) => self.records += 1,
+            Err(Damage::Torn { needed, available }) => self.torn = Some((record.lsn, *needed, *available)),
+            Err(_) => self.corrupt += 1,
+        }
+    }
+
+    fn status(&self) -> String {
+        let mut status = match self.torn {
+            None => "clean".to_string(),
+            Some((lsn, needed, available)) => {
+                format!("torn at {lsn} ({needed} bytes needed, {available} left)")
+            }
+        };
+        if self.corrupt > 0 {
+            status.push_str(&format!(", {} corrupt", self.corrupt));
+        }
+        status
+    }
+}
+
+fn record_line(record: &WalRecord) -> String {
+    let lsn = record.lsn.to_string();
+    match &record.entry {
+        Err(damage) => format!("{lsn:<10}  !! {damage}"),
+        Ok(Event { op: EventOp::FlushAll, .. }) => format!("{lsn:<10}  flush_all"),
+        Ok(event) if event.new.is_null() => {
+            format!("{lsn:<10}  del  {}  v{}", event.key, event.version)
+        }
+        Ok(event) => format!("{lsn:<10}  set  {}  v{}  {}", event.key, event.version, event.new),
+    }
+}
+
+fn record_json(record: &WalRecord) -> Value {
+    match &record.entry {
+        Ok(event) => json!({"lsn": record.lsn, "event": event}),
+        Err(damage) => json!({"lsn": record.lsn, "damage": damage.to_string()}),
+    }
+}
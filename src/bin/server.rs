@@ -1,49 +1,541 @@
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use clap::Parser;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
     net::TcpListener,
-    sync::mpsc,
+    sync::{mpsc, Semaphore},
+    time::{timeout, timeout_at, Instant},
 };
+use tracing::{error, info, info_span, warn, Instrument};
 
 use fluxdb::{
-    engine::{handler::EngineHandle, runtime::EngineRuntime},
-    net::protocol::{Request, Response},
+    config::ServerConfig,
+    logging::{self, LogFormat},
+    engine::{config::{Durability, Recovery}, handler::EngineHandle, runtime::EngineRuntime},
+    metrics,
+    net::{
+        acl::AdminPolicy,
+        auth::{Authenticator, Gate},
+        codec::{encode, is_frame_too_large, read_frame_limited},
+        dispatch::{dispatch, dispatch_pipelined, MAX_IN_FLIGHT},
+        handshake::{welcome, Negotiated},
+        http,
+        limits::{ConnectionLimiter, ConnectionSlot, ServerLimits, COUNTERS, TOO_MANY_CONNECTIONS},
+        protocol::{Capability, Framing, Request, RequestFrame, Response, ResponseFrame},
+        ratelimit::ConnectionRate,
+        resp,
+        tls::{self, certificate_user, TlsAcceptor},
+        ws::{handle_ws_connection, reject_ws_connection},
+    },
 };
 
 const OUTBOUND_BUFFER: usize = 128;
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// everything the tcp transport can turn on through Hello
+const OFFERED: [Capability; 3] = [
+    Capability::Msgpack,
+    Capability::Deflate,
+    Capability::Pipelining,
+];
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "FluxDB TCP server")]
+struct Args {
+    /// TOML config file; flags and FLUXDB_* environment variables override what it sets
+    #[arg(long, env = "FLUXDB_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Validate the configuration, print it and exit without starting anything
+    #[arg(long)]
+    check_config: bool,
+
+    /// Start even if the WAL is damaged before its end: damaged records are skipped and their
+    /// writes lost (each one is logged). Without it the server refuses to start on such damage
+    #[arg(long)]
+    recover_best_effort: bool,
+
+    /// Let clients run flush_all and backup when the server has no auth file. Without it they
+    /// need an authenticated admin
+    #[arg(long)]
+    allow_anonymous_admin: bool,
+
+    /// Directory backup requests write below; without it the server refuses backups
+    #[arg(long, env = "FLUXDB_BACKUP_ROOT")]
+    backup_root: Option<PathBuf>,
+
+    /// Address for the line-delimited JSON tcp listener [default: 127.0.0.1:7000]
+    #[arg(long, env = "FLUXDB_ADDR")]
+    addr: Option<String>,
+
+    /// Address for the WebSocket listener (disabled when not set)
+    #[arg(long, env = "FLUXDB_WS_ADDR")]
+    ws_addr: Option<String>,
+
+    /// Address for the HTTP/REST + SSE listener (disabled when not set)
+    #[arg(long, env = "FLUXDB_HTTP_ADDR")]
+    http_addr: Option<String>,
+
+    /// Address for the Redis RESP2/RESP3 listener (disabled when not set)
+    #[arg(long, env = "FLUXDB_RESP_ADDR")]
+    resp_addr: Option<String>,
+
+    /// Address for the Prometheus metrics endpoint, GET /metrics, no auth (disabled when not set)
+    #[arg(long, env = "FLUXDB_METRICS_ADDR")]
+    metrics_addr: Option<String>,
+
+    /// Path for a Unix domain socket listener speaking the tcp protocol (disabled when not set)
+    #[arg(long, env = "FLUXDB_UNIX_SOCKET")]
+    unix_socket: Option<PathBuf>,
+
+    /// Octal permissions for --unix-socket; only users that may write it can connect [default: 660]
+    #[arg(long, env = "FLUXDB_UNIX_SOCKET_MODE", value_parser = parse_mode)]
+    unix_socket_mode: Option<u32>,
+
+    /// TOML file with users and argon2 secret hashes; every listener requires auth when set
+    #[arg(long, env = "FLUXDB_AUTH_FILE")]
+    auth_file: Option<PathBuf>,
+
+    /// Directory for the WAL segments and the snapshot, one per instance [default: ./fluxdb]
+    #[arg(long, env = "FLUXDB_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Size in bytes at which a WAL segment is rotated [default: 67108864]
+    #[arg(long, env = "FLUXDB_WAL_SEGMENT_SIZE")]
+    wal_segment_size: Option<u64>,
+
+    /// When writes are fsynced: sync (before acking), periodic or off [default: sync]
+    #[arg(long, env = "FLUXDB_DURABILITY")]
+    durability: Option<Durability>,
+
+    /// Fsync interval for --durability periodic, in milliseconds [default: 100]
+    #[arg(long, env = "FLUXDB_FSYNC_INTERVAL_MS")]
+    fsync_interval_ms: Option<u64>,
+
+    /// Seconds between snapshots [default: 30]
+    #[arg(long, env = "FLUXDB_SNAPSHOT_INTERVAL_SECS")]
+    snapshot_interval_secs: Option<u64>,
+
+    /// Also snapshot after this many writes [default: 1000]
+    #[arg(long, env = "FLUXDB_SNAPSHOT_EVERY_WRITES")]
+    snapshot_every_writes: Option<u64>,
+
+    /// PEM certificate chain for TLS on the tcp listener
+    #[arg(long, env = "FLUXDB_TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, env = "FLUXDB_TLS_KEY")]
+    tls_key: Option<PathBuf>,
+
+    /// PEM CA bundle; clients must present a certificate signed by it (mutual TLS)
+    #[arg(long, env = "FLUXDB_TLS_CLIENT_CA")]
+    tls_client_ca: Option<PathBuf>,
+
+    /// Largest request (json line, msgpack frame or websocket message) accepted, in bytes [default: 16777216]
+    #[arg(long, env = "FLUXDB_MAX_REQUEST_BYTES")]
+    max_request_bytes: Option<usize>,
+
+    /// Concurrent connections over all listeners; extra ones get an error and are closed [default: 1024]
+    #[arg(long, env = "FLUXDB_MAX_CONNECTIONS")]
+    max_connections: Option<usize>,
+
+    /// Close connections that send nothing for this long, unless they hold subscriptions (0 = never) [default: 300]
+    #[arg(long, env = "FLUXDB_IDLE_TIMEOUT_SECS")]
+    idle_timeout_secs: Option<u64>,
+
+    /// Time a client gets to finish a request once it started sending it (0 = unlimited) [default: 30]
+    #[arg(long, env = "FLUXDB_READ_TIMEOUT_SECS")]
+    read_timeout_secs: Option<u64>,
+
+    /// Subscriptions one connection may hold at once [default: 1024]
+    #[arg(long, env = "FLUXDB_MAX_SUBSCRIPTIONS")]
+    max_subscriptions: Option<usize>,
+
+    /// Read requests per second per connection (unlimited when not set)
+    #[arg(long, env = "FLUXDB_CONN_READ_OPS")]
+    conn_read_ops: Option<u64>,
+
+    /// Write requests per second per connection (unlimited when not set)
+    #[arg(long, env = "FLUXDB_CONN_WRITE_OPS")]
+    conn_write_ops: Option<u64>,
+
+    /// Bytes of values read per second per connection (unlimited when not set)
+    #[arg(long, env = "FLUXDB_CONN_READ_BYTES")]
+    conn_read_bytes: Option<u64>,
+
+    /// Bytes of write requests per second per connection (unlimited when not set)
+    #[arg(long, env = "FLUXDB_CONN_WRITE_BYTES")]
+    conn_write_bytes: Option<u64>,
+
+    /// Log filter in the RUST_LOG syntax, e.g. "info" or "warn,fluxdb::engine=debug" [default: info]
+    #[arg(long, env = "FLUXDB_LOG_LEVEL")]
+    log_level: Option<String>,
+
+    /// Log line format on stderr: json or text [default: json]
+    #[arg(long, env = "FLUXDB_LOG_FORMAT")]
+    log_format: Option<LogFormat>,
+}
+
+// "660" / "0660" / "0o660"
+fn parse_mode(s: &str) -> Result<u32, String> {
+    let digits = s.strip_prefix("0o").unwrap_or(s);
+    u32::from_str_radix(digits, 8).map_err(|_| format!("{s:?} is not an octal mode like 660"))
+}
+
+// the config file (or the defaults) with every flag / env variable that was given laid over it
+fn load_config(args: &Args) -> Result<ServerConfig, String> {
+    let mut config = match &args.config {
+        Some(path) => ServerConfig::load(path)?,
+        None => ServerConfig::default(),
+    };
+
+    fn set<T: Clone>(target: &mut T, value: &Option<T>) {
+        if let Some(value) = value {
+            *target = value.clone();
+        }
+    }
+    fn set_some<T: Clone>(target: &mut Option<T>, value: &Option<T>) {
+        if value.is_some() {
+            *target = value.clone();
+        }
+    }
+
+    set(&mut config.addr, &args.addr);
+    set_some(&mut config.ws_addr, &args.ws_addr);
+    set_some(&mut config.http_addr, &args.http_addr);
+    set_some(&mut config.resp_addr, &args.resp_addr);
+    set_some(&mut config.metrics_addr, &args.metrics_addr);
+    set_some(&mut config.unix_socket, &args.unix_socket);
+    set(&mut config.unix_socket_mode, &args.unix_socket_mode);
+    set_some(&mut config.auth_file, &args.auth_file);
+    set(&mut config.data_dir, &args.data_dir);
+
+    set_some(&mut config.tls.cert, &args.tls_cert);
+    set_some(&mut config.tls.key, &args.tls_key);
+    set_some(&mut config.tls.client_ca, &args.tls_client_ca);
+
+    set(&mut config.wal.segment_size, &args.wal_segment_size);
+    set(&mut config.wal.durability, &args.durability);
+    set(&mut config.wal.fsync_interval_ms, &args.fsync_interval_ms);
+    set(&mut config.snapshot.interval_secs, &args.snapshot_interval_secs);
+    set(&mut config.snapshot.every_writes, &args.snapshot_every_writes);
+
+    let limits = &mut config.limits;
+    set(&mut limits.max_request_bytes, &args.max_request_bytes);
+    set(&mut limits.max_connections, &args.max_connections);
+    set(&mut limits.idle_timeout_secs, &args.idle_timeout_secs);
+    set(&mut limits.read_timeout_secs, &args.read_timeout_secs);
+    set(&mut limits.max_subscriptions, &args.max_subscriptions);
+    set_some(&mut limits.conn_rate.read_ops, &args.conn_read_ops);
+    set_some(&mut limits.conn_rate.write_ops, &args.conn_write_ops);
+    set_some(&mut limits.conn_rate.read_bytes, &args.conn_read_bytes);
+    set_some(&mut limits.conn_rate.write_bytes, &args.conn_write_bytes);
+
+    set(&mut config.log.level, &args.log_level);
+    set(&mut config.log.format, &args.log_format);
+    if args.allow_anonymous_admin {
+        config.admin.allow_anonymous = true;
+    }
+    set_some(&mut config.admin.backup_root, &args.backup_root);
+
+    config.validate()?;
+    Ok(config)
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid configuration: {e}");
+            std::process::exit(2);
+        }
+    };
+    if args.check_config {
+        println!("{config:#?}");
+        println!("configuration ok");
+        return Ok(());
+    }
+    if let Err(e) = logging::init(&config.log.level, config.log.format) {
+        eprintln!("{e}");
+        std::process::exit(2);
+    }
+
     // Starting the DB engine
-    let runtime = EngineRuntime::start(); // internal worker threads
+    let mut engine = config.engine();
+    if args.recover_best_effort {
+        engine.recovery = Recovery::BestEffort;
+        warn!("--recover-best-effort: damaged wal records will be skipped and their writes lost");
+    }
+    let runtime = match EngineRuntime::open(engine).await {
+        // internal worker threads
+        Ok(runtime) => runtime,
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(1);
+        }
+    };
     let handle = runtime.handle; // api to talk to engine
+    info!(
+        data_dir = %config.data_dir.display(),
+        durability = %config.wal.durability,
+        "engine started"
+    );
+
+    let auth = match &config.auth_file {
+        Some(path) => {
+            let auth = Arc::new(Authenticator::load(path)?);
+            info!(auth_file = %path.display(), "auth enabled");
+            spawn_auth_reloader(auth.clone())?;
+            Some(auth)
+        }
+        None => None,
+    };
+
+    let limits = config.limits();
+    let admin = Arc::new(config.admin());
+    if admin.allow_anonymous && auth.is_none() {
+        warn!("allow_anonymous_admin: any client may flush or back up the database");
+    }
+    // tcp, unix socket, websocket, http and resp connections draw from the same pool
+    let limiter = ConnectionLimiter::new(limits.max_connections);
+
+    if let Some(ws_addr) = &config.ws_addr {
+        let ws_listener = TcpListener::bind(ws_addr).await?;
+        info!(addr = %ws_addr, "websocket listening");
+
+        let handle = handle.clone();
+        let auth = auth.clone();
+        let limiter = limiter.clone();
+        let admin = admin.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match ws_listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        warn!(error = %e, "websocket accept failed");
+                        continue;
+                    }
+                };
+                let _ = stream.set_nodelay(true);
+                let handle = handle.clone();
+                let auth = auth.clone();
+                let admin = admin.clone();
+                let slot = limiter.try_acquire();
+
+                let span = info_span!("connection", transport = "websocket", peer = %addr);
+                tokio::spawn(
+                    async move {
+                        let result = match slot {
+                            Some(_slot) => handle_ws_connection(stream, handle, auth, limits, admin).await,
+                            None => reject_ws_connection(stream, TOO_MANY_CONNECTIONS).await,
+                        };
+                        if let Err(e) = result {
+                            info!(error = %e, "connection closed with error");
+                        }
+                    }
+                    .instrument(span),
+                );
+            }
+        });
+    }
+
+    if let Some(http_addr) = &config.http_addr {
+        let http_listener = TcpListener::bind(http_addr).await?;
+        info!(addr = %http_addr, "http listening");
+
+        let handle = handle.clone();
+        let auth = auth.clone();
+        let limiter = limiter.clone();
+        tokio::spawn(async move {
+            if let Err(e) = http::serve_with(http_listener, handle, auth, limits, limiter).await {
+                error!(error = %e, "http server stopped");
+            }
+        });
+    }
+
+    if let Some(resp_addr) = &config.resp_addr {
+        let resp_listener = TcpListener::bind(resp_addr).await?;
+        info!(addr = %resp_addr, "resp listening");
+
+        let handle = handle.clone();
+        let auth = auth.clone();
+        let limiter = limiter.clone();
+        tokio::spawn(async move {
+            if let Err(e) = resp::serve_with(resp_listener, handle, auth, limits, limiter).await {
+                error!(error = %e, "resp server stopped");
+            }
+        });
+    }
+
+    if let Some(metrics_addr) = &config.metrics_addr {
+        let metrics_listener = TcpListener::bind(metrics_addr).await?;
+        info!(addr = %metrics_addr, "metrics listening");
+
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(metrics_listener).await {
+                error!(error = %e, "metrics server stopped");
+            }
+        });
+    }
+
+    if let Some(path) = &config.unix_socket {
+        let unix_listener = bind_unix(path, config.unix_socket_mode)?;
+        info!(
+            path = %path.display(),
+            mode = format!("{:o}", config.unix_socket_mode),
+            "unix socket listening"
+        );
+
+        let handle = handle.clone();
+        let auth = auth.clone();
+        let limiter = limiter.clone();
+        let admin = admin.clone();
+        tokio::spawn(async move {
+            loop {
+                let stream = match unix_listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        warn!(error = %e, "unix socket accept failed");
+                        continue;
+                    }
+                };
+                // no peer ip, so failed logins over the socket share one lockout entry
+                let conn = Connection {
+                    peer: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    handle: handle.clone(),
+                    auth: auth.clone(),
+                    limits,
+                    admin: admin.clone(),
+                    slot: limiter.try_acquire(),
+                };
+                let span = info_span!("connection", transport = "unix");
+                tokio::spawn(
+                    async move {
+                        if let Err(e) = handle_connection(stream, conn, None).await {
+                            info!(error = %e, "connection closed with error");
+                        }
+                    }
+                    .instrument(span),
+                );
+            }
+        });
+    }
 
-    // creating the tcp listener at port 7000
-    let listener = TcpListener::bind("127.0.0.1:7000").await?;
-    println!("server listening on 127.0.0.1:7000");
+    let tls = match (&config.tls.cert, &config.tls.key) {
+        (Some(cert), Some(key)) => {
+            let config = tls::server_config(cert, key, config.tls.client_ca.as_deref())?;
+            Some(TlsAcceptor::from(config))
+        }
+        _ => None,
+    };
+
+    // creating the tcp listener (port 7000 by default)
+    let listener = TcpListener::bind(&config.addr).await?;
+    let tls_mode = match (&tls, &config.tls.client_ca) {
+        (Some(_), Some(_)) => "client certificates required",
+        (Some(_), None) => "on",
+        _ => "off",
+    };
+    info!(addr = %config.addr, tls = tls_mode, "server listening");
 
     loop {
         let (stream, addr) = listener.accept().await?;
         stream.set_nodelay(true)?; // Disable Nagle's algorithm for lower latency
-        let handle = handle.clone();
+        let conn = Connection {
+            peer: addr.ip(),
+            handle: handle.clone(),
+            auth: auth.clone(),
+            limits,
+            admin: admin.clone(),
+            slot: limiter.try_acquire(),
+        };
+        let tls = tls.clone();
 
+        let span = info_span!("connection", transport = "tcp", peer = %addr);
         tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream, handle).await {
-                eprintln!("connection {addr} closed with error: {e}");
+            let result = match tls {
+                Some(acceptor) => {
+                    let accepted = timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await;
+                    match accepted {
+                        Ok(Ok(stream)) => {
+                            let cert_user = certificate_user(stream.get_ref().1);
+                            handle_connection(stream, conn, cert_user).await
+                        }
+                        Ok(Err(e)) => Err(format!("tls handshake failed: {e}").into()),
+                        Err(_) => Err("tls handshake timed out".into()),
+                    }
+                }
+                None => handle_connection(stream, conn, None).await,
+            };
+            if let Err(e) = result {
+                info!(error = %e, "connection closed with error");
             }
-        });
+        }.instrument(span));
     }
 }
 
 // each client gets its own handle_connection  // ? this doesnt mean each request has its own connection
 
-async fn handle_connection(
-    stream: tokio::net::TcpStream,
+// what the accept loop hands each connection task
+struct Connection {
+    peer: IpAddr,
     handle: EngineHandle,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let (read_half, mut write_half) = stream.into_split(); // breaking tcp connection into two different handler
+    auth: Option<Arc<Authenticator>>,
+    limits: ServerLimits,
+    admin: Arc<AdminPolicy>,
+    slot: Option<ConnectionSlot>, // None = over --max-connections
+}
+
+// generic over the stream so plain tcp, tls and unix socket connections share it. cert_user is the CN of a
+// verified tls client certificate, which logs the connection in when it names a known user
+async fn handle_connection<S>(
+    stream: S,
+    conn: Connection,
+    cert_user: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let Connection {
+        peer,
+        handle,
+        auth,
+        limits,
+        admin,
+        slot,
+    } = conn;
+    let (read_half, mut write_half) = tokio::io::split(stream); // breaking the connection into two different handler
+
+    // over the limit: one json error line (what a client expects first, hello or not) and close
+    let Some(_slot) = slot else {
+        let reply = ResponseFrame::from(Response::Error {
+            message: TOO_MANY_CONNECTIONS.to_string(),
+        });
+        write_half.write_all(&encode(Framing::Json, &reply)?).await?;
+        let _ = write_half.shutdown().await;
+        return Ok(());
+    };
+
+    let mut gate = Gate::new(auth, peer); // login state, open when auth is off
+    if let Some(user) = cert_user {
+        gate.login_with_certificate(&user);
+    }
     let mut reader = BufReader::new(read_half);
 
-    let (out_tx, mut out_rx) = mpsc::channel::<Response>(OUTBOUND_BUFFER);
+    let (out_tx, mut out_rx) = mpsc::channel::<ResponseFrame>(OUTBOUND_BUFFER);
+    let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT)); // caps concurrent id'd requests
+    let subscriptions = Arc::new(Semaphore::new(limits.max_subscriptions));
+    let rate = Arc::new(ConnectionRate::new(limits.conn_rate));
 
     /*
                     ┌────────────────────┐
@@ -58,103 +550,144 @@ async fn handle_connection(
     harder to reason 
     */
 
+    let mut buf: Vec<u8> = Vec::new();
+
+    // handshake: the first json line may be a Hello, which checks the protocol version and
+    // negotiates framing / pipelining. clients that skip it keep the pre-handshake defaults
+    let mut session = Negotiated::legacy();
+    let mut first = None;
+    let opening = read_request(&mut reader, Framing::Json, &mut buf, &limits, true, gate.deadline());
+    let opening = match opening.await {
+        Err(e) if ends_connection(&e) => {
+            let reply = ResponseFrame::from(Response::Error {
+                message: e.to_string(),
+            });
+            write_half.write_all(&encode(Framing::Json, &reply)?).await?;
+            return Ok(());
+        }
+        other => other?,
+    };
+    match opening {
+        None => return Ok(()),
+        Some(Ok(RequestFrame {
+            id,
+            trace_id: _,
+            req:
+                Request::Hello {
+                    protocol_version,
+                    capabilities,
+                    ..
+                },
+        })) => {
+            // the Welcome is still json, everything after it uses the negotiated framing
+            match welcome(protocol_version, &capabilities, &OFFERED, limits.wire()) {
+                Ok((negotiated, resp)) => {
+                    write_half
+                        .write_all(&encode(Framing::Json, &ResponseFrame { id, resp })?)
+                        .await?;
+                    session = negotiated;
+                }
+                Err(message) => {
+                    let reply = ResponseFrame {
+                        id,
+                        resp: Response::Error { message },
+                    };
+                    write_half.write_all(&encode(Framing::Json, &reply)?).await?;
+                    return Ok(());
+                }
+            }
+        }
+        other => first = other,
+    }
+    let framing = session.framing;
+
+    let writer_rate = rate.clone();
     let writer_task = tokio::spawn(async move { // moved the ownership of mut write_half to this task 
         while let Some(resp) = out_rx.recv().await {
-            let mut line_bytes = match serde_json::to_vec(&resp) {
+            let bytes = match encode(framing, &resp) {
                 Ok(b) => b,
                 Err(e) => {
-                    eprintln!("response serialization failed: {e}");
+                    error!(error = %e, "response serialization failed");
                     break;
                 }
             };
-            line_bytes.push(b'\n');
+            if let Response::Value { .. } = resp.resp {
+                writer_rate.charge_read_bytes(bytes.len()); // read bytes are only known now
+            }
 
-            if write_half.write_all(&line_bytes).await.is_err() { 
+            if write_half.write_all(&bytes).await.is_err() { 
                 break;
             }
         }
-    });
-
-    let mut line: String = String::new();
+    }.in_current_span());
 
     loop {
-        line.clear(); 
-        let n = reader.read_line(&mut line).await?;
-        if n == 0 {
-            break;
-        }
-
-        let trimmed: &str = line.trim();
-        if trimmed.is_empty() {
-            continue;
-        }
+        // connections holding subscriptions may stay quiet forever, they are waiting for events
+        let idle = subscriptions.available_permits() == limits.max_subscriptions;
+        let decoded = match first.take() {
+            Some(decoded) => decoded,
+            None => match read_request(&mut reader, framing, &mut buf, &limits, idle, gate.deadline()).await {
+                Ok(Some(decoded)) => decoded,
+                Ok(None) => break,
+                Err(e) if ends_connection(&e) => {
+                    let message = e.to_string();
+                    let _ = out_tx.send(Response::Error { message }.into()).await;
+                    break;
+                }
+                // tls peers that hang up without close_notify, same as a clean EOF for us
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            },
+        };
 
-        // trimmed here is already a &str reference from line.trim() that's why no need to pass double refernece
-        let req: Request = match serde_json::from_str(trimmed) { // deserialize this trimmed String back to the rust enum 
-            Ok(req) => req,
+        let request_bytes = buf.len();
+        let frame: RequestFrame = match decoded {
+            Ok(frame) => frame,
             Err(e) => {
+                let kind = match framing {
+                    Framing::Json => "json",
+                    Framing::Msgpack | Framing::MsgpackDeflate => "msgpack",
+                };
                 let _ = out_tx
-                    .send(Response::Error {
-                        message: format!("invalid request json: {e}"),
-                    })
+                    .send(
+                        Response::Error {
+                            message: format!("invalid request {kind}: {e}"),
+                        }
+                        .into(),
+                    )
                     .await;
                 continue;
             }
         };
 
-        match req {
-            Request::Set { key, value } => {
-                let resp: Response = match handle.set(key, value).await {
-                    Ok(()) => Response::Ok,
-                    Err(message) => Response::Error { message },
-                };
-                let _ = out_tx.send(resp).await;
-            }
-            Request::Get { key } => {
-                let resp = match handle.get(key).await {
-                    Ok(doc) => Response::Value { doc },
-                    Err(message) => Response::Error { message },
-                };
-                let _ = out_tx.send(resp).await;
-            }
-            Request::Del { key } => {
-                let resp = match handle.delete(key).await {
-                    Ok(()) => Response::Ok,
-                    Err(message) => Response::Error { message },
-                };
-                let _ = out_tx.send(resp).await;
-            }
-            Request::Patch { key, delta } => {
-                let resp = match handle.patch(key, delta).await {
-                    Ok(()) => Response::Ok,
-                    Err(message) => Response::Error { message },
-                };
-                let _ = out_tx.send(resp).await;
-            }
-            Request::Snapshot => {
-                let resp = match handle.snapshot().await {
-                    Ok(()) => Response::Ok,
-                    Err(message) => Response::Error { message },
-                };
-                let _ = out_tx.send(resp).await;
-            }
-            Request::Subscribe { key } => match handle.subscribe(key.clone()).await {
-                Ok(mut sub_rx) => {
-                    let _ = out_tx.send(Response::Subscribed { key }).await;
-
-                    let sub_tx = out_tx.clone();
-                    tokio::spawn(async move {
-                        while let Some(event) = sub_rx.recv().await {
-                            if sub_tx.send(Response::Event { event }).await.is_err() {
-                                break;
-                            }
-                        }
-                    });
-                }
-                Err(message) => {
-                    let _ = out_tx.send(Response::Error { message }).await;
-                }
-            },
+        // Auth is answered inline (later frames must see the result), everything else waits for it
+        if let Some(resp) = gate.check(&frame.req).await {
+            let _ = out_tx.send(ResponseFrame { id: frame.id, resp }).await;
+            continue;
+        }
+
+        // id'd requests are spawned so a slow write doesn't hold up later reads on this connection,
+        // unless the client said hello without asking for pipelining
+        // over budget is answered right away instead of queueing in front of the engine
+        let principal = gate.principal();
+        if let Some(resp) = rate.check(principal.clone(), &frame.req, request_bytes) {
+            let _ = out_tx.send(ResponseFrame { id: frame.id, resp }).await;
+            continue;
+        }
+
+        if session.pipelining {
+            dispatch_pipelined(
+                &handle,
+                frame,
+                &out_tx,
+                &in_flight,
+                principal,
+                &subscriptions,
+                &admin,
+            )
+            .await;
+        } else {
+            dispatch(&handle, frame, &out_tx, principal, &subscriptions, &admin).await;
         }
     }
 
@@ -163,13 +696,135 @@ async fn handle_connection(
     Ok(())
 }
 
+// a socket file left behind by a server that is gone is removed, one that still answers is
+// an error (two servers on one socket). The mode is set before the first accept
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: u32) -> std::io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            let message = format!("{} exists and is not a socket", path.display());
+            return Err(std::io::Error::new(ErrorKind::AlreadyExists, message));
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            let message = format!("another server is listening on {}", path.display());
+            return Err(std::io::Error::new(ErrorKind::AddrInUse, message));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &Path, _mode: u32) -> std::io::Result<tokio::net::TcpListener> {
+    Err(std::io::Error::new(ErrorKind::Unsupported, "unix sockets are only supported on unix"))
+}
+
+// `kill -HUP <pid>` re-reads the auth file (users, secrets, acls). A broken file is reported
+// and the previous one stays active
+#[cfg(unix)]
+fn spawn_auth_reloader(auth: Arc<Authenticator>) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            match auth.reload() {
+                Ok(users) => info!(users, "auth file reloaded"),
+                Err(e) => warn!(error = %e, "auth reload failed, keeping the old config"),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn spawn_auth_reloader(_auth: Arc<Authenticator>) -> std::io::Result<()> {
+    Ok(())
+}
+
+// read_frame_limited with the connection's deadlines:
+// - waiting for a request to start: the login deadline while auth is pending, and the idle
+//   timeout when `idle` (the connection has no subscriptions)
+// - once it started: the read timeout, so a slow sender can't hold a connection open
+// timeouts come back as ErrorKind::TimedOut with the message for the client
+async fn read_request<R>(
+    reader: &mut R,
+    framing: Framing,
+    buf: &mut Vec<u8>,
+    limits: &ServerLimits,
+    idle: bool,
+    deadline: Option<Instant>,
+) -> std::io::Result<Option<Result<RequestFrame, String>>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let timed_out = |message: &str| std::io::Error::new(ErrorKind::TimedOut, message.to_string());
+    let login_expired = |at: Instant| deadline.is_some_and(|d| d <= at);
+
+    let idle_deadline = limits
+        .idle_timeout
+        .filter(|_| idle)
+        .map(|t| Instant::now() + t);
+    if let Some(at) = earliest(deadline, idle_deadline) {
+        match timeout_at(at, reader.fill_buf()).await {
+            Ok(filled) => filled.map(|_| ())?,
+            Err(_) if login_expired(at) => return Err(timed_out("authentication timeout")),
+            Err(_) => {
+                COUNTERS.idle_timeout();
+                return Err(timed_out("idle timeout"));
+            }
+        }
+    }
+
+    let read_deadline = limits.read_timeout.map(|t| Instant::now() + t);
+    let read = read_frame_limited(reader, framing, buf, limits.max_request_bytes);
+    let result = match earliest(deadline, read_deadline) {
+        Some(at) => match timeout_at(at, read).await {
+            Ok(result) => result,
+            Err(_) if login_expired(at) => return Err(timed_out("authentication timeout")),
+            Err(_) => {
+                COUNTERS.read_timeout();
+                return Err(timed_out("read timeout"));
+            }
+        },
+        None => read.await,
+    };
+    if let Err(e) = &result {
+        if is_frame_too_large(e) {
+            COUNTERS.request_too_large();
+        }
+    }
+    result
+}
+
+fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+// errors the client is told about before the connection is closed
+fn ends_connection(e: &std::io::Error) -> bool {
+    e.kind() == ErrorKind::TimedOut || is_frame_too_large(e)
+}
+
 /*
-* 1. Start the TCP connection at port 7000
+* 1. Start the TCP connection at port 7000 (plus websocket / http / resp listeners if --ws-addr / --http-addr / --resp-addr are set)
 * 2. Accept TCP connections
 * 3. Spawns one async task per connection
 * 4. Inside Connection:
- * A. Read JSON Requests
+ * A. Read JSON Requests (or msgpack frames after a Hello that negotiated them)
+ *    with --auth-file nothing but Hello/Auth is accepted until an Auth succeeds
+ *    oversized requests, idle / slow connections and connections over --max-connections get an error and are closed (net::limits)
  * B. Send them to engine
  * C. Sends the responses back
  * D. Supports subscription (streaming events)
+* 5. Request -> engine mapping lives in net::dispatch so tcp and websocket share it (http and resp have their own command sets in net::http / net::resp)
+* 6. --metrics-addr serves fluxdb::metrics in the Prometheus text format
 */
@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use crate::{
+    client::{ClientOptions, Endpoint},
+    net::tls::{self, TlsConnector},
+};
+
+// the connection flags every command line tool takes, flattened into its own args:
+//
+//   #[command(flatten)]
+//   connect: ConnectArgs,
+#[derive(clap::Args, Debug, Clone)]
+pub struct ConnectArgs {
+    #[arg(long, default_value = "127.0.0.1:7000")]
+    pub addr: String,
+
+    /// Connect to the server's Unix domain socket instead of --addr
+    #[arg(long, conflicts_with = "tls")]
+    pub unix: Option<PathBuf>,
+
+    /// User to authenticate as (servers started with --auth-file)
+    #[arg(long, requires = "secret")]
+    pub user: Option<String>,
+
+    /// Password or API token for --user
+    #[arg(long, env = "FLUXDB_SECRET", hide_env_values = true)]
+    pub secret: Option<String>,
+
+    /// Connect over TLS
+    #[arg(long, default_value_t = false)]
+    pub tls: bool,
+
+    /// PEM CA bundle to verify the server with (default: public web roots)
+    #[arg(long, requires = "tls")]
+    pub ca: Option<PathBuf>,
+
+    /// PEM client certificate for mutual TLS
+    #[arg(long, requires_all = ["tls", "key"])]
+    pub cert: Option<PathBuf>,
+
+    /// PEM private key for --cert
+    #[arg(long, requires = "cert")]
+    pub key: Option<PathBuf>,
+}
+
+impl ConnectArgs {
+    // client options for these flags, the rest left at their defaults. `name` is the
+    // client_name the server logs the connection with
+    pub fn client_options(&self, name: &str) -> Result<ClientOptions, String> {
+        let tls = if self.tls {
+            let identity = self.cert.as_deref().zip(self.key.as_deref());
+            tls::server_name(&self.addr)?; // fail before connecting on an addr a cert can't match
+            Some(TlsConnector::from(tls::client_config(self.ca.as_deref(), identity)?))
+        } else {
+            None
+        };
+        let endpoint = match &self.unix {
+            Some(path) => Endpoint::Unix(path.clone()),
+            None => Endpoint::Tcp(self.addr.clone()),
+        };
+        Ok(ClientOptions {
+            endpoint,
+            tls,
+            credentials: self.user.clone().zip(self.secret.clone()),
+            client_name: name.to_string(),
+            ..ClientOptions::tcp(&self.addr)
+        })
+    }
+
+    // what the server is called in messages: the socket path or the addr
+    pub fn target(&self) -> String {
+        match &self.unix {
+            Some(path) => path.display().to_string(),
+            None => self.addr.clone(),
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    io::{BufReader, ErrorKind, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use serde_json::Value;
+use tokio_rustls::rustls;
+
+use crate::{
+    client::{
+        error::{self, ClientError},
+        ClientOptions, Endpoint,
+    },
+    engine::backup::Manifest,
+    event::Event,
+    net::{
+        codec::{encode, read_frame_blocking},
+        handshake::{Negotiated, PROTOCOL_VERSION},
+        protocol::{
+            Capability, Framing, Info, Request, RequestFrame, Response, ResponseFrame, Stats,
+        },
+        tls,
+    },
+    store::kv::Document,
+};
+
+// Client without tokio, for synchronous callers (build scripts, cli tools). Same options, same
+// errors as the async one, but one connection and one request at a time:
+//
+// - requests go out without an id and are answered in order (no pipelining)
+// - request_timeout is the socket read / write timeout. A timed out or dropped connection is
+//   closed and reopened (once) by the next call, there is no background reconnecting.
+//   pool_size and the backoff settings are ignored
+// - subscribe() opens a connection of its own, see Subscription
+pub struct Client {
+    options: ClientOptions,
+    conn: Option<Conn>,
+}
+
+impl Client {
+    pub fn connect(addr: &str) -> Result<Self, ClientError> {
+        Self::connect_with(ClientOptions::tcp(addr))
+    }
+
+    pub fn connect_with(options: ClientOptions) -> Result<Self, ClientError> {
+        let conn = Conn::open(&options)?;
+        Ok(Self {
+            options,
+            conn: Some(conn),
+        })
+    }
+
+    pub fn set(&mut self, key: String, value: Value) -> Result<(), ClientError> {
+        error::expect_ok(self.request(Request::Set { key, value })?)
+    }
+
+    pub fn patch(&mut self, key: String, delta: Value) -> Result<(), ClientError> {
+        error::expect_ok(self.request(Request::Patch { key, delta })?)
+    }
+
+    pub fn delete(&mut self, key: String) -> Result<(), ClientError> {
+        error::expect_ok(self.request(Request::Del { key })?)
+    }
+
+    pub fn get(&mut self, key: String) -> Result<Option<Document>, ClientError> {
+        match error::check(self.request(Request::Get { key })?)? {
+            Response::Value { doc } => Ok(doc),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    pub fn snapshot(&mut self) -> Result<(), ClientError> {
+        error::expect_ok(self.request(Request::Snapshot)?)
+    }
+
+    pub fn stats(&mut self) -> Result<Stats, ClientError> {
+        match error::check(self.request(Request::Stats)?)? {
+            Response::Stats { stats } => Ok(stats),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    pub fn info(&mut self) -> Result<Info, ClientError> {
+        match error::check(self.request(Request::Info)?)? {
+            Response::Info { info } => Ok(info),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    pub fn db_size(&mut self) -> Result<u64, ClientError> {
+        match error::check(self.request(Request::DbSize)?)? {
+            Response::DbSize { size } => Ok(size),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    pub fn keys(
+        &mut self,
+        pattern: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<String>, bool), ClientError> {
+        match error::check(self.request(Request::Keys { pattern, limit })?)? {
+            Response::Keys { keys, truncated } => Ok((keys, truncated)),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    pub fn flush_all(&mut self) -> Result<(), ClientError> {
+        error::expect_ok(self.request(Request::FlushAll)?)
+    }
+
+    pub fn backup(&mut self, dest_dir: String) -> Result<Manifest, ClientError> {
+        match error::check(self.request(Request::Backup { dest_dir })?)? {
+            Response::Backup { manifest } => Ok(manifest),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    // the subscription gets its own connection (no read timeout, events can be far apart),
+    // this client stays usable for requests
+    pub fn subscribe(&self, key: String) -> Result<Subscription, ClientError> {
+        Subscription::open(&self.options, key)
+    }
+
+    // any request, answered with the raw response (Error and RateLimited included).
+    // Subscribe goes through subscribe(), Hello and Auth are done by the connection itself
+    pub fn request(&mut self, req: Request) -> Result<Response, ClientError> {
+        self.send_request(RequestFrame::from(req))
+    }
+
+    // request() with a trace id the server puts on every log line the request causes
+    pub fn request_traced(
+        &mut self,
+        req: Request,
+        trace_id: String,
+    ) -> Result<Response, ClientError> {
+        self.send_request(RequestFrame {
+            trace_id: Some(trace_id),
+            ..RequestFrame::from(req)
+        })
+    }
+
+    fn send_request(&mut self, frame: RequestFrame) -> Result<Response, ClientError> {
+        let req = &frame.req;
+        match req {
+            Request::Subscribe { .. } | Request::Hello { .. } | Request::Auth { .. } => {
+                return Err(ClientError::Protocol(format!(
+                    "{req:?} can't be sent through request()"
+                )));
+            }
+            _ => {}
+        }
+
+        let conn = match &mut self.conn {
+            Some(conn) => conn,
+            None => self.conn.insert(Conn::open(&self.options)?),
+        };
+        let result = conn.send(&frame);
+        // a half read reply would answer the next request, start over instead
+        if matches!(
+            result,
+            Err(ClientError::Io(_) | ClientError::Disconnected | ClientError::Timeout)
+        ) {
+            self.conn = None;
+        }
+        result
+    }
+}
+
+// a blocking iterator over the events of one key. It ends (None) when the connection drops or
+// the server ends the subscription; the reason, if the server gave one, is yielded as an Err
+// first. Unlike the async client there is no resubscribing, open a new one after it ended
+pub struct Subscription {
+    key: String,
+    conn: Option<Conn>,
+}
+
+impl Subscription {
+    fn open(options: &ClientOptions, key: String) -> Result<Self, ClientError> {
+        let mut conn = Conn::open(options)?;
+        let req = Request::Subscribe { key: key.clone() };
+        match error::check(conn.call(req)?)? {
+            Response::Subscribed { .. } => {}
+            other => return Err(error::unexpected(&other)),
+        }
+        conn.socket
+            .set_timeouts(None, Some(options.request_timeout))?;
+        Ok(Self {
+            key,
+            conn: Some(conn),
+        })
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Result<Event, ClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let conn = self.conn.as_mut()?;
+        let item = match conn.read() {
+            Ok(Response::Event { event }) => return Some(Ok(event)),
+            Ok(Response::Error { message }) => Some(Err(ClientError::Server(message))),
+            Ok(other) => Some(Err(error::unexpected(&other))),
+            Err(ClientError::Disconnected) => None,
+            Err(e) => Some(Err(e)),
+        };
+        self.conn = None;
+        item
+    }
+}
+
+trait Transport: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Transport for T {}
+
+// one negotiated connection. Timeouts live on the socket, so it is kept around to change them
+struct Conn {
+    stream: BufReader<Box<dyn Transport>>,
+    socket: Socket,
+    framing: Framing,
+    buf: Vec<u8>,
+}
+
+enum Socket {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixStream),
+}
+
+impl Socket {
+    fn set_timeouts(&self, read: Option<Duration>, write: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(s) => {
+                s.set_read_timeout(read)?;
+                s.set_write_timeout(write)
+            }
+            #[cfg(unix)]
+            Socket::Unix(s) => {
+                s.set_read_timeout(read)?;
+                s.set_write_timeout(write)
+            }
+        }
+    }
+}
+
+impl Conn {
+    // connect (unix socket, or tcp + tls), Hello, then Auth when credentials were given
+    fn open(options: &ClientOptions) -> Result<Self, ClientError> {
+        let (socket, transport): (Socket, Box<dyn Transport>) = match &options.endpoint {
+            Endpoint::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_nodelay(true)?;
+                let socket = Socket::Tcp(stream.try_clone()?);
+                match &options.tls {
+                    Some(connector) => {
+                        let name = tls::server_name(addr).map_err(ClientError::Io)?;
+                        let session =
+                            rustls::ClientConnection::new(connector.config().clone(), name)
+                                .map_err(|e| ClientError::Io(e.to_string()))?;
+                        (socket, Box::new(rustls::StreamOwned::new(session, stream)))
+                    }
+                    None => (socket, Box::new(stream)),
+                }
+            }
+            Endpoint::Unix(path) => connect_unix(path)?,
+        };
+        socket.set_timeouts(Some(options.request_timeout), Some(options.request_timeout))?;
+
+        let mut conn = Conn {
+            stream: BufReader::new(transport),
+            socket,
+            framing: Framing::Json,
+            buf: Vec::new(),
+        };
+        conn.hello(options)?;
+        if let Some((user, secret)) = &options.credentials {
+            let req = Request::Auth {
+                user: user.clone(),
+                secret: secret.clone(),
+            };
+            error::expect_ok(conn.call(req)?)?;
+        }
+        Ok(conn)
+    }
+
+    // no pipelining asked for: id-less requests are answered strictly in order
+    fn hello(&mut self, options: &ClientOptions) -> Result<(), ClientError> {
+        let capabilities = match options.framing {
+            Framing::Json => vec![],
+            Framing::Msgpack => vec![Capability::Msgpack],
+            Framing::MsgpackDeflate => vec![Capability::Msgpack, Capability::Deflate],
+        };
+        let req = Request::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            client_name: Some(options.client_name.clone()),
+            capabilities,
+        };
+        // the hello exchange is always json, the granted framing applies after it
+        let granted = match error::check(self.call(req)?)? {
+            Response::Welcome { capabilities, .. } => {
+                Negotiated::from_capabilities(&capabilities).framing
+            }
+            other => return Err(error::unexpected(&other)),
+        };
+        // never silently fall back to another framing than the one asked for
+        if granted != options.framing {
+            return Err(ClientError::Io(format!(
+                "server granted {granted:?} framing instead of {:?}",
+                options.framing
+            )));
+        }
+        self.framing = granted;
+        Ok(())
+    }
+
+    fn call(&mut self, req: Request) -> Result<Response, ClientError> {
+        self.send(&RequestFrame::from(req))
+    }
+
+    fn send(&mut self, frame: &RequestFrame) -> Result<Response, ClientError> {
+        let bytes = encode(self.framing, frame).map_err(ClientError::Protocol)?;
+        let writer = self.stream.get_mut();
+        writer
+            .write_all(&bytes)
+            .and_then(|_| writer.flush())
+            .map_err(io_error)?;
+        self.read()
+    }
+
+    fn read(&mut self) -> Result<Response, ClientError> {
+        match read_frame_blocking::<_, ResponseFrame>(&mut self.stream, self.framing, &mut self.buf)
+        {
+            Ok(Some(Ok(frame))) => Ok(frame.resp),
+            Ok(Some(Err(e))) => Err(ClientError::Protocol(e)),
+            Ok(None) => Err(ClientError::Disconnected),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+}
+
+// a socket timeout shows up as WouldBlock (unix) or TimedOut (windows)
+fn io_error(e: std::io::Error) -> ClientError {
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => ClientError::Timeout,
+        ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset | ErrorKind::BrokenPipe => {
+            ClientError::Disconnected
+        }
+        _ => ClientError::Io(e.to_string()),
+    }
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &std::path::Path) -> std::io::Result<(Socket, Box<dyn Transport>)> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    Ok((Socket::Unix(stream.try_clone()?), Box::new(stream)))
+}
+
+#[cfg(not(unix))]
+fn connect_unix(_path: &std::path::Path) -> std::io::Result<(Socket, Box<dyn Transport>)> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "unix sockets are only supported on unix",
+    ))
+}
@@ -0,0 +1,481 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde_json::Value;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::{mpsc, oneshot},
+    time::{sleep, timeout},
+};
+
+use crate::{
+    client::{
+        error::{self, ClientError},
+        ClientOptions, Endpoint,
+    },
+    event::{Event, EventOp},
+    net::{
+        auth::login,
+        codec::{encode, read_frame},
+        handshake::hello,
+        protocol::{Capability, Framing, Request, RequestFrame, Response, ResponseFrame},
+        tls,
+    },
+    store::kv::Document,
+};
+
+pub(crate) const EVENT_BUFFER: usize = 1024;
+const CALL_BUFFER: usize = 128;
+
+type Ack = oneshot::Sender<Result<(), ClientError>>;
+
+// what Client hands a pooled connection
+pub(crate) enum Call {
+    Request {
+        req: Request,
+        trace_id: Option<String>,
+        resp: oneshot::Sender<Result<Response, ClientError>>,
+    },
+    Subscribe {
+        key: String,
+        events: mpsc::Sender<Event>,
+        resp: Ack,
+    },
+}
+
+// one slot of the pool. The socket itself belongs to the supervise task, which outlives
+// any single connection to the server
+pub(crate) struct Connection {
+    calls: mpsc::Sender<Call>,
+}
+
+impl Connection {
+    pub(crate) async fn open(options: Arc<ClientOptions>) -> Result<Self, ClientError> {
+        let stream = connect(&options).await?;
+        let (calls, calls_rx) = mpsc::channel(CALL_BUFFER);
+        tokio::spawn(supervise(options, stream, calls_rx));
+        Ok(Self { calls })
+    }
+
+    pub(crate) async fn send(&self, call: Call) -> Result<(), ClientError> {
+        self.calls
+            .send(call)
+            .await
+            .map_err(|_| ClientError::Disconnected)
+    }
+}
+
+type BoxRead = Box<dyn AsyncRead + Send + Unpin>;
+type BoxWrite = Box<dyn AsyncWrite + Send + Unpin>;
+
+struct Stream {
+    reader: BufReader<BoxRead>,
+    writer: BoxWrite,
+    framing: Framing,
+}
+
+// connect (unix socket, or tcp + tls), Hello, then Auth when credentials were given.
+// bounded by the request timeout so a black holed address doesn't stall reconnecting
+async fn connect(options: &ClientOptions) -> Result<Stream, ClientError> {
+    timeout(options.request_timeout, open_stream(options))
+        .await
+        .map_err(|_| ClientError::Io("connect timed out".to_string()))?
+}
+
+async fn open_stream(options: &ClientOptions) -> Result<Stream, ClientError> {
+    let (read_half, mut writer): (BoxRead, BoxWrite) = match &options.endpoint {
+        Endpoint::Tcp(addr) => {
+            let stream = TcpStream::connect(addr).await?;
+            stream.set_nodelay(true)?;
+            match &options.tls {
+                Some(connector) => {
+                    let name = tls::server_name(addr).map_err(ClientError::Io)?;
+                    let (r, w) = tokio::io::split(connector.connect(name, stream).await?);
+                    (Box::new(r), Box::new(w))
+                }
+                None => {
+                    let (r, w) = stream.into_split();
+                    (Box::new(r), Box::new(w))
+                }
+            }
+        }
+        Endpoint::Unix(path) => connect_unix(path).await?,
+    };
+    let mut reader = BufReader::new(read_half);
+
+    let mut capabilities = vec![Capability::Pipelining];
+    match options.framing {
+        Framing::Json => {}
+        Framing::Msgpack => capabilities.push(Capability::Msgpack),
+        Framing::MsgpackDeflate => capabilities.extend([Capability::Msgpack, Capability::Deflate]),
+    }
+    let framing = hello(
+        &mut reader,
+        &mut writer,
+        &options.client_name,
+        &capabilities,
+    )
+    .await?
+    .framing;
+    // never silently fall back to another framing than the one asked for
+    if framing != options.framing {
+        return Err(ClientError::Io(format!(
+            "server granted {framing:?} framing instead of {:?}",
+            options.framing
+        )));
+    }
+    if let Some((user, secret)) = &options.credentials {
+        login(&mut reader, &mut writer, framing, user, secret).await?;
+    }
+    Ok(Stream {
+        reader,
+        writer,
+        framing,
+    })
+}
+
+#[cfg(unix)]
+async fn connect_unix(path: &std::path::Path) -> std::io::Result<(BoxRead, BoxWrite)> {
+    let stream = tokio::net::UnixStream::connect(path).await?;
+    let (r, w) = stream.into_split();
+    Ok((Box::new(r), Box::new(w)))
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(_path: &std::path::Path) -> std::io::Result<(BoxRead, BoxWrite)> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "unix sockets are only supported on unix",
+    ))
+}
+
+// a subscription as the caller sees it. It is re-sent on every new connection
+struct Sub {
+    key: String,
+    events: mpsc::Sender<Event>,
+    last: Option<(Value, u64)>, // newest (value, version) passed on, or the baseline read
+}
+
+impl Sub {
+    // false = drop the subscription: the receiver is gone, or it fell EVENT_BUFFER behind
+    fn deliver(&mut self, event: Event) -> bool {
+        let state = (event.new.clone(), event.version);
+        if self.last.as_ref() == Some(&state) {
+            return true; // already passed on as a catch-up event
+        }
+        self.last = Some(state);
+        self.events.try_send(event).is_ok()
+    }
+
+    // the Get sent after Subscribed. The first one only records where the subscription starts,
+    // after a reconnect anything that changed while the connection was down becomes one event
+    fn catch_up(&mut self, doc: Option<Document>, reconnected: bool) -> bool {
+        let Some((last_value, last_version)) = self.last.clone() else {
+            self.last = Some(doc.map_or((Value::Null, 0), |doc| (doc.value, doc.version)));
+            return true;
+        };
+        if !reconnected {
+            return true;
+        }
+        let event = match doc {
+            Some(doc) => Event {
+                key: self.key.clone(),
+                old: last_value,
+                new: doc.value,
+                version: doc.version,
+                op: EventOp::Write,
+            },
+            None if last_value.is_null() => return true,
+            // deleted meanwhile, shaped like the event a delete sends
+            None => Event {
+                key: self.key.clone(),
+                old: last_value,
+                new: Value::Null,
+                version: last_version + 1,
+                op: EventOp::Write,
+            },
+        };
+        self.deliver(event)
+    }
+}
+
+// waiting for a reply on the current connection, by request id
+enum Pending {
+    Call(oneshot::Sender<Result<Response, ClientError>>),
+    // ack is None when this re-subscribes after a reconnect
+    Subscribe { sub: Sub, ack: Option<Ack> },
+    Baseline { sub_id: u64, reconnected: bool },
+}
+
+// owns a pooled connection for the client's lifetime: runs a session per server connection
+// and reconnects in between. Ends when every Client clone is dropped
+async fn supervise(options: Arc<ClientOptions>, stream: Stream, mut calls: mpsc::Receiver<Call>) {
+    let mut stream = Some(stream);
+    let mut queued = Vec::new();
+    let mut subs = Vec::new();
+    loop {
+        let stream = match stream.take() {
+            Some(stream) => stream,
+            None => match reconnect(&options, &mut calls, &mut queued).await {
+                Some(stream) => stream,
+                None => return,
+            },
+        };
+        let session = Session::new(stream.writer, stream.framing);
+        match session
+            .run(stream.reader, &mut calls, std::mem::take(&mut queued), subs)
+            .await
+        {
+            Some(carried) => subs = carried,
+            None => return,
+        }
+    }
+}
+
+// exponential backoff. Calls made meanwhile are kept and sent once connected, unless their
+// caller gave up (request timeout) by then
+async fn reconnect(
+    options: &ClientOptions,
+    calls: &mut mpsc::Receiver<Call>,
+    queued: &mut Vec<Call>,
+) -> Option<Stream> {
+    let mut delay = options.backoff_initial;
+    loop {
+        let wake = sleep(delay);
+        tokio::pin!(wake);
+        loop {
+            tokio::select! {
+                _ = &mut wake => break,
+                call = calls.recv() => match call {
+                    Some(call) => queued.push(call),
+                    None => return None,
+                },
+            }
+        }
+        if let Ok(stream) = connect(options).await {
+            return Some(stream);
+        }
+        delay = (delay * 2).min(options.backoff_max);
+    }
+}
+
+struct Session {
+    writer: BoxWrite,
+    framing: Framing,
+    next_id: u64,
+    pending: HashMap<u64, Pending>,
+    subs: HashMap<u64, Sub>, // by the id of their Subscribe on this connection
+    broken: bool,            // a write failed
+}
+
+impl Session {
+    fn new(writer: BoxWrite, framing: Framing) -> Self {
+        Self {
+            writer,
+            framing,
+            next_id: 1,
+            pending: HashMap::new(),
+            subs: HashMap::new(),
+            broken: false,
+        }
+    }
+
+    // None = the client is gone. Otherwise the connection broke, and the subscriptions to
+    // re-send on the next one come back
+    async fn run(
+        mut self,
+        reader: BufReader<BoxRead>,
+        calls: &mut mpsc::Receiver<Call>,
+        queued: Vec<Call>,
+        resubscribe: Vec<(Sub, Option<Ack>)>,
+    ) -> Option<Vec<(Sub, Option<Ack>)>> {
+        // the reader never waits on us, so a server blocked writing replies can't stall our writes
+        let (frames_tx, mut frames) = mpsc::unbounded_channel();
+        let reader_task = tokio::spawn(read_frames(reader, self.framing, frames_tx));
+
+        for (sub, ack) in resubscribe {
+            self.subscribe(sub, ack).await;
+        }
+        for call in queued {
+            self.start(call).await;
+        }
+
+        while !self.broken {
+            tokio::select! {
+                frame = frames.recv() => match frame {
+                    Some(frame) => self.handle(frame).await,
+                    None => break,
+                },
+                call = calls.recv() => match call {
+                    Some(call) => self.start(call).await,
+                    None => {
+                        reader_task.abort();
+                        return None;
+                    }
+                },
+            }
+        }
+        reader_task.abort();
+        Some(self.wind_down())
+    }
+
+    async fn start(&mut self, call: Call) {
+        match call {
+            Call::Request { req, trace_id, resp } => {
+                if resp.is_closed() {
+                    return;
+                }
+                let id = self.id();
+                self.pending.insert(id, Pending::Call(resp));
+                self.write(id, req, trace_id).await;
+            }
+            Call::Subscribe { key, events, resp } => {
+                if resp.is_closed() {
+                    return;
+                }
+                let sub = Sub {
+                    key,
+                    events,
+                    last: None,
+                };
+                self.subscribe(sub, Some(resp)).await;
+            }
+        }
+    }
+
+    async fn subscribe(&mut self, sub: Sub, ack: Option<Ack>) {
+        if sub.events.is_closed() {
+            return;
+        }
+        let id = self.id();
+        let req = Request::Subscribe {
+            key: sub.key.clone(),
+        };
+        self.pending.insert(id, Pending::Subscribe { sub, ack });
+        self.write(id, req, None).await;
+    }
+
+    async fn handle(&mut self, frame: ResponseFrame) {
+        // no id = a connection level error (idle timeout, ...), the server closes right after
+        let Some(id) = frame.id else {
+            return;
+        };
+
+        if let Some(sub) = self.subs.get_mut(&id) {
+            // anything but an event (an acl change) ends the subscription on the server
+            let keep = match frame.resp {
+                Response::Event { event } => sub.deliver(event),
+                _ => false,
+            };
+            if !keep {
+                self.subs.remove(&id);
+            }
+            return;
+        }
+
+        match self.pending.remove(&id) {
+            Some(Pending::Call(resp)) => {
+                let _ = resp.send(Ok(frame.resp));
+            }
+            Some(Pending::Subscribe { sub, ack }) => match error::check(frame.resp) {
+                Ok(Response::Subscribed { .. }) => {
+                    let reconnected = ack.is_none();
+                    if let Some(ack) = ack {
+                        let _ = ack.send(Ok(()));
+                    }
+                    let key = sub.key.clone();
+                    self.subs.insert(id, sub);
+
+                    // only now, a pipelined Get sent with the Subscribe could run before it
+                    let get_id = self.id();
+                    let baseline = Pending::Baseline {
+                        sub_id: id,
+                        reconnected,
+                    };
+                    self.pending.insert(get_id, baseline);
+                    self.write(get_id, Request::Get { key }, None).await;
+                }
+                // a refused re-subscription just ends, its receiver sees the channel close
+                Ok(other) => {
+                    if let Some(ack) = ack {
+                        let _ = ack.send(Err(error::unexpected(&other)));
+                    }
+                }
+                Err(e) => {
+                    if let Some(ack) = ack {
+                        let _ = ack.send(Err(e));
+                    }
+                }
+            },
+            Some(Pending::Baseline {
+                sub_id,
+                reconnected,
+            }) => {
+                let (Some(sub), Response::Value { doc }) = (self.subs.get_mut(&sub_id), frame.resp)
+                else {
+                    return;
+                };
+                if !sub.catch_up(doc, reconnected) {
+                    self.subs.remove(&sub_id);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    async fn write(&mut self, id: u64, req: Request, trace_id: Option<String>) {
+        let frame = RequestFrame {
+            id: Some(id),
+            trace_id,
+            req,
+        };
+        let written = match encode(self.framing, &frame) {
+            Ok(bytes) => self.writer.write_all(&bytes).await.is_ok(),
+            Err(_) => false,
+        };
+        if !written {
+            self.broken = true;
+        }
+    }
+
+    // in flight requests fail, subscriptions (confirmed or not) move to the next connection
+    fn wind_down(mut self) -> Vec<(Sub, Option<Ack>)> {
+        let mut carried: Vec<_> = self.subs.drain().map(|(_, sub)| (sub, None)).collect();
+        for (_, pending) in self.pending.drain() {
+            match pending {
+                Pending::Call(resp) => {
+                    let _ = resp.send(Err(ClientError::Disconnected));
+                }
+                Pending::Subscribe { sub, ack } => carried.push((sub, ack)),
+                Pending::Baseline { .. } => {}
+            }
+        }
+        carried
+    }
+}
+
+// undecodable frames (a newer server) are skipped, a read error or EOF ends the connection
+async fn read_frames(
+    mut reader: BufReader<BoxRead>,
+    framing: Framing,
+    frames: mpsc::UnboundedSender<ResponseFrame>,
+) {
+    let mut buf = Vec::new();
+    loop {
+        match read_frame(&mut reader, framing, &mut buf).await {
+            Ok(Some(Ok(frame))) => {
+                if frames.send(frame).is_err() {
+                    break;
+                }
+            }
+            Ok(Some(Err(_))) => continue,
+            Ok(None) | Err(_) => break,
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt;
+
+use crate::net::protocol::Response;
+
+// what a client call can fail with. Shared by the async and the blocking client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientError {
+    // connecting, the handshake or the socket itself failed
+    Io(String),
+    // the server answered with Response::Error
+    Server(String),
+    // over the connection's or the user's rate limit, the request was not run
+    RateLimited { retry_after_ms: u64 },
+    // the connection dropped while the request was in flight. It may or may not have run
+    Disconnected,
+    // no answer within the request timeout (that includes waiting for a reconnect)
+    Timeout,
+    // the server sent something that doesn't answer the request
+    Protocol(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "connection error: {e}"),
+            ClientError::Server(message) => write!(f, "server error: {message}"),
+            ClientError::RateLimited { retry_after_ms } => {
+                write!(f, "rate limited, retry after {retry_after_ms}ms")
+            }
+            ClientError::Disconnected => write!(f, "disconnected before the server answered"),
+            ClientError::Timeout => write!(f, "request timed out"),
+            ClientError::Protocol(e) => write!(f, "unexpected reply: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<std::io::Error> for ClientError {
+    fn from(e: std::io::Error) -> Self {
+        ClientError::Io(e.to_string())
+    }
+}
+
+// Error / RateLimited replies become errors, anything else is handed back
+pub fn check(resp: Response) -> Result<Response, ClientError> {
+    match resp {
+        Response::Error { message } => Err(ClientError::Server(message)),
+        Response::RateLimited { retry_after_ms } => {
+            Err(ClientError::RateLimited { retry_after_ms })
+        }
+        other => Ok(other),
+    }
+}
+
+pub fn expect_ok(resp: Response) -> Result<(), ClientError> {
+    match check(resp)? {
+        Response::Ok => Ok(()),
+        other => Err(unexpected(&other)),
+    }
+}
+
+pub fn unexpected(resp: &Response) -> ClientError {
+    ClientError::Protocol(format!("{resp:?}"))
+}
@@ -0,0 +1,231 @@
+mod args;
+pub mod blocking;
+mod connection;
+pub mod error;
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    engine::backup::Manifest,
+    event::Event,
+    net::{
+        protocol::{Framing, Info, Request, Response, Stats},
+        tls::TlsConnector,
+    },
+    store::kv::Document,
+};
+
+pub use args::ConnectArgs;
+use connection::{Call, Connection};
+pub use error::ClientError;
+
+// where the server listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Tcp(String), // host:port
+    Unix(PathBuf),
+}
+
+#[derive(Clone)]
+pub struct ClientOptions {
+    pub endpoint: Endpoint,
+    pub tls: Option<TlsConnector>, // tcp only, the certificate must match the host in the addr
+    pub credentials: Option<(String, String)>, // user, secret
+    pub framing: Framing,
+    pub client_name: String,
+    pub pool_size: usize, // connections, requests and subscriptions are spread over them
+    pub request_timeout: Duration, // includes time spent waiting for a reconnect
+    pub backoff_initial: Duration, // first reconnect delay, doubled per failed attempt
+    pub backoff_max: Duration,
+}
+
+impl ClientOptions {
+    pub fn tcp(addr: &str) -> Self {
+        Self {
+            endpoint: Endpoint::Tcp(addr.to_string()),
+            tls: None,
+            credentials: None,
+            framing: Framing::Json,
+            client_name: "fluxdb-client".to_string(),
+            pool_size: 4,
+            request_timeout: Duration::from_secs(30),
+            backoff_initial: Duration::from_millis(50),
+            backoff_max: Duration::from_secs(5),
+        }
+    }
+
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Self {
+            endpoint: Endpoint::Unix(path.into()),
+            ..Self::tcp("")
+        }
+    }
+}
+
+// EngineHandle over the network. Cheap to clone, clones share the pool.
+//
+// - every pooled connection says Hello (pipelining + the framing asked for) and logs in
+// - a dropped connection is reopened in the background with exponential backoff. Requests
+//   made meanwhile wait for it (up to request_timeout); requests that were in flight when it
+//   dropped fail with ClientError::Disconnected since they may or may not have run
+// - subscriptions survive reconnects. The server can't replay missed events, so after
+//   re-subscribing the client reads the key and sends one catch-up event if it changed while
+//   the connection was down (several missed changes show up as a single event)
+#[derive(Clone)]
+pub struct Client {
+    pool: Arc<[Connection]>,
+    next: Arc<AtomicUsize>,
+    request_timeout: Duration,
+}
+
+impl Client {
+    pub async fn connect(addr: &str) -> Result<Self, ClientError> {
+        Self::connect_with(ClientOptions::tcp(addr)).await
+    }
+
+    // fails if any of the pooled connections can't be opened, reconnecting starts after that
+    pub async fn connect_with(options: ClientOptions) -> Result<Self, ClientError> {
+        let options = Arc::new(options);
+        let mut pool = Vec::with_capacity(options.pool_size.max(1));
+        for _ in 0..options.pool_size.max(1) {
+            pool.push(Connection::open(options.clone()).await?);
+        }
+        Ok(Self {
+            pool: pool.into(),
+            next: Arc::new(AtomicUsize::new(0)),
+            request_timeout: options.request_timeout,
+        })
+    }
+
+    pub async fn set(&self, key: String, value: Value) -> Result<(), ClientError> {
+        error::expect_ok(self.request(Request::Set { key, value }).await?)
+    }
+
+    pub async fn patch(&self, key: String, delta: Value) -> Result<(), ClientError> {
+        error::expect_ok(self.request(Request::Patch { key, delta }).await?)
+    }
+
+    pub async fn delete(&self, key: String) -> Result<(), ClientError> {
+        error::expect_ok(self.request(Request::Del { key }).await?)
+    }
+
+    pub async fn get(&self, key: String) -> Result<Option<Document>, ClientError> {
+        match error::check(self.request(Request::Get { key }).await?)? {
+            Response::Value { doc } => Ok(doc),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    pub async fn snapshot(&self) -> Result<(), ClientError> {
+        error::expect_ok(self.request(Request::Snapshot).await?)
+    }
+
+    pub async fn stats(&self) -> Result<Stats, ClientError> {
+        match error::check(self.request(Request::Stats).await?)? {
+            Response::Stats { stats } => Ok(stats),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    pub async fn info(&self) -> Result<Info, ClientError> {
+        match error::check(self.request(Request::Info).await?)? {
+            Response::Info { info } => Ok(info),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    pub async fn db_size(&self) -> Result<u64, ClientError> {
+        match error::check(self.request(Request::DbSize).await?)? {
+            Response::DbSize { size } => Ok(size),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    // sorted, plus whether more keys matched than `limit`
+    pub async fn keys(
+        &self,
+        pattern: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<String>, bool), ClientError> {
+        match error::check(self.request(Request::Keys { pattern, limit }).await?)? {
+            Response::Keys { keys, truncated } => Ok((keys, truncated)),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    pub async fn flush_all(&self) -> Result<(), ClientError> {
+        error::expect_ok(self.request(Request::FlushAll).await?)
+    }
+
+    // dest_dir is on the server's filesystem
+    pub async fn backup(&self, dest_dir: String) -> Result<Manifest, ClientError> {
+        match error::check(self.request(Request::Backup { dest_dir }).await?)? {
+            Response::Backup { manifest } => Ok(manifest),
+            other => Err(error::unexpected(&other)),
+        }
+    }
+
+    // the receiver ends when the client is dropped, when it falls too far behind (like a slow
+    // subscriber on the server) or when the server ends the subscription (acl change)
+    pub async fn subscribe(&self, key: String) -> Result<mpsc::Receiver<Event>, ClientError> {
+        let (events, events_rx) = mpsc::channel(connection::EVENT_BUFFER);
+        let (resp, resp_rx) = oneshot::channel();
+        self.call(Call::Subscribe { key, events, resp }, resp_rx)
+            .await??;
+        Ok(events_rx)
+    }
+
+    // any request, answered with the raw response (Error and RateLimited included).
+    // Subscribe goes through subscribe(), Hello and Auth are done by the connection itself
+    pub async fn request(&self, req: Request) -> Result<Response, ClientError> {
+        self.send_request(req, None).await
+    }
+
+    // request() with a trace id the server puts on every log line the request causes
+    pub async fn request_traced(
+        &self,
+        req: Request,
+        trace_id: String,
+    ) -> Result<Response, ClientError> {
+        self.send_request(req, Some(trace_id)).await
+    }
+
+    async fn send_request(
+        &self,
+        req: Request,
+        trace_id: Option<String>,
+    ) -> Result<Response, ClientError> {
+        match req {
+            Request::Subscribe { .. } | Request::Hello { .. } | Request::Auth { .. } => {
+                return Err(ClientError::Protocol(format!(
+                    "{req:?} can't be sent through request()"
+                )));
+            }
+            _ => {}
+        }
+        let (resp, resp_rx) = oneshot::channel();
+        self.call(Call::Request { req, trace_id, resp }, resp_rx)
+            .await?
+    }
+
+    async fn call<T>(&self, call: Call, resp_rx: oneshot::Receiver<T>) -> Result<T, ClientError> {
+        let n = self.next.fetch_add(1, Ordering::Relaxed) % self.pool.len();
+        let exchange = async {
+            self.pool[n].send(call).await?;
+            resp_rx.await.map_err(|_| ClientError::Disconnected)
+        };
+        tokio::time::timeout(self.request_timeout, exchange)
+            .await
+            .map_err(|_| ClientError::Timeout)?
+    }
+}
@@ -0,0 +1,333 @@
+use std::{net::ToSocketAddrs, path::PathBuf, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{
+    engine::config::{Durability, EngineConfig, Recovery},
+    logging::{self, LogFormat},
+    net::{acl::AdminPolicy, limits::ServerLimits, ratelimit::Budget},
+};
+
+// the server's config file (toml). Every key is optional, missing ones keep the default.
+// The server binary lays FLUXDB_* environment variables and then command line flags over it
+// (flag > env > file > default), then calls validate:
+//
+//   addr = "0.0.0.0:7000"
+//   ws_addr = "0.0.0.0:8080"
+//   http_addr = "0.0.0.0:8081"
+//   resp_addr = "0.0.0.0:6379"
+//   metrics_addr = "10.0.0.5:9100" # prometheus scrape endpoint, unauthenticated
+//   unix_socket = "/run/fluxdb/a.sock"
+//   unix_socket_mode = 0o660     # who may connect is decided by the file permissions
+//   auth_file = "/etc/fluxdb/auth.toml"
+//   data_dir = "/var/lib/fluxdb/a"
+//
+//   [tls]
+//   cert = "server.pem"
+//   key = "server.key"
+//   client_ca = "clients-ca.pem"
+//
+//   [wal]
+//   segment_size = 67108864
+//   durability = "sync"          # sync, periodic or off
+//   fsync_interval_ms = 100      # periodic only
+//
+//   [snapshot]
+//   interval_secs = 30
+//   every_writes = 1000
+//
+//   [log]
+//   level = "info"               # RUST_LOG syntax, e.g. "warn,fluxdb::engine=debug"
+//   format = "json"              # json or text
+//
+//   [limits]
+//   max_request_bytes = 16777216
+//   max_connections = 1024
+//   idle_timeout_secs = 300      # 0 = never
+//   read_timeout_secs = 30       # 0 = never
+//   max_subscriptions = 1024
+//   conn_rate = { write_ops = 500 }
+//
+//   [admin]
+//   allow_anonymous = false      # flush_all and backup when there is no auth_file
+//   backup_root = "/var/backups/fluxdb" # backup requests name a directory below it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub addr: String,
+    pub ws_addr: Option<String>,
+    pub http_addr: Option<String>,
+    pub resp_addr: Option<String>,
+    pub metrics_addr: Option<String>,
+    pub unix_socket: Option<PathBuf>,
+    pub unix_socket_mode: u32,
+    pub auth_file: Option<PathBuf>,
+    pub data_dir: PathBuf,
+    pub tls: TlsConfig,
+    pub wal: WalConfig,
+    pub snapshot: SnapshotConfig,
+    pub limits: LimitsConfig,
+    pub log: LogConfig,
+    pub admin: AdminConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub client_ca: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalConfig {
+    pub segment_size: u64,
+    pub durability: Durability,
+    pub fsync_interval_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    pub interval_secs: u64,
+    pub every_writes: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    pub allow_anonymous: bool,
+    pub backup_root: Option<PathBuf>, // None = backups are refused
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_request_bytes: usize,
+    pub max_connections: usize,
+    pub idle_timeout_secs: u64,
+    pub read_timeout_secs: u64,
+    pub max_subscriptions: usize,
+    pub conn_rate: Budget,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let engine = EngineConfig::default();
+        Self {
+            addr: "127.0.0.1:7000".to_string(),
+            ws_addr: None,
+            http_addr: None,
+            resp_addr: None,
+            metrics_addr: None,
+            unix_socket: None,
+            unix_socket_mode: 0o660,
+            auth_file: None,
+            data_dir: engine.data_dir,
+            tls: TlsConfig::default(),
+            wal: WalConfig::default(),
+            snapshot: SnapshotConfig::default(),
+            limits: LimitsConfig::default(),
+            log: LogConfig::default(),
+            admin: AdminConfig::default(),
+        }
+    }
+}
+
+impl Default for WalConfig {
+    fn default() -> Self {
+        let engine = EngineConfig::default();
+        Self {
+            segment_size: engine.segment_size,
+            durability: engine.durability,
+            fsync_interval_ms: engine.fsync_interval.as_millis() as u64,
+        }
+    }
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        let engine = EngineConfig::default();
+        Self {
+            interval_secs: engine.snapshot_interval.as_secs(),
+            every_writes: engine.snapshot_every,
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        let limits = ServerLimits::default();
+        let secs = |t: Option<Duration>| t.map_or(0, |t| t.as_secs());
+        Self {
+            max_request_bytes: limits.max_request_bytes,
+            max_connections: limits.max_connections,
+            idle_timeout_secs: secs(limits.idle_timeout),
+            read_timeout_secs: secs(limits.read_timeout),
+            max_subscriptions: limits.max_subscriptions,
+            conn_rate: limits.conn_rate,
+        }
+    }
+}
+
+// 0 = no timeout
+fn timeout(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+impl ServerConfig {
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("reading config file {}: {e}", path.display()))?;
+        toml::from_str(&text).map_err(|e| format!("parsing config file {}: {e}", path.display()))
+    }
+
+    pub fn engine(&self) -> EngineConfig {
+        EngineConfig {
+            data_dir: self.data_dir.clone(),
+            segment_size: self.wal.segment_size,
+            durability: self.wal.durability,
+            fsync_interval: Duration::from_millis(self.wal.fsync_interval_ms),
+            snapshot_interval: Duration::from_secs(self.snapshot.interval_secs),
+            snapshot_every: self.snapshot.every_writes,
+            // not a config file setting, see server --recover-best-effort
+            recovery: Recovery::Strict,
+        }
+    }
+
+    pub fn limits(&self) -> ServerLimits {
+        let limits = &self.limits;
+        ServerLimits {
+            max_request_bytes: limits.max_request_bytes,
+            max_connections: limits.max_connections,
+            max_subscriptions: limits.max_subscriptions,
+            idle_timeout: timeout(limits.idle_timeout_secs),
+            read_timeout: timeout(limits.read_timeout_secs),
+            conn_rate: limits.conn_rate,
+        }
+    }
+
+    pub fn admin(&self) -> AdminPolicy {
+        AdminPolicy {
+            allow_anonymous: self.admin.allow_anonymous,
+            backup_root: self.admin.backup_root.clone(),
+        }
+    }
+
+    // everything that can be checked before binding anything. Errors name the setting
+    pub fn validate(&self) -> Result<(), String> {
+        check_addr("addr", &self.addr)?;
+        for (name, addr) in [
+            ("ws_addr", &self.ws_addr),
+            ("http_addr", &self.http_addr),
+            ("resp_addr", &self.resp_addr),
+            ("metrics_addr", &self.metrics_addr),
+        ] {
+            if let Some(addr) = addr {
+                check_addr(name, addr)?;
+            }
+        }
+
+        if let Some(path) = &self.unix_socket {
+            check_unix_socket(path, self.unix_socket_mode)?;
+        }
+
+        match (&self.tls.cert, &self.tls.key) {
+            (Some(_), None) => return Err("tls cert is set but tls key is not".to_string()),
+            (None, Some(_)) => return Err("tls key is set but tls cert is not".to_string()),
+            (None, None) if self.tls.client_ca.is_some() => {
+                return Err("tls client ca needs tls cert and key".to_string());
+            }
+            _ => {}
+        }
+
+        self.engine().validate()?;
+        if let Some(root) = &self.admin.backup_root {
+            if root.as_os_str().is_empty() || root.is_file() {
+                return Err(format!(
+                    "admin backup_root {:?} is not a directory",
+                    root.display()
+                ));
+            }
+        }
+        logging::parse_level(&self.log.level)?;
+
+        let limits = &self.limits;
+        if limits.max_request_bytes == 0 || limits.max_request_bytes > u32::MAX as usize {
+            return Err(format!(
+                "max request bytes must be between 1 and {}",
+                u32::MAX
+            ));
+        }
+        if limits.max_connections == 0 {
+            return Err("max connections must be above 0".to_string());
+        }
+        if limits.max_subscriptions == 0 {
+            return Err("max subscriptions must be above 0".to_string());
+        }
+        let rate = limits.conn_rate;
+        for (name, value) in [
+            ("read_ops", rate.read_ops),
+            ("write_ops", rate.write_ops),
+            ("read_bytes", rate.read_bytes),
+            ("write_bytes", rate.write_bytes),
+        ] {
+            if value == Some(0) {
+                return Err(format!(
+                    "conn rate {name} must be above 0 (leave it out for unlimited)"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_addr(name: &str, addr: &str) -> Result<(), String> {
+    match addr.to_socket_addrs().map(|mut addrs| addrs.next()) {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(format!("{name} {addr:?} does not resolve to any address")),
+        Err(e) => Err(format!("{name} {addr:?} is not a valid host:port: {e}")),
+    }
+}
+
+fn check_unix_socket(path: &std::path::Path, mode: u32) -> Result<(), String> {
+    if cfg!(not(unix)) {
+        return Err("unix_socket is only supported on unix".to_string());
+    }
+    if path.as_os_str().is_empty() {
+        return Err("unix_socket must not be empty".to_string());
+    }
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(std::path::Path::new("."));
+    if !dir.is_dir() {
+        return Err(format!(
+            "unix_socket directory {} does not exist",
+            dir.display()
+        ));
+    }
+    if mode > 0o777 {
+        return Err(format!(
+            "unix_socket_mode {mode:o} is not a permission mode (000 to 777)"
+        ));
+    }
+    Ok(())
+}
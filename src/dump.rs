@@ -0,0 +1,245 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    engine::{
+        config::Recovery,
+        db::{replay, snapshot_path},
+    },
+    store::{kv::{Document, Store}, snapshot::Snapshot, wal::lsn::Lsn, wal::replay::WalIterator},
+};
+
+// logical export / import for fluxdb-dump and fluxdb-load. Only documents go in here, nothing
+// about segments or snapshots, so a dump loads into a server whatever its on disk format is.
+//
+// jsonl: one {"key": .., "value": .., "version": ..} per line
+// csv:   key,version,<field>,.. with one column per top level field of the documents. Only for
+//        flat objects (no nested objects / arrays). An empty cell is a missing field; a cell that
+//        reads as a json number, bool or null is loaded as one, anything else as a string
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub key: String,
+    pub value: Value,
+    // what it was where it was dumped. Informational on load, the server numbers its own writes
+    #[serde(default)]
+    pub version: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Jsonl,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" | "json" => Ok(Format::Jsonl),
+            "csv" => Ok(Format::Csv),
+            other => Err(format!("unknown format {other:?}, expected jsonl or csv")),
+        }
+    }
+}
+
+// the documents of a data dir (snapshot + the wal after it), sorted by key. Opens nothing for
+// writing, so it also works on the data dir of a running server (as of the last complete record).
+// Wal damage is judged like the server's open does (db::replay): a torn tail is left out, damage
+// anywhere else is an error unless `recovery` is BestEffort.
+// The replayed store has to be in memory, the records are moved out of it one at a time
+pub fn read_data_dir(
+    data_dir: &Path,
+    prefix: &str,
+    recovery: Recovery,
+) -> Result<impl Iterator<Item = Record>, String> {
+    if !data_dir.join("wal").is_dir() {
+        return Err(format!("{} is not a fluxdb data dir (no wal/)", data_dir.display()));
+    }
+    let mut store = Store::new();
+    let lsn = match fs::read(snapshot_path(data_dir)) {
+        Ok(bytes) => {
+            let snapshot: Snapshot =
+                serde_json::from_slice(&bytes).map_err(|e| format!("invalid snapshot.json: {e}"))?;
+            store.data = snapshot.data;
+            snapshot.lsn
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Lsn::ZERO,
+        Err(e) => return Err(format!("snapshot.json: {e}")),
+    };
+    let mut wal = WalIterator::open(data_dir, lsn).map_err(|e| format!("wal: {e}"))?;
+    replay(&mut wal, &mut store, lsn, recovery).map_err(|e| format!("wal: {e}"))?;
+
+    // a btree frees its nodes as it is iterated, so written records don't stay around
+    let sorted: BTreeMap<String, Document> = store
+        .data
+        .into_iter()
+        .filter(|(key, _)| key.starts_with(prefix))
+        .collect();
+    Ok(sorted.into_iter().map(|(key, doc)| Record {
+        key,
+        value: doc.value,
+        version: doc.version,
+    }))
+}
+
+// writes records as they come. Jsonl goes straight out; csv needs every field for its header,
+// so it only checks each record on push and writes them all in finish
+pub struct Writer<W: Write> {
+    out: W,
+    format: Format,
+    held: Vec<Record>, // csv only
+    written: u64,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W, format: Format) -> Self {
+        Self {
+            out,
+            format,
+            held: Vec::new(),
+            written: 0,
+        }
+    }
+
+    pub fn push(&mut self, record: Record) -> Result<(), String> {
+        match self.format {
+            Format::Jsonl => {
+                serde_json::to_writer(&mut self.out, &record)
+                    .map_err(|e| format!("write error: {e}"))?;
+                self.out.write_all(b"\n").map_err(|e| format!("write error: {e}"))?;
+            }
+            Format::Csv => {
+                flat_object(&record)?;
+                self.held.push(record);
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    // flushes, and returns how many records were written
+    pub fn finish(mut self) -> Result<u64, String> {
+        let done = match self.format {
+            Format::Jsonl => self.out.flush().map_err(|e| e.to_string()),
+            Format::Csv => write_csv(&mut self.out, &self.held).map_err(|e| e.to_string()),
+        };
+        done.map_err(|e| format!("write error: {e}"))?;
+        Ok(self.written)
+    }
+}
+
+pub fn write(out: impl Write, format: Format, records: &[Record]) -> Result<(), String> {
+    let mut writer = Writer::new(out, format);
+    for record in records {
+        writer.push(record.clone())?;
+    }
+    writer.finish().map(|_| ())
+}
+
+fn write_csv(out: impl Write, records: &[Record]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut fields = BTreeSet::new();
+    for record in records {
+        let object = flat_object(record)?;
+        fields.extend(object.keys().map(String::as_str));
+    }
+    if let Some(clash) = fields.iter().find(|f| matches!(**f, "key" | "version")) {
+        return Err(format!("a document field is named {clash:?}, which csv uses for its own column, use jsonl").into());
+    }
+
+    let mut csv = csv::Writer::from_writer(out);
+    csv.write_record(["key", "version"].into_iter().chain(fields.iter().copied()))?;
+    for record in records {
+        let object = flat_object(record)?;
+        let mut row = vec![record.key.clone(), record.version.to_string()];
+        row.extend(fields.iter().map(|field| match object.get(*field) {
+            None => String::new(),
+            Some(Value::String(s)) => s.clone(),
+            Some(scalar) => scalar.to_string(),
+        }));
+        csv.write_record(&row)?;
+    }
+    Ok(csv.flush()?)
+}
+
+fn flat_object(record: &Record) -> Result<&Map<String, Value>, String> {
+    match &record.value {
+        Value::Object(object) if !object.values().any(|v| v.is_object() || v.is_array()) => Ok(object),
+        _ => Err(format!("{}: not a flat object, csv only holds those, use jsonl", record.key)),
+    }
+}
+
+// records one at a time, so a big file never has to fit in memory. Errors carry the line / row
+pub fn read(
+    input: impl Read + 'static,
+    format: Format,
+) -> Result<Box<dyn Iterator<Item = Result<Record, String>>>, String> {
+    match format {
+        Format::Jsonl => Ok(Box::new(BufReader::new(input).lines().enumerate().filter_map(
+            |(i, line)| {
+                let line = match line {
+                    Ok(line) if line.trim().is_empty() => return None,
+                    Ok(line) => line,
+                    Err(e) => return Some(Err(format!("line {}: {e}", i + 1))),
+                };
+                Some(serde_json::from_str(&line).map_err(|e| format!("line {}: {e}", i + 1)))
+            },
+        ))),
+        Format::Csv => {
+            let mut csv = csv::Reader::from_reader(input);
+            let header: Vec<String> = csv
+                .headers()
+                .map_err(|e| format!("csv header: {e}"))?
+                .iter()
+                .map(str::to_string)
+                .collect();
+            if header.first().map(String::as_str) != Some("key") {
+                return Err("csv header must start with a key column".to_string());
+            }
+            Ok(Box::new(csv.into_records().enumerate().map(move |(i, row)| {
+                // row 1 is the header
+                let row = row.map_err(|e| format!("row {}: {e}", i + 2))?;
+                csv_record(&header, &row).map_err(|e| format!("row {}: {e}", i + 2))
+            })))
+        }
+    }
+}
+
+fn csv_record(header: &[String], row: &csv::StringRecord) -> Result<Record, String> {
+    let (mut key, mut version, mut object) = (String::new(), 0, Map::new());
+    for (column, cell) in header.iter().zip(row.iter()) {
+        match column.as_str() {
+            "key" => key = cell.to_string(),
+            "version" if !cell.is_empty() => {
+                version = cell.parse().map_err(|_| format!("bad version {cell:?}"))?;
+            }
+            "version" => {}
+            _ if cell.is_empty() => {}
+            field => {
+                let value = match serde_json::from_str::<Value>(cell) {
+                    Ok(v) if !(v.is_string() || v.is_object() || v.is_array()) => v,
+                    _ => Value::String(cell.to_string()),
+                };
+                object.insert(field.to_string(), value);
+            }
+        }
+    }
+    if key.is_empty() {
+        return Err("empty key".to_string());
+    }
+    Ok(Record {
+        key,
+        value: Value::Object(object),
+        version,
+    })
+}
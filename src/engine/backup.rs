@@ -0,0 +1,241 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    engine::db::snapshot_path,
+    store::{snapshot::Snapshot, wal::lsn::Lsn},
+};
+
+// A backup directory is a data dir (snapshot.json + wal/N.log) plus manifest.json:
+//
+// - snapshot.json is a checkpoint taken for the backup, at snapshot_lsn
+// - wal/ holds the segments from snapshot_lsn.segment up to end_lsn, the last one cut at
+//   end_lsn.offset. Replaying them on top of the snapshot gives the store as of end_lsn
+// - the manifest is written last, so a directory without one is an unfinished backup
+//
+// The server takes it while writes continue (snapshot actor, SnapshotActorCommand::Backup):
+// everything before end_lsn is complete records that are never rewritten, later appends are
+// simply not part of the backup.
+
+pub const MANIFEST: &str = "manifest.json";
+const FORMAT: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub created_unix_ms: u64,
+    pub snapshot_lsn: Lsn,
+    pub end_lsn: Lsn,
+    pub files: Vec<BackupFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub path: String, // relative to the backup dir, "/" separated
+    pub bytes: u64,
+    pub sha256: String, // hex
+}
+
+// writes the backup into `dest`, which must not exist or be empty. `snapshot` is the serialized
+// checkpoint at snapshot_lsn, the wal segments are copied out of `data_dir`
+pub fn write_backup(
+    data_dir: &Path,
+    dest: &Path,
+    snapshot: &[u8],
+    snapshot_lsn: Lsn,
+    end_lsn: Lsn,
+) -> Result<Manifest, String> {
+    if fs::read_dir(dest).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(format!("backup destination {} is not empty", dest.display()));
+    }
+    fs::create_dir_all(dest.join("wal")).map_err(|e| format!("backup create dir error: {e}"))?;
+
+    let mut files = vec![copy_file(&mut &snapshot[..], dest, "snapshot.json".to_string())?];
+    for segment in snapshot_lsn.segment..=end_lsn.segment {
+        let source = data_dir.join("wal").join(format!("{segment}.log"));
+        let file =
+            File::open(&source).map_err(|e| format!("backup open {} error: {e}", source.display()))?;
+        let path = format!("wal/{segment}.log");
+        files.push(if segment == end_lsn.segment {
+            copy_file(&mut file.take(end_lsn.offset), dest, path)?
+        } else {
+            copy_file(&mut &file, dest, path)?
+        });
+    }
+    if files.last().map(|f| f.bytes) != Some(end_lsn.offset) {
+        return Err(format!("wal segment {} is shorter than {end_lsn:?}", end_lsn.segment));
+    }
+
+    let manifest = Manifest {
+        format: FORMAT,
+        created_unix_ms: unix_ms(),
+        snapshot_lsn,
+        end_lsn,
+        files,
+    };
+    let bytes = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    let tmp = dest.join("manifest.json.tmp");
+    write_synced(&tmp, &bytes).map_err(|e| format!("backup manifest write error: {e}"))?;
+    fs::rename(&tmp, dest.join(MANIFEST)).map_err(|e| format!("backup manifest rename error: {e}"))?;
+    sync_dir(dest).map_err(|e| format!("backup dir fsync error: {e}"))?;
+    Ok(manifest)
+}
+
+// checks a backup dir against its manifest: every file present with its size and checksum, the
+// snapshot at snapshot_lsn, and no gap in the wal segments
+pub fn verify(dir: &Path) -> Result<Manifest, String> {
+    let bytes = fs::read(dir.join(MANIFEST))
+        .map_err(|e| format!("no readable {MANIFEST} in {}: {e}", dir.display()))?;
+    let manifest: Manifest =
+        serde_json::from_slice(&bytes).map_err(|e| format!("invalid {MANIFEST}: {e}"))?;
+    if manifest.format != FORMAT {
+        return Err(format!("unsupported backup format {}", manifest.format));
+    }
+
+    let expected: Vec<String> = std::iter::once("snapshot.json".to_string())
+        .chain((manifest.snapshot_lsn.segment..=manifest.end_lsn.segment).map(|s| format!("wal/{s}.log")))
+        .collect();
+    let listed: Vec<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+    if listed != expected {
+        return Err(format!("manifest lists {listed:?}, expected {expected:?}"));
+    }
+
+    for file in &manifest.files {
+        let path = dir.join(&file.path);
+        let (bytes, sha256) = hash_file(&path).map_err(|e| format!("{}: {e}", file.path))?;
+        if bytes != file.bytes || sha256 != file.sha256 {
+            return Err(format!("{} does not match the manifest (size or checksum)", file.path));
+        }
+    }
+
+    let snapshot: Snapshot = serde_json::from_slice(
+        &fs::read(snapshot_path(dir)).map_err(|e| format!("snapshot.json: {e}"))?,
+    )
+    .map_err(|e| format!("snapshot.json: {e}"))?;
+    if snapshot.lsn != manifest.snapshot_lsn {
+        return Err(format!(
+            "snapshot.json is at {:?}, the manifest says {:?}",
+            snapshot.lsn, manifest.snapshot_lsn
+        ));
+    }
+    Ok(manifest)
+}
+
+// verifies `backup`, copies it next to `data_dir` and swaps it in. An existing, non empty
+// data_dir is only replaced with `replace`, and then moved aside (returned) rather than deleted.
+// The server must not be running on data_dir
+pub fn restore(
+    backup: &Path,
+    data_dir: &Path,
+    replace: bool,
+) -> Result<(Manifest, Option<PathBuf>), String> {
+    let manifest = verify(backup)?;
+
+    let occupied = fs::read_dir(data_dir).is_ok_and(|mut entries| entries.next().is_some());
+    if occupied && !replace {
+        return Err(format!(
+            "{} already has data, pass --force to move it aside and restore anyway",
+            data_dir.display()
+        ));
+    }
+
+    let staging = sibling(data_dir, "restoring");
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(|e| format!("restore cleanup error: {e}"))?;
+    }
+    fs::create_dir_all(staging.join("wal")).map_err(|e| format!("restore create dir error: {e}"))?;
+    for file in &manifest.files {
+        let mut source = File::open(backup.join(&file.path)).map_err(|e| format!("{}: {e}", file.path))?;
+        // checked again on the copy, the backup could have changed since verify
+        let copied = copy_file(&mut source, &staging, file.path.clone())?;
+        if copied != *file {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(format!("{} changed while it was being restored", file.path));
+        }
+    }
+    sync_dir(&staging).map_err(|e| format!("restore fsync error: {e}"))?;
+
+    let previous = if data_dir.exists() {
+        let aside = sibling(data_dir, &format!("pre-restore-{}", unix_ms()));
+        fs::rename(data_dir, &aside).map_err(|e| format!("restore move aside error: {e}"))?;
+        Some(aside)
+    } else {
+        None
+    };
+    fs::rename(&staging, data_dir).map_err(|e| format!("restore rename error: {e}"))?;
+    if let Some(parent) = data_dir.parent().filter(|p| !p.as_os_str().is_empty()) {
+        let _ = sync_dir(parent);
+    }
+    Ok((manifest, previous))
+}
+
+// copies `source` to dir/path (fsynced), hashing on the way
+fn copy_file(source: &mut impl Read, dir: &Path, path: String) -> Result<BackupFile, String> {
+    let (bytes, sha256) = copy_synced(source, &dir.join(&path))
+        .map_err(|e| format!("backup copy {path} error: {e}"))?;
+    Ok(BackupFile { path, bytes, sha256 })
+}
+
+fn copy_synced(source: &mut impl Read, to: &Path) -> io::Result<(u64, String)> {
+    let mut out = File::create(to)?;
+    let copied = hash_copy(source, Some(&mut out))?;
+    out.sync_all()?;
+    Ok(copied)
+}
+
+fn hash_file(path: &Path) -> io::Result<(u64, String)> {
+    hash_copy(&mut File::open(path)?, None)
+}
+
+fn hash_copy(source: &mut impl Read, mut out: Option<&mut File>) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut total = 0;
+    loop {
+        let n = source.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        if let Some(out) = out.as_deref_mut() {
+            out.write_all(&buf[..n])?;
+        }
+        total += n as u64;
+    }
+    let hex = hasher.finalize().iter().map(|b| format!("{b:02x}")).collect();
+    Ok((total, hex))
+}
+
+fn write_synced(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()?;
+    let wal = dir.join("wal");
+    if wal.is_dir() {
+        File::open(wal)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// ./fluxdb -> ./.fluxdb.<suffix>
+fn sibling(dir: &Path, suffix: &str) -> PathBuf {
+    let name = dir.file_name().map_or("fluxdb".into(), |n| n.to_string_lossy());
+    dir.with_file_name(format!(".{name}.{suffix}"))
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
@@ -0,0 +1,114 @@
+use std::{fmt, path::PathBuf, str::FromStr, time::Duration};
+
+use serde::Deserialize;
+
+// when a write is acknowledged relative to its fsync
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Durability {
+    // fsync every batch before anything in it is applied or acked (the default, nothing acked is lost)
+    #[default]
+    Sync,
+    // ack once the record is in the WAL file, fsync every fsync_interval. A crash loses at most
+    // that much acked data
+    Periodic,
+    // no fsync except before a snapshot, the OS writes the WAL back whenever it likes. For tests
+    // and throwaway data
+    Off,
+}
+
+impl FromStr for Durability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sync" => Ok(Durability::Sync),
+            "periodic" => Ok(Durability::Periodic),
+            "off" => Ok(Durability::Off),
+            other => Err(format!(
+                "unknown durability mode {other:?}, expected sync, periodic or off"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Durability::Sync => "sync",
+            Durability::Periodic => "periodic",
+            Durability::Off => "off",
+        };
+        f.write_str(name)
+    }
+}
+
+// what Database::open does with wal damage that isn't a torn tail (damage at the very end of the
+// log, which is always cut off: a write the crash interrupted)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Recovery {
+    // refuse to open, nothing is lost without someone deciding so (the default)
+    #[default]
+    Strict,
+    // skip damaged records and replay everything else, logging each one. Only ever set for one
+    // start, by hand (server --recover-best-effort)
+    BestEffort,
+}
+
+// everything EngineRuntime::start_with needs. Default = what the engine always did
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub data_dir: PathBuf, // <data_dir>/wal/*.log and <data_dir>/snapshot.json
+    pub segment_size: u64, // a WAL segment is rotated once it would grow past this
+    pub durability: Durability,
+    pub fsync_interval: Duration, // Durability::Periodic only
+    pub snapshot_interval: Duration,
+    pub snapshot_every: u64, // also snapshot after this many writes
+    pub recovery: Recovery,
+}
+
+pub const MIN_SEGMENT_SIZE: u64 = 4096;
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("./fluxdb"),
+            segment_size: 64 * 1024 * 1024,
+            durability: Durability::Sync,
+            fsync_interval: Duration::from_millis(100),
+            snapshot_interval: Duration::from_secs(30),
+            snapshot_every: 1000,
+            recovery: Recovery::Strict,
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.data_dir.as_os_str().is_empty() {
+            return Err("data dir must not be empty".to_string());
+        }
+        if self.data_dir.is_file() {
+            return Err(format!(
+                "data dir {} is a file, not a directory",
+                self.data_dir.display()
+            ));
+        }
+        if self.segment_size < MIN_SEGMENT_SIZE {
+            return Err(format!(
+                "wal segment size {} is below the minimum of {MIN_SEGMENT_SIZE} bytes",
+                self.segment_size
+            ));
+        }
+        if self.durability == Durability::Periodic && self.fsync_interval.is_zero() {
+            return Err("fsync interval must be above 0 with periodic durability".to_string());
+        }
+        if self.snapshot_interval.is_zero() {
+            return Err("snapshot interval must be above 0".to_string());
+        }
+        if self.snapshot_every == 0 {
+            return Err("snapshot every must be above 0 writes".to_string());
+        }
+        Ok(())
+    }
+}
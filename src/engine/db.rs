@@ -1,28 +1,55 @@
 use std::{
+    collections::HashMap,
     io::{self},
-    sync::Arc,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
 };
 
 use serde_json::Value;
 use tokio::sync::RwLock;
+use tracing::{debug, error, instrument, warn};
 
-use crate::store::kv::Store;
+use crate::engine::config::Recovery;
+use crate::engine::info::{FsyncStats, WriterInfo};
+use crate::interface::command::WriteError;
+use crate::metrics::{approx_size, METRICS};
+use crate::store::kv::{Document, Store};
 use crate::store::snapshot::Snapshot;
+use crate::store::wal::replay::{torn_tail, Damage, WalIterator};
 use crate::store::wal::Wal;
-use crate::{event::Event, store::wal::lsn::Lsn};
+use crate::{
+    event::{Event, EventOp},
+    store::wal::lsn::Lsn,
+};
 
 pub struct Database {
     store: Arc<RwLock<Store>>,
     wal: Wal,
     pub fail_next_fsync: bool,
+    approx_bytes: u64, // metrics::approx_size over the whole store, kept up to date per event
+    pub skipped_on_open: u64, // damaged records Recovery::BestEffort replayed past
+    // writes appended to the wal but not applied to the store yet (the fsync batch), newest per
+    // key, a null value for a delete. Versions and conditional checks build on these, or two
+    // writes to one key in a batch would both see the store's version
+    pending: HashMap<String, Document>,
+    pending_flush: bool, // a flush is in the batch, keys not in `pending` are gone
 }
 
 impl Database {
     // Open DB + replay WAL (recovery)
     pub async fn open(path: &str, store: Arc<RwLock<Store>>) -> io::Result<Self> {
-        let wal = Wal::open(path, 64 * 1024 * 1024)?;
+        Self::open_with(Path::new(path), DEFAULT_SEGMENT_SIZE, Recovery::Strict, store).await
+    }
+
+    pub async fn open_with(
+        path: &Path,
+        segment_size: u64,
+        recovery: Recovery,
+        store: Arc<RwLock<Store>>,
+    ) -> io::Result<Self> {
+        let mut wal = Wal::open(path, segment_size)?;
 
-        let snap_path = snapshot_path(&path);
+        let snap_path = snapshot_path(path);
 
         let mut guard = store.write().await; // taking exclusive write lock
         *guard = Store::new(); // replacing the entire guard value
@@ -37,9 +64,20 @@ impl Database {
         };
 
         let mut iter = wal.replay_from(start_lsn)?;
-        while let Some(event) = iter.next_event()? {
-            guard.apply_event(event);
+        let replayed = replay(&mut iter, &mut guard, start_lsn, recovery)?;
+        if let Some((lsn, damage)) = replayed.torn_tail {
+            // the write a crash interrupted. Cut off, or the next appends would land behind it
+            let bytes = wal.truncate_tail(lsn)?;
+            warn!(%lsn, %damage, bytes, "truncated a torn record at the end of the wal");
         }
+        let skipped_on_open = replayed.skipped;
+
+        let approx_bytes = guard
+            .data
+            .iter()
+            .map(|(key, doc)| approx_size(key, &doc.value))
+            .sum();
+        publish_size(guard.data.len(), approx_bytes);
 
         drop(guard); // usually the lock is realased automatically when the scope ends but can use exclusively 
 
@@ -47,6 +85,10 @@ impl Database {
             store,
             wal,
             fail_next_fsync: false,
+            approx_bytes,
+            skipped_on_open,
+            pending: HashMap::new(),
+            pending_flush: false,
         })
     }
 
@@ -69,40 +111,130 @@ impl Database {
     fn execute_pre_durability(&mut self, event: Event) -> io::Result<Event> {
         // 1. WAL durability
         self.wal.append(&event)?;
+        if event.op == EventOp::FlushAll {
+            self.pending.clear();
+            self.pending_flush = true;
+        } else {
+            let doc = Document { value: event.new.clone(), version: event.version };
+            self.pending.insert(event.key.clone(), doc);
+        }
         Ok(event)
     }
 
-    pub async fn execute_post_durability(&mut self, event: Event) -> io::Result<()> {
+    // the key as the next write must see it: the batch's newest write to it, else the store
+    fn latest<'a>(&'a self, store: &'a Store, key: &str) -> Option<&'a Document> {
+        match self.pending.get(key) {
+            Some(doc) if doc.value.is_null() => None,
+            Some(doc) => Some(doc),
+            None if self.pending_flush => None,
+            None => store.get(key),
+        }
+    }
+
+    // the batch failed its fsync and won't be applied, the store is current again
+    pub fn discard_pending(&mut self) {
+        self.pending.clear();
+        self.pending_flush = false;
+    }
+
+    // returns what subscribers should be sent: the event itself, or for a flush one delete per
+    // key that was there
+    #[instrument(level = "debug", skip_all, fields(key = %event.key, version = event.version))]
+    pub async fn execute_post_durability(&mut self, event: Event) -> io::Result<Vec<Event>> {
         // 2. apply to memory (shared store)
-        {
+        let notify = {
             let mut guard = self.store.write().await;
-            guard.apply_event(event.clone());
-        } // write lock released here
-        Ok(())
+            // once its newest write is in the store, a key needs no overlay
+            if event.op == EventOp::FlushAll {
+                self.pending_flush = false;
+            } else if self.pending.get(&event.key).is_some_and(|doc| doc.version == event.version) {
+                self.pending.remove(&event.key);
+            }
+            if event.op == EventOp::FlushAll {
+                let deleted = guard.flush_notifications();
+                guard.apply_event(event);
+                self.approx_bytes = 0;
+                publish_size(0, 0);
+                deleted
+            } else {
+                let old = guard.get(&event.key).map_or(0, |doc| approx_size(&event.key, &doc.value));
+                let new = match &event.new {
+                    Value::Null => 0,
+                    value => approx_size(&event.key, value),
+                };
+                guard.apply_event(event.clone());
+                self.approx_bytes = self.approx_bytes.saturating_sub(old) + new;
+                publish_size(guard.data.len(), self.approx_bytes);
+                vec![event]
+            }
+        }; // write lock released here
+        debug!("applied");
+        Ok(notify)
     }
 
     // Public safe write APIs
+    // (the spans nest under the request's, see WriteCommand)
+    #[instrument(level = "debug", skip_all, fields(key = %key))]
     pub async fn put(&mut self, key: String, value: Value) -> io::Result<Event> {
         let guard = self.store.read().await;
-        let event = guard.put(key, value);
+        let event = Store::put(self.latest(&guard, &key), key, value);
         drop(guard);
         self.execute_pre_durability(event)
     }
 
+    #[instrument(level = "debug", skip(self))]
     pub async fn delete(&mut self, key: &str) -> io::Result<Event> {
         let guard = self.store.read().await;
-        let event = guard.delete(key);
+        let event = Store::delete(self.latest(&guard, key), key);
         drop(guard);
         self.execute_pre_durability(event)
     }
 
+    #[instrument(level = "debug", skip(self, delta))]
     pub async fn patch(&mut self, key: &str, delta: Value) -> io::Result<Event> {
         let guard = self.store.read().await;
-        let event = guard.patch(key, delta);
+        let event = Store::patch(self.latest(&guard, key), key, delta);
         drop(guard);
         self.execute_pre_durability(event)
     }
 
+    #[instrument(level = "debug", skip_all)]
+    pub async fn flush_all(&mut self) -> io::Result<Event> {
+        let event = Store::flush_all();
+        self.execute_pre_durability(event)
+    }
+
+    // optimistic concurrency check used by conditional writes (If-Match style)
+    pub async fn check_version(&self, key: &str, expected: Option<u64>) -> Result<(), WriteError> {
+        let Some(expected) = expected else {
+            return Ok(());
+        };
+
+        let guard = self.store.read().await;
+        let actual = self.latest(&guard, key).map(|doc| doc.version).unwrap_or(0);
+        drop(guard);
+
+        if actual != expected {
+            return Err(WriteError::VersionConflict { expected, actual });
+        }
+        Ok(())
+    }
+
+    // the store / wal part of WriterInfo, the write actor passes in its own counters
+    pub async fn info(&self, writes_since_snapshot: u64, fsync: FsyncStats) -> io::Result<WriterInfo> {
+        let lsn = self.wal.current_lsn()?;
+        let (wal_segments, wal_bytes) = self.wal.disk_usage()?;
+        let keys = self.store.read().await.data.len() as u64;
+        Ok(WriterInfo {
+            keys,
+            lsn,
+            wal_segments,
+            wal_bytes,
+            writes_since_snapshot,
+            fsync,
+        })
+    }
+
     pub fn fsync_wal(&mut self) -> io::Result<()> {
         if self.fail_next_fsync {
             self.fail_next_fsync = false;
@@ -115,6 +247,85 @@ impl Database {
     }
 }
 
-fn snapshot_path(wal_path: &str) -> String {
-    format!("{wal_path}.snapshot")
+const DEFAULT_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+// what replay leaves to its caller
+pub struct Replayed {
+    pub torn_tail: Option<(Lsn, Damage)>, // open cuts it off, read-only readers leave it
+    pub skipped: u64, // damaged records Recovery::BestEffort replayed past
+}
+
+// applies the wal from `start` to `store` with the rules open uses, so every reader of a data dir
+// agrees on what it holds. Damage is only known to be a torn tail once nothing good comes after
+// it, so it waits here until a good record (mid-log: fatal, or skipped) or the end of the wal
+pub fn replay(iter: &mut WalIterator, store: &mut Store, start: Lsn, recovery: Recovery) -> io::Result<Replayed> {
+    let mut last_good = None;
+    let mut damaged: Vec<(Lsn, Damage)> = vec![];
+    let mut skipped = 0;
+    while let Some(record) = iter.next_record()? {
+        let event = match record.entry {
+            Ok(event) => event,
+            Err(damage) => {
+                damaged.push((record.lsn, damage));
+                continue;
+            }
+        };
+        if !damaged.is_empty() {
+            if recovery == Recovery::Strict {
+                let (lsn, damage) = &damaged[0];
+                return Err(mid_log_corruption(*lsn, damage, last_good, start, "good records follow it"));
+            }
+            for (lsn, damage) in damaged.drain(..) {
+                error!(%lsn, %damage, "best-effort recovery: skipping damaged wal record, its write is lost");
+                skipped += 1;
+            }
+        }
+        last_good = Some(record.lsn);
+        store.apply_event(event);
+    }
+
+    if torn_tail(&damaged).is_some() {
+        return Ok(Replayed {
+            torn_tail: damaged.pop(),
+            skipped,
+        });
+    }
+    if let Some((lsn, damage)) = damaged.first() {
+        if recovery == Recovery::Strict {
+            let why = "it is not one unfinished append at the end of the wal";
+            return Err(mid_log_corruption(*lsn, damage, last_good, start, why));
+        }
+        for (lsn, damage) in &damaged {
+            error!(%lsn, %damage, "best-effort recovery: skipping damaged wal record, its write is lost");
+        }
+        skipped += damaged.len() as u64;
+    }
+    Ok(Replayed { torn_tail: None, skipped })
+}
+
+// `why` it is not a torn tail
+fn mid_log_corruption(lsn: Lsn, damage: &Damage, last_good: Option<Lsn>, start: Lsn, why: &str) -> io::Error {
+    let last_good = match last_good {
+        Some(lsn) => format!("Last good record at {lsn}"),
+        None => format!("No good record since replay started at {start}"),
+    };
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "wal corruption in segment {} at offset {}: {damage}. {last_good}. Not a torn tail: {why}. \
+             Inspect it with fluxdb-wal check or fluxdb-fsck, or start with --recover-best-effort to \
+             skip damaged records and lose their writes",
+            lsn.segment, lsn.offset
+        ),
+    )
+}
+
+// the snapshot lives next to the wal dir, so every data dir is self contained
+pub fn snapshot_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("snapshot.json")
+}
+
+fn publish_size(keys: usize, approx_bytes: u64) {
+    METRICS.keys.store(keys as u64, Ordering::Relaxed);
+    METRICS.memory_bytes.store(approx_bytes, Ordering::Relaxed);
 }
@@ -0,0 +1,329 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    engine::db::snapshot_path,
+    event::EventOp,
+    store::{
+        kv::Store,
+        snapshot::Snapshot,
+        wal::{
+            lsn::Lsn,
+            replay::{segment_ids, torn_tail, Damage, WalIterator},
+        },
+    },
+};
+
+// offline consistency check of a data dir (fluxdb-fsck). The server must not be running on it.
+//
+// Database::open cuts a torn tail off (the crash cut the last write short) and refuses to start
+// on damage anywhere else. check() draws the same line (replay::torn_tail): damage counts as a torn
+// tail only when it is a single unfinished append with no good record after it. Everything else is
+// mid-log corruption, which is reported and never touched.
+
+pub const QUARANTINE: &str = "quarantine";
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub segments: Vec<u64>,
+    pub records: u64,
+    pub snapshot_lsn: Option<Lsn>,
+    pub problems: Vec<Problem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    NoWal,
+    // segment ids `from..=to` are missing, replay would fail opening the first of them
+    MissingSegments { from: u64, to: u64 },
+    // repairable: cut the segment back to lsn.offset
+    TornTail { lsn: Lsn, bytes: u64 },
+    // damage with good records after it, `following` of them in the same segment. The server
+    // won't start on it without --recover-best-effort
+    MidLogCorruption { lsn: Lsn, damage: Damage, following: u64 },
+    // snapshot.json unreadable or not matching the wal. `replayable`: the wal is whole from 0:0,
+    // so the store can be rebuilt without it and it can be quarantined
+    BadSnapshot { error: String, replayable: bool },
+    VersionRegression { key: String, lsn: Lsn, version: u64, previous: u64 },
+    // leftovers of an interrupted write (snapshot.json.tmp) or files that aren't segments
+    StrayFile { path: PathBuf },
+}
+
+impl Problem {
+    pub fn repairable(&self) -> bool {
+        match self {
+            Problem::TornTail { .. } | Problem::StrayFile { .. } => true,
+            Problem::BadSnapshot { replayable, .. } => *replayable,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::NoWal => write!(f, "no wal segments"),
+            Problem::MissingSegments { from, to } if from == to => write!(f, "wal segment {from} is missing"),
+            Problem::MissingSegments { from, to } => write!(f, "wal segments {from} to {to} are missing"),
+            Problem::TornTail { lsn, bytes } => {
+                write!(f, "torn tail at {lsn}: {bytes} bytes of an unfinished write")
+            }
+            Problem::MidLogCorruption { lsn, damage, following } => {
+                write!(f, "mid-log corruption at {lsn}: {damage}, {following} good records follow it in the segment")
+            }
+            Problem::BadSnapshot { error, .. } => write!(f, "snapshot: {error}"),
+            Problem::VersionRegression {
+                key,
+                lsn,
+                version,
+                previous,
+            } => write!(f, "version regression at {lsn}: {key} goes from v{previous} to v{version}"),
+            Problem::StrayFile { path } => write!(f, "stray file {}", path.display()),
+        }
+    }
+}
+
+struct Found {
+    lsn: Lsn,
+    damage: Damage,
+    good_before: u64, // good records read before it, anywhere
+    segment_good_before: u64,
+}
+
+pub fn check(data_dir: &Path) -> Result<Report, String> {
+    if !data_dir.is_dir() {
+        return Err(format!("{} is not a directory", data_dir.display()));
+    }
+    let mut report = Report::default();
+    let ids = match segment_ids(data_dir) {
+        Ok(ids) => ids,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(format!("wal: {e}")),
+    };
+    report.segments = ids.clone();
+    report.problems.extend(stray_files(data_dir).map_err(|e| format!("listing files: {e}"))?);
+    let Some(&first) = ids.first() else {
+        report.problems.push(Problem::NoWal);
+        return Ok(report);
+    };
+    for pair in ids.windows(2) {
+        if pair[1] != pair[0] + 1 {
+            report.problems.push(Problem::MissingSegments { from: pair[0] + 1, to: pair[1] - 1 });
+        }
+    }
+
+    let snapshot = match fs::read(snapshot_path(data_dir)) {
+        Ok(bytes) => Some(serde_json::from_slice::<Snapshot>(&bytes).map_err(|e| format!("not a snapshot ({e})"))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => Some(Err(e.to_string())),
+    };
+    let snapshot_lsn = snapshot.as_ref().and_then(|s| s.as_ref().ok()).map(|s| s.lsn);
+    report.snapshot_lsn = snapshot_lsn;
+    // the store as of the snapshot lsn, replayed from 0:0, to hold the snapshot against
+    let mut at_snapshot: Option<Store> = None;
+    let mut snapshot_on_record = false;
+
+    // versions are followed through every record, each write must move its key forward
+    let mut store = Store::new();
+    let mut found: Vec<Found> = vec![];
+    let mut good = 0;
+    let mut segment_good = HashMap::new(); // id -> good records in it
+    for &id in &ids {
+        let mut good_in_segment = 0;
+        let mut wal = WalIterator::segment(data_dir, id).map_err(|e| format!("segment {id}: {e}"))?;
+        while let Some(record) = wal.next_record().map_err(|e| format!("segment {id}: {e}"))? {
+            if Some(record.lsn) == snapshot_lsn {
+                snapshot_on_record = true;
+                at_snapshot.get_or_insert_with(|| store.clone());
+            }
+            let event = match record.entry {
+                Ok(event) => event,
+                Err(damage) => {
+                    found.push(Found {
+                        lsn: record.lsn,
+                        damage,
+                        good_before: good,
+                        segment_good_before: good_in_segment,
+                    });
+                    continue;
+                }
+            };
+            good += 1;
+            good_in_segment += 1;
+            if event.op == EventOp::Write {
+                if let Some(previous) = store.get(&event.key).map(|doc| doc.version) {
+                    if event.version <= previous {
+                        report.problems.push(Problem::VersionRegression {
+                            key: event.key.clone(),
+                            lsn: record.lsn,
+                            version: event.version,
+                            previous,
+                        });
+                    }
+                }
+            }
+            store.apply_event(event);
+        }
+        segment_good.insert(id, good_in_segment);
+        // a snapshot taken right at the end of a segment (or of the wal)
+        if snapshot_lsn.is_some_and(|lsn| lsn.segment == id && at_snapshot.is_none()) {
+            let end = fs::metadata(data_dir.join("wal").join(format!("{id}.log")))
+                .map_err(|e| format!("segment {id}: {e}"))?
+                .len();
+            if snapshot_lsn.is_some_and(|lsn| lsn.offset == end) {
+                snapshot_on_record = true;
+                at_snapshot = Some(store.clone());
+            }
+        }
+    }
+    report.records = good;
+
+    // the damage after which nothing good was read is a torn tail if torn_tail says so, the same
+    // rule Database::open cuts it off by. Everything else is corruption
+    let trailing: Vec<(Lsn, Damage)> = found
+        .iter()
+        .filter(|f| f.good_before == good)
+        .map(|f| (f.lsn, f.damage.clone()))
+        .collect();
+    let torn = torn_tail(&trailing);
+    for f in &found {
+        if Some(f.lsn) == torn {
+            let segment = f.lsn.segment;
+            let size = fs::metadata(data_dir.join("wal").join(format!("{segment}.log")))
+                .map_err(|e| format!("segment {segment}: {e}"))?
+                .len();
+            report.problems.push(Problem::TornTail { lsn: f.lsn, bytes: size - f.lsn.offset });
+            continue;
+        }
+        report.problems.push(Problem::MidLogCorruption {
+            lsn: f.lsn,
+            damage: f.damage.clone(),
+            following: segment_good[&f.lsn.segment] - f.segment_good_before,
+        });
+    }
+
+    // replayable: every record from 0:0 reads, so the store can be rebuilt without a snapshot
+    let mid_log = report.problems.iter().any(|p| {
+        matches!(p, Problem::MidLogCorruption { .. } | Problem::MissingSegments { .. })
+    });
+    let replayable = first == 0 && !mid_log;
+    let snapshot_error = match snapshot {
+        None => None,
+        Some(Err(e)) => Some(e),
+        Some(Ok(snapshot)) if !ids.contains(&snapshot.lsn.segment) => {
+            Some(format!("points at {} in a segment that doesn't exist", snapshot.lsn))
+        }
+        Some(Ok(snapshot)) if !snapshot_on_record => {
+            Some(format!("points at {}, which is not the start of a record", snapshot.lsn))
+        }
+        Some(Ok(snapshot)) => match at_snapshot {
+            // with older segments gone there is nothing to hold it against
+            Some(replayed) if first == 0 => snapshot_mismatch(&snapshot, &replayed),
+            _ => None,
+        },
+    };
+    if let Some(error) = snapshot_error {
+        report.problems.push(Problem::BadSnapshot { error, replayable });
+    }
+    Ok(report)
+}
+
+fn snapshot_mismatch(snapshot: &Snapshot, replayed: &Store) -> Option<String> {
+    let keys: BTreeSet<&String> = snapshot.data.keys().chain(replayed.data.keys()).collect();
+    let differing: Vec<&String> = keys
+        .into_iter()
+        .filter(|key| {
+            match (snapshot.data.get(*key), replayed.data.get(*key)) {
+                (Some(a), Some(b)) => a.version != b.version || a.value != b.value,
+                _ => true,
+            }
+        })
+        .collect();
+    let first = differing.first()?;
+    Some(format!(
+        "{} keys differ from replaying the wal up to {} (first: {first})",
+        differing.len(),
+        snapshot.lsn
+    ))
+}
+
+fn stray_files(data_dir: &Path) -> io::Result<Vec<Problem>> {
+    let mut stray = vec![];
+    let tmp = snapshot_path(data_dir).with_extension("json.tmp");
+    if tmp.exists() {
+        stray.push(Problem::StrayFile { path: tmp });
+    }
+    let wal = data_dir.join("wal");
+    if wal.is_dir() {
+        for entry in fs::read_dir(&wal)? {
+            let name = entry?.file_name();
+            let segment = name
+                .to_string_lossy()
+                .strip_suffix(".log")
+                .is_some_and(|id| id.parse::<u64>().is_ok());
+            if !segment {
+                stray.push(Problem::StrayFile { path: wal.join(name) });
+            }
+        }
+    }
+    stray.sort_by_key(|p| p.to_string());
+    Ok(stray)
+}
+
+// fixes what is repairable, returns what was done. Originals go to
+// <data_dir>/quarantine/<unix_ms>/ first: a whole copy of a segment before it is truncated, the
+// file itself for stray files and a bad snapshot
+pub fn repair(data_dir: &Path, report: &Report) -> Result<Vec<String>, String> {
+    let repairable: Vec<&Problem> = report.problems.iter().filter(|p| p.repairable()).collect();
+    if repairable.is_empty() {
+        return Ok(vec![]);
+    }
+    let unix_ms = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let quarantine = data_dir.join(QUARANTINE).join(unix_ms.to_string());
+    fs::create_dir_all(&quarantine).map_err(|e| format!("quarantine dir: {e}"))?;
+
+    let mut done = vec![];
+    for problem in repairable {
+        match problem {
+            Problem::TornTail { lsn, bytes } => {
+                let path = data_dir.join("wal").join(format!("{}.log", lsn.segment));
+                let kept = quarantine.join(format!("wal-{}.log", lsn.segment));
+                copy_synced(&path, &kept).map_err(|e| format!("quarantine {}: {e}", path.display()))?;
+                let file = OpenOptions::new().write(true).open(&path).map_err(|e| e.to_string())?;
+                file.set_len(lsn.offset).map_err(|e| format!("truncate {}: {e}", path.display()))?;
+                file.sync_all().map_err(|e| e.to_string())?;
+                done.push(format!("truncated {} to {} ({bytes} bytes cut, original in {})", path.display(), lsn.offset, kept.display()));
+            }
+            Problem::BadSnapshot { .. } => {
+                let path = snapshot_path(data_dir);
+                let moved = quarantine.join("snapshot.json");
+                fs::rename(&path, &moved).map_err(|e| format!("quarantine snapshot: {e}"))?;
+                done.push(format!("moved {} to {}, the next start replays the whole wal", path.display(), moved.display()));
+            }
+            Problem::StrayFile { path } => {
+                let name = path.file_name().unwrap_or_default();
+                let moved = quarantine.join(name);
+                fs::rename(path, &moved).map_err(|e| format!("quarantine {}: {e}", path.display()))?;
+                done.push(format!("moved {} to {}", path.display(), moved.display()));
+            }
+            _ => {}
+        }
+    }
+    for dir in [data_dir.to_path_buf(), data_dir.join("wal"), quarantine] {
+        File::open(&dir).and_then(|d| d.sync_all()).map_err(|e| format!("fsync {}: {e}", dir.display()))?;
+    }
+    Ok(done)
+}
+
+fn copy_synced(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to)?;
+    File::open(to)?.sync_all()
+}
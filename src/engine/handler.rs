@@ -1,10 +1,13 @@
+use std::{path::PathBuf, time::Instant};
+
 use tokio::sync::mpsc;
 
 use crate::{
-    engine::{notify_actor::NotifyCommand, snapshot_actor::SnapshotActorCommand}, event::Event, interface::command::{ReadCommand, WriteCommand}, store::kv::Document
+    engine::{backup::Manifest, info::EngineInfo, notify_actor::NotifyCommand, snapshot_actor::SnapshotActorCommand}, event::Event, interface::command::{ReadCommand, WriteCommand, WriteError}, store::kv::Document
 };
 use serde_json::Value;
 use tokio::sync::oneshot;
+use tracing::Span;
 
 #[derive(Clone)]
 pub struct EngineHandle {
@@ -12,6 +15,7 @@ pub struct EngineHandle {
     write_tx: mpsc::Sender<WriteCommand>,
     snap_tx: mpsc::Sender<SnapshotActorCommand>,
     notify_tx: mpsc::Sender<NotifyCommand>,
+    started: Instant,
 }
 
 impl EngineHandle {
@@ -26,56 +30,132 @@ impl EngineHandle {
             write_tx,
             snap_tx,
             notify_tx,
+            started: Instant::now(),
         }
     }
 
     pub async fn set(&self, key: String, value: Value) -> Result<(), String> {
+        Ok(self.set_if_version(key, value, None).await?)
+    }
+
+    pub async fn patch(&self, key: String, delta: Value) -> Result<(), String> {
+        Ok(self.patch_if_version(key, delta, None).await?)
+    }
+
+    pub async fn delete(&self, key: String) -> Result<(), String> {
+        Ok(self.delete_if_version(key, None).await?)
+    }
+
+    // conditional writes: rejected with VersionConflict unless the current Document.version
+    // equals expected_version (0 means "key must not exist"). None = unconditional
+    pub async fn set_if_version(
+        &self,
+        key: String,
+        value: Value,
+        expected_version: Option<u64>,
+    ) -> Result<(), WriteError> {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.write_tx
             .send(WriteCommand::Set {
                 key,
                 value,
+                expected_version,
+                span: Span::current(),
                 resp: resp_tx,
             })
             .await
-            .map_err(|_| "writer dropped".to_string())?;
-        resp_rx.await.map_err(|_| "writer dropped".to_string())?
+            .map_err(|_| writer_dropped())?;
+        resp_rx.await.map_err(|_| writer_dropped())?
     }
 
-    pub async fn patch(&self, key: String, delta: Value) -> Result<(), String> {
+    pub async fn patch_if_version(
+        &self,
+        key: String,
+        delta: Value,
+        expected_version: Option<u64>,
+    ) -> Result<(), WriteError> {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.write_tx
             .send(WriteCommand::Patch {
                 key,
                 delta,
+                expected_version,
+                span: Span::current(),
                 resp: resp_tx,
             })
             .await
-            .map_err(|_| "writer dropped".to_string())?;
+            .map_err(|_| writer_dropped())?;
 
-        resp_rx.await.map_err(|_| "writer dropped".to_string())?
+        resp_rx.await.map_err(|_| writer_dropped())?
     }
 
-    pub async fn delete(&self, key: String) -> Result<(), String> {
+    pub async fn delete_if_version(
+        &self,
+        key: String,
+        expected_version: Option<u64>,
+    ) -> Result<(), WriteError> {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.write_tx
-            .send(WriteCommand::Del { key, resp: resp_tx })
+            .send(WriteCommand::Del {
+                key,
+                expected_version,
+                span: Span::current(),
+                resp: resp_tx,
+            })
             .await
-            .map_err(|_| "writer dropped".to_string())?;
+            .map_err(|_| writer_dropped())?;
 
-        resp_rx.await.map_err(|_| "writer dropped".to_string())?
+        resp_rx.await.map_err(|_| writer_dropped())?
     }
 
     pub async fn get(&self, key: String) -> Result<Option<Document>, String> {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.read_tx
-            .send(ReadCommand::Get { key, resp: resp_tx })
+            .send(ReadCommand::Get {
+                key,
+                span: Span::current(),
+                resp: resp_tx,
+            })
             .await
             .map_err(|_| "writer dropped".to_string())?;
 
         resp_rx.await.map_err(|_| "writer dropped".to_string())
     }
 
+    pub async fn keys(&self, pattern: Option<String>) -> Result<Vec<String>, String> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.read_tx
+            .send(ReadCommand::Keys { pattern, resp: resp_tx })
+            .await
+            .map_err(|_| "reader dropped".to_string())?;
+
+        resp_rx.await.map_err(|_| "reader dropped".to_string())
+    }
+
+    pub async fn db_size(&self) -> Result<u64, String> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.read_tx
+            .send(ReadCommand::DbSize { resp: resp_tx })
+            .await
+            .map_err(|_| "reader dropped".to_string())?;
+
+        resp_rx.await.map_err(|_| "reader dropped".to_string())
+    }
+
+    // deletes every key with one durable wal record; subscribers get a delete per key
+    pub async fn flush_all(&self) -> Result<(), String> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.write_tx
+            .send(WriteCommand::FlushAll {
+                span: Span::current(),
+                resp: resp_tx,
+            })
+            .await
+            .map_err(|_| writer_dropped())?;
+
+        Ok(resp_rx.await.map_err(|_| writer_dropped())??)
+    }
+
     pub async fn snapshot(&self) -> Result<(), String> {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.snap_tx
@@ -85,6 +165,16 @@ impl EngineHandle {
         resp_rx.await.map_err(|_| "writer dropped".to_string())?
     }
 
+    // a fresh snapshot plus the wal after it, copied into `dest` while writes go on
+    pub async fn backup(&self, dest: PathBuf) -> Result<Manifest, String> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.snap_tx
+            .send(SnapshotActorCommand::Backup { dest, resp: resp_tx })
+            .await
+            .map_err(|_| "snapshot actor dropped".to_string())?;
+        resp_rx.await.map_err(|_| "snapshot actor dropped".to_string())?
+    }
+
     pub async fn subscribe(&self, key: String) -> Result<mpsc::Receiver<Event>, String> {
         let (resp_tx, resp_rx) = oneshot::channel();
 
@@ -98,6 +188,59 @@ impl EngineHandle {
             .map_err(|_| "notify actor dropped".to_string())
     }
 
+    // like subscribe, but for every key matching a glob pattern (events arrive with their key)
+    pub async fn subscribe_pattern(&self, pattern: String) -> Result<mpsc::Receiver<Event>, String> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        self.notify_tx
+            .send(NotifyCommand::SubscribePattern { pattern, resp: resp_tx })
+            .await
+            .map_err(|_| "notify actor dropped".to_string())?;
+
+        resp_rx
+            .await
+            .map_err(|_| "notify actor dropped".to_string())
+    }
+
+    // asks the writer, the snapshot actor and the notify actor at the same time
+    pub async fn info(&self) -> Result<EngineInfo, String> {
+        let (writer_tx, writer_rx) = oneshot::channel();
+        let (snap_tx, snap_rx) = oneshot::channel();
+        let (notify_tx, notify_rx) = oneshot::channel();
+        self.write_tx
+            .send(WriteCommand::Info { resp: writer_tx })
+            .await
+            .map_err(|_| "writer dropped".to_string())?;
+        self.snap_tx
+            .send(SnapshotActorCommand::Info { resp: snap_tx })
+            .await
+            .map_err(|_| "snapshot actor dropped".to_string())?;
+        self.notify_tx
+            .send(NotifyCommand::Info { resp: notify_tx })
+            .await
+            .map_err(|_| "notify actor dropped".to_string())?;
+
+        let (writer, last_snapshot, subscriptions) = tokio::join!(writer_rx, snap_rx, notify_rx);
+        let writer = writer.map_err(|_| "writer dropped".to_string())??;
+        let last_snapshot = last_snapshot.map_err(|_| "snapshot actor dropped".to_string())?;
+        let (subscriptions, pattern_subscriptions) =
+            subscriptions.map_err(|_| "notify actor dropped".to_string())?;
+
+        Ok(EngineInfo {
+            uptime_secs: self.started.elapsed().as_secs(),
+            keys: writer.keys,
+            lsn: writer.lsn,
+            active_segment: writer.lsn.segment,
+            wal_segments: writer.wal_segments,
+            wal_bytes: writer.wal_bytes,
+            writes_since_snapshot: writer.writes_since_snapshot,
+            fsync: writer.fsync,
+            last_snapshot,
+            subscriptions,
+            pattern_subscriptions,
+        })
+    }
+
     pub async fn inject_failure(&self) {
         let (resp_tx, resp_rx) = oneshot::channel();
         let _ = self
@@ -107,3 +250,7 @@ impl EngineHandle {
         let _ = resp_rx.await;
     }
 }
+
+fn writer_dropped() -> WriteError {
+    WriteError::Failed("writer dropped".to_string())
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use crate::store::wal::lsn::Lsn;
+
+// what EngineHandle::info reports. Each actor answers for what it owns (see the Info variants
+// of WriteCommand, SnapshotActorCommand and NotifyCommand), the handle puts it together
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EngineInfo {
+    pub uptime_secs: u64,
+    pub keys: u64,
+    pub lsn: Lsn, // end of the wal, where the next record goes
+    pub active_segment: u64,
+    pub wal_segments: u64,
+    pub wal_bytes: u64, // on disk, every segment still around
+    pub writes_since_snapshot: u64, // applied since the last snapshot payload was taken
+    pub fsync: FsyncStats,
+    pub last_snapshot: Option<SnapshotInfo>, // None until the first snapshot of this process
+    pub subscriptions: u64, // key subscriptions
+    pub pattern_subscriptions: u64,
+}
+
+// the write actor's part
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriterInfo {
+    pub keys: u64,
+    pub lsn: Lsn,
+    pub wal_segments: u64,
+    pub wal_bytes: u64,
+    pub writes_since_snapshot: u64,
+    pub fsync: FsyncStats,
+}
+
+// every wal fsync of this process, batch and periodic
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FsyncStats {
+    pub count: u64,
+    pub failures: u64,
+    pub total_us: u64,
+    pub last_us: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub lsn: Lsn,
+    pub unix_ms: u64, // when it was written
+}
@@ -4,6 +4,10 @@ mod read_actor;
 mod snapshot_actor;
 mod notify_actor;
 
+pub mod backup;
+pub mod config;
 pub mod db;
+pub mod fsck;
 pub mod handler;
+pub mod info;
 pub mod runtime;
\ No newline at end of file
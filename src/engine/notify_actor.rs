@@ -1,4 +1,5 @@
 use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, Span};
 
 use crate::{event::Event, reactivity::reactivity::Reactivity};
 
@@ -7,8 +8,18 @@ pub enum NotifyCommand {
         key: String,
         resp: oneshot::Sender<mpsc::Receiver<Event>>,
     },
+    SubscribePattern {
+        pattern: String,
+        resp: oneshot::Sender<mpsc::Receiver<Event>>,
+    },
+    // usually one event, a flush sends a delete for every key
     Dispatch {
-        event: Event,
+        events: Vec<Event>,
+        span: Span, // the write's, see WriteCommand
+    },
+    // open (key, pattern) subscriptions
+    Info {
+        resp: oneshot::Sender<(u64, u64)>,
     },
 }
 
@@ -32,8 +43,20 @@ impl NotifyActor {
                     let sub = self.reactivity.subscribe(&key);
                     let _ = resp.send(sub);
                 }
-                NotifyCommand::Dispatch { event } => {
-                    self.reactivity.dispatch_event(&event);
+                NotifyCommand::SubscribePattern { pattern, resp } => {
+                    let sub = self.reactivity.subscribe_pattern(&pattern);
+                    let _ = resp.send(sub);
+                }
+                NotifyCommand::Dispatch { events, span } => {
+                    span.in_scope(|| {
+                        for event in &events {
+                            self.reactivity.dispatch_event(event);
+                            debug!(key = event.key, version = event.version, "notified");
+                        }
+                    });
+                }
+                NotifyCommand::Info { resp } => {
+                    let _ = resp.send(self.reactivity.open_subscriptions());
                 }
             }
         }
@@ -1,7 +1,10 @@
 use tokio::sync::oneshot;
-use crate::event::Event;
+use tracing::Span;
+
+use crate::{event::Event, interface::command::WriteError};
 
 pub struct PendingWrite {
     pub event: Event,
-    pub resp: oneshot::Sender<Result<(), String>>,
-}
\ No newline at end of file
+    pub span: Span, // the request's, see WriteCommand
+    pub resp: oneshot::Sender<Result<(), WriteError>>,
+}
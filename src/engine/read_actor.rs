@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use tokio::sync::{RwLock, mpsc};
+use tracing::debug;
 
 use crate::{interface::command::ReadCommand, store::kv::Store};
 
@@ -10,11 +11,21 @@ pub async fn read_actor(
 ) {
     while let Some(cmd) = read_rx.recv().await {
         match cmd {
-            ReadCommand::Get { key, resp } => {
+            ReadCommand::Get { key, span, resp } => {
                 let guard = shared_store.read().await;
                 let out = guard.get(&key).cloned();
+                span.in_scope(|| debug!(key, found = out.is_some(), "read"));
                 let _ = resp.send(out);
             }
+            ReadCommand::Keys { pattern, resp } => {
+                let guard = shared_store.read().await;
+                let out = guard.keys(pattern.as_deref());
+                let _ = resp.send(out);
+            }
+            ReadCommand::DbSize { resp } => {
+                let guard = shared_store.read().await;
+                let _ = resp.send(guard.data.len() as u64);
+            }
         }
     }
 }
@@ -1,9 +1,12 @@
-use std::{sync::Arc, time::Duration};
+use std::{future::Future, sync::Arc};
 
 use tokio::sync::{RwLock, mpsc};
+use tracing::warn;
 
 use crate::{
     engine::{
+        config::EngineConfig,
+        db::Database,
         handler::EngineHandle,
         notify_actor::{NotifyActor, NotifyCommand},
         read_actor::read_actor,
@@ -19,27 +22,65 @@ pub struct EngineRuntime {
 }
 
 impl EngineRuntime {
+    // ./fluxdb with the default settings
     pub fn start() -> Self {
+        Self::start_with(EngineConfig::default())
+    }
+
+    // expects a validated config (EngineConfig::validate). The database is opened by the write
+    // actor, which panics if that fails (tests, embedding); servers use open
+    pub fn start_with(config: EngineConfig) -> Self {
+        let shared_store = Arc::new(RwLock::new(Store::new()));
+        let (data_dir, store) = (config.data_dir.clone(), shared_store.clone());
+        let (segment_size, recovery) = (config.segment_size, config.recovery);
+        let db = async move {
+            Database::open_with(&data_dir, segment_size, recovery, store)
+                .await
+                .expect("failed to open database")
+        };
+        Self::spawn(config, shared_store, db)
+    }
+
+    // opens the database (wal replay included) before anything runs, so a data dir that can't
+    // be recovered is an error here instead of a dead write actor
+    pub async fn open(config: EngineConfig) -> Result<Self, String> {
+        let shared_store = Arc::new(RwLock::new(Store::new()));
+        let db = Database::open_with(&config.data_dir, config.segment_size, config.recovery, shared_store.clone())
+            .await
+            .map_err(|e| format!("opening {}: {e}", config.data_dir.display()))?;
+        let skipped = db.skipped_on_open;
+
+        let runtime = Self::spawn(config, shared_store, async { db });
+        if skipped > 0 {
+            // the next start begins after the damage instead of needing the flag again
+            runtime.handle.snapshot().await?;
+            warn!(skipped, "best-effort recovery skipped damaged wal records, snapshot taken past them");
+        }
+        Ok(runtime)
+    }
+
+    fn spawn(
+        config: EngineConfig,
+        shared_store: Arc<RwLock<Store>>,
+        db: impl Future<Output = Database> + Send + 'static,
+    ) -> Self {
         // initializing all channels
         let (read_tx, read_rx) = mpsc::channel::<ReadCommand>(32);
         let (write_tx, write_rx) = mpsc::channel::<WriteCommand>(32); // channel for writing and updating, is generally slower.
         let (snap_tx, snap_rx) = mpsc::channel::<SnapshotActorCommand>(32);
         let (notify_tx, notify_rx) = mpsc::channel::<NotifyCommand>(32);
 
-        let shared_store = Arc::new(RwLock::new(Store::new()));
-
         // spawning all tasks
         tokio::spawn(read_actor(read_rx, shared_store.clone())); // cloned the pointer 
-        tokio::spawn(write_actor(
-            write_rx,
-            shared_store,
-            snap_tx.clone(),
-            notify_tx.clone(),
-        )); // moved the ownership of shared_store 
+        let (writer_snap_tx, writer_notify_tx, writer_config) = (snap_tx.clone(), notify_tx.clone(), config.clone());
+        tokio::spawn(async move {
+            write_actor(write_rx, db.await, writer_snap_tx, writer_notify_tx, writer_config).await
+        });
         tokio::spawn(snapshot_actor(
             snap_rx,
             write_tx.clone(),
-            Duration::from_secs(30),
+            config.data_dir.clone(),
+            config.snapshot_interval,
         ));
 
         // in the end both pointing to same thing
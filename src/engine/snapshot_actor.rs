@@ -1,45 +1,85 @@
 use std::{
     fs::{File, rename},
     io::Write,
-    path::Path,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::SystemTime,
 };
 
 use tokio::{
     sync::{mpsc, oneshot},
-    time::{Duration, interval},
+    time::{Duration, Instant, interval},
 };
 
-use crate::{interface::command::WriteCommand, store::snapshot::Snapshot};
+use tracing::{debug, warn};
+
+use crate::{
+    engine::{
+        backup::{self, Manifest},
+        db::snapshot_path,
+        info::SnapshotInfo,
+    },
+    interface::command::WriteCommand,
+    metrics::METRICS,
+    store::{snapshot::Snapshot, wal::lsn::Lsn},
+};
 
 pub enum SnapshotActorCommand {
     TriggerNow,
     TriggerNowWithAck {
         resp: oneshot::Sender<Result<(), String>>,
     },
+    // the last snapshot this actor wrote
+    Info {
+        resp: oneshot::Sender<Option<SnapshotInfo>>,
+    },
+    // a fresh checkpoint plus the wal after it, copied into `dest` (see engine::backup)
+    Backup {
+        dest: PathBuf,
+        resp: oneshot::Sender<Result<Manifest, String>>,
+    },
 }
 
 pub async fn snapshot_actor(
     mut rx: mpsc::Receiver<SnapshotActorCommand>,
     write_tx: mpsc::Sender<WriteCommand>,
+    data_dir: PathBuf,
     period: Duration,
 ) {
+    let path = snapshot_path(&data_dir);
     let mut tick = interval(period);
+    let mut last: Option<SnapshotInfo> = None;
 
     loop {
         tokio::select! {
             _ = tick.tick() => {
-                let _ = run_snapshot_cycle(&write_tx).await;
+                last = run_snapshot_cycle(&write_tx, &path).await.ok().or(last);
             }
 
             cmd = rx.recv() => {
                 match cmd {
                     Some(SnapshotActorCommand::TriggerNow) => {
-                        let _ = run_snapshot_cycle(&write_tx).await;
+                        last = run_snapshot_cycle(&write_tx, &path).await.ok().or(last);
                     }
 
                     Some(SnapshotActorCommand::TriggerNowWithAck { resp }) => {
-                        let result = run_snapshot_cycle(&write_tx).await;
-                        let _ = resp.send(result);
+                        let result = run_snapshot_cycle(&write_tx, &path).await;
+                        let _ = resp.send(result.map(|info| {
+                            last = Some(info);
+                        }));
+                    }
+
+                    Some(SnapshotActorCommand::Info { resp }) => {
+                        let _ = resp.send(last);
+                    }
+
+                    // runs here so no other snapshot can happen while the wal is being copied
+                    Some(SnapshotActorCommand::Backup { dest, resp }) => {
+                        let result = run_backup(&write_tx, &path, &data_dir, dest).await;
+                        let _ = resp.send(result.map(|(info, manifest)| {
+                            last = Some(info);
+                            manifest
+                        }));
                     }
 
                     None => break,
@@ -49,10 +89,72 @@ pub async fn snapshot_actor(
     }
 }
 
-async fn run_snapshot_cycle(write_tx: &mpsc::Sender<WriteCommand>) -> Result<(), String> {
-    let snapshot = request_snapshot_payload(write_tx).await?;
-    checkpoint_durability("flux.wal", &snapshot)?;
-    Ok(())
+async fn run_snapshot_cycle(
+    write_tx: &mpsc::Sender<WriteCommand>,
+    path: &Path,
+) -> Result<SnapshotInfo, String> {
+    take_snapshot(write_tx, path).await.map(|(info, _)| info)
+}
+
+// the checkpoint is also the backup's snapshot.json, then everything the writer has appended
+// since is copied up to the lsn it reports. Records past that lsn are left out
+async fn run_backup(
+    write_tx: &mpsc::Sender<WriteCommand>,
+    path: &Path,
+    data_dir: &Path,
+    dest: PathBuf,
+) -> Result<(SnapshotInfo, Manifest), String> {
+    let (info, bytes) = take_snapshot(write_tx, path).await?;
+    let end_lsn = request_wal_end(write_tx).await?;
+    let data_dir = data_dir.to_path_buf();
+    let manifest = tokio::task::spawn_blocking(move || {
+        backup::write_backup(&data_dir, &dest, &bytes, info.lsn, end_lsn)
+    })
+    .await
+    .map_err(|e| format!("backup task error: {e}"))?;
+    match &manifest {
+        Ok(m) => debug!(snapshot_lsn = ?m.snapshot_lsn, end_lsn = ?m.end_lsn, files = m.files.len(), "backup written"),
+        Err(e) => warn!(error = %e, "backup failed"),
+    }
+    Ok((info, manifest?))
+}
+
+// returns the serialized snapshot too, for backups
+async fn take_snapshot(
+    write_tx: &mpsc::Sender<WriteCommand>,
+    path: &Path,
+) -> Result<(SnapshotInfo, Vec<u8>), String> {
+    let started = Instant::now();
+    let result = async {
+        let snapshot = request_snapshot_payload(write_tx).await?;
+        let bytes =
+            serde_json::to_vec(&snapshot).map_err(|e| format!("snapshot serialize error: {e}"))?;
+        checkpoint_durability(path, &bytes)?;
+        Ok((snapshot.lsn, bytes))
+    }
+    .await;
+    match &result {
+        Ok((_, bytes)) => {
+            let elapsed = started.elapsed();
+            let size = bytes.len() as u64;
+            METRICS.snapshot_seconds.observe_duration(elapsed);
+            METRICS.snapshot_bytes.store(size, Ordering::Relaxed);
+            debug!(bytes = size, elapsed_ms = elapsed.as_millis() as u64, "snapshot written");
+        }
+        Err(e) => {
+            METRICS.snapshot_failures.fetch_add(1, Ordering::Relaxed);
+            warn!(error = %e, "snapshot failed");
+        }
+    }
+    result.map(|(lsn, bytes)| {
+        let info = SnapshotInfo {
+            lsn,
+            unix_ms: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+        };
+        (info, bytes)
+    })
 }
 
 async fn request_snapshot_payload(
@@ -68,25 +170,34 @@ async fn request_snapshot_payload(
     resp_rx.await.map_err(|_| "writer dropped".to_string())?
 }
 
-fn checkpoint_durability(wal_path: &str, snapshot: &Snapshot) -> Result<(), String> {
-    let final_path = format!("{wal_path}.snapshot");
-    let tmp_path = format!("{final_path}.tmp");
+// where the wal ends once the writer's current batch is through
+async fn request_wal_end(write_tx: &mpsc::Sender<WriteCommand>) -> Result<Lsn, String> {
+    let (resp_tx, resp_rx) = oneshot::channel();
+
+    write_tx
+        .send(WriteCommand::Info { resp: resp_tx })
+        .await
+        .map_err(|_| "writer dropped".to_string())?;
+
+    let info = resp_rx.await.map_err(|_| "writer dropped".to_string())??;
+    Ok(info.lsn)
+}
 
-    let bytes =
-        serde_json::to_vec(snapshot).map_err(|e| format!("snapshot serialize error: {e}"))?;
+fn checkpoint_durability(final_path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let tmp_path = final_path.with_extension("json.tmp");
 
     let mut tmp_file =
         File::create(&tmp_path).map_err(|e| format!("snapshot create tmp error: {e}"))?;
     tmp_file
-        .write_all(&bytes)
+        .write_all(bytes)
         .map_err(|e| format!("snapshot write tmp error: {e}"))?;
     tmp_file
         .sync_all()
         .map_err(|e| format!("snapshot fsync tmp error: {e}"))?;
 
-    rename(&tmp_path, &final_path).map_err(|e| format!("snapshot rename error: {e}"))?;
+    rename(&tmp_path, final_path).map_err(|e| format!("snapshot rename error: {e}"))?;
 
-    let dir = Path::new(&final_path)
+    let dir = final_path
         .parent()
         .filter(|p| !p.as_os_str().is_empty())
         .unwrap_or(Path::new("."));
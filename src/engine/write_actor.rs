@@ -1,14 +1,18 @@
-use std::sync::Arc;
+use std::sync::atomic::Ordering;
 
-use tokio::sync::{RwLock, mpsc};
-use tokio::time::{Duration, interval};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{Duration, Instant, interval};
+use tracing::{Instrument, Span, debug, error, warn};
 
+use crate::engine::config::{Durability, EngineConfig};
 use crate::engine::db::Database;
+use crate::engine::info::{FsyncStats, WriterInfo};
 use crate::engine::notify_actor::NotifyCommand;
 use crate::engine::pending::PendingWrite;
 use crate::engine::snapshot_actor::SnapshotActorCommand;
-use crate::interface::command::WriteCommand;
-use crate::store::kv::Store;
+use crate::interface::command::{WriteCommand, WriteError};
+use crate::metrics::METRICS;
+use crate::store::snapshot::Snapshot;
 
 /// Runs the single-writer database actor loop.
 ///
@@ -21,23 +25,28 @@ use crate::store::kv::Store;
 /// Single write loop
 pub async fn write_actor(
     mut rx: mpsc::Receiver<WriteCommand>,
-    shared_store: Arc<RwLock<Store>>,
+    mut db: Database,
     snap_tx: mpsc::Sender<SnapshotActorCommand>,
     notify_tx: mpsc::Sender<NotifyCommand>,
+    config: EngineConfig,
 ) {
-    // open DB inside the writer
-    let mut db = Database::open("./fluxdb", shared_store)
-        .await
-        .expect("failed to open database");
-
     // fsync batching timer
     let mut tick = interval(Duration::from_millis(5));
 
     // pending writes waiting for durability barrier
     let mut pending: Vec<PendingWrite> = Vec::new();
+    // snapshot requests wait for the batch: pending events are in the wal but not in the store
+    // yet, a snapshot taken now would claim an lsn past data it doesn't contain
+    let mut snapshots: Vec<SnapshotReply> = Vec::new();
+    // same for info requests, so keys / lsn / writes_since_snapshot describe one state
+    let mut infos: Vec<InfoReply> = Vec::new();
 
     let mut writes_since_snapshot: u64 = 0;
-    const SNAPSHOT_EVERY: u64 = 1000;
+    let mut fsync = FsyncStats::default();
+
+    // Durability::Periodic: wal written but not fsynced yet, and when the last fsync was
+    let mut unsynced = false;
+    let mut last_sync = Instant::now();
 
     // serialized execution loop (database actor)
     loop {
@@ -47,7 +56,7 @@ pub async fn write_actor(
         tokio::select! {
             res = rx.recv() => { // recv blocks until a message is received
                 match res {
-                    Some(cmd) => handle_write_command(&mut db, &mut pending, cmd).await,
+                    Some(cmd) => handle_write_command(&mut db, &mut pending, &mut snapshots, &mut infos, cmd).await,
                     None => break, // Channel closed, exit actor
                 }
             }
@@ -58,66 +67,210 @@ pub async fn write_actor(
 
         // 2. Opportunistically drain all currently available commands
         while let Ok(cmd) = rx.try_recv() { // try_recv is non-blocking and drains all messages quickly (using this directly and only this will consume 100 percent CPU)
-            handle_write_command(&mut db, &mut pending, cmd).await;
+            handle_write_command(&mut db, &mut pending, &mut snapshots, &mut infos, cmd).await;
+        }
+
+        // periodic mode syncs on the clock instead of per batch. A failure can't be reported to
+        // writes that were already acked, so it is only logged
+        if unsynced && last_sync.elapsed() >= config.fsync_interval {
+            if let Err(e) = timed_fsync(&mut db, &mut fsync) {
+                error!(error = %e, "periodic wal fsync failed");
+            }
+            unsynced = false;
+            last_sync = Instant::now();
         }
 
+        METRICS
+            .write_queue_depth
+            .store((pending.len() + rx.len()) as u64, Ordering::Relaxed);
+
         // 3. If we have pending writes, fsync immediately
         if !pending.is_empty() {
-            // durability barrier
-            if let Err(e) = db.fsync_wal() {
+            METRICS.write_batch_size.observe(pending.len() as f64);
+            // durability barrier (sync mode only)
+            let synced = match config.durability {
+                Durability::Sync => timed_fsync(&mut db, &mut fsync),
+                Durability::Periodic => {
+                    unsynced = true;
+                    Ok(())
+                }
+                Durability::Off => Ok(()),
+            };
+            let batch = pending.len();
+            if let Err(e) = synced {
+                error!(error = %e, writes = batch, "wal fsync failed, rejecting the batch");
+                db.discard_pending();
                 for p in pending.drain(..) {
-                    let _ = p.resp.send(Err(e.to_string()));
+                    p.span.in_scope(|| warn!(error = %e, "write failed at the wal fsync"));
+                    let _ = p.resp.send(Err(WriteError::Failed(e.to_string())));
                 }
                 continue;
             }
 
-            // apply + notify + ACK
+            // apply + notify + ACK, each inside its own request's span
             for p in pending.drain(..) {
-                let event = p.event.clone();
-                if let Err(e) = db.execute_post_durability(p.event).await {
-                    let _ = p.resp.send(Err(e.to_string()));
-                } else {
-                    let _ = p.resp.send(Ok(()));
-                    let _ = notify_tx.send(NotifyCommand::Dispatch { event }).await;
-                    writes_since_snapshot += 1;
-                    if writes_since_snapshot >= SNAPSHOT_EVERY {
-                        let _ = snap_tx.send(SnapshotActorCommand::TriggerNow).await;
-                        writes_since_snapshot = 0;
+                p.span.in_scope(|| debug!(batch, durability = %config.durability, "durable"));
+                match db.execute_post_durability(p.event).instrument(p.span.clone()).await {
+                    Err(e) => {
+                        p.span.in_scope(|| error!(error = %e, "apply failed"));
+                        let _ = p.resp.send(Err(WriteError::Failed(e.to_string())));
+                    }
+                    Ok(events) => {
+                        let _ = p.resp.send(Ok(()));
+                        let span = p.span;
+                        let _ = notify_tx.send(NotifyCommand::Dispatch { events, span }).await;
+                        writes_since_snapshot += 1;
+                        if writes_since_snapshot >= config.snapshot_every {
+                            let _ = snap_tx.send(SnapshotActorCommand::TriggerNow).await;
+                            writes_since_snapshot = 0;
+                        }
                     }
                 }
             }
         }
+
+        // store and wal agree again. The snapshot records the wal's current lsn, outside sync mode
+        // that may cover bytes that were never fsynced. A power loss would bring the segment back
+        // shorter than the lsn and new appends would land before it, so sync first
+        if !snapshots.is_empty() && config.durability != Durability::Sync {
+            let synced = timed_fsync(&mut db, &mut fsync);
+            if let Err(e) = synced {
+                error!(error = %e, "wal fsync before snapshot failed");
+                for resp in snapshots.drain(..) {
+                    let _ = resp.send(Err(format!("wal fsync before snapshot failed: {e}")));
+                }
+            }
+            unsynced = false;
+            last_sync = Instant::now();
+        }
+        for resp in snapshots.drain(..) {
+            let payload = db.checkpoint_payload().await.map_err(|e| e.to_string());
+            if payload.is_ok() {
+                writes_since_snapshot = 0;
+            }
+            let _ = resp.send(payload);
+        }
+        for resp in infos.drain(..) {
+            let info = db.info(writes_since_snapshot, fsync).await;
+            let _ = resp.send(info.map_err(|e| e.to_string()));
+        }
     }
 }
 
-async fn handle_write_command(db: &mut Database, pending: &mut Vec<PendingWrite>, cmd: WriteCommand) {
+type SnapshotReply = oneshot::Sender<Result<Snapshot, String>>;
+type InfoReply = oneshot::Sender<Result<WriterInfo, String>>;
+
+fn timed_fsync(db: &mut Database, stats: &mut FsyncStats) -> std::io::Result<()> {
+    let started = Instant::now();
+    let result = db.fsync_wal();
+    let elapsed = started.elapsed();
+    METRICS.wal_fsync_seconds.observe_duration(elapsed);
+    stats.count += 1;
+    stats.failures += result.is_err() as u64;
+    stats.last_us = elapsed.as_micros() as u64;
+    stats.total_us += stats.last_us;
+    debug!(elapsed_us = elapsed.as_micros() as u64, "wal fsync");
+    result
+}
+
+// runs the command inside the span it brought along
+async fn handle_write_command(
+    db: &mut Database,
+    pending: &mut Vec<PendingWrite>,
+    snapshots: &mut Vec<SnapshotReply>,
+    infos: &mut Vec<InfoReply>,
+    cmd: WriteCommand,
+) {
+    let span = match &cmd {
+        WriteCommand::Set { span, .. }
+        | WriteCommand::Del { span, .. }
+        | WriteCommand::Patch { span, .. }
+        | WriteCommand::FlushAll { span, .. } => span.clone(),
+        WriteCommand::Snapshot { .. }
+        | WriteCommand::InjectFailure { .. }
+        | WriteCommand::Info { .. } => Span::none(),
+    };
+    run_write_command(db, pending, snapshots, infos, cmd)
+        .instrument(span)
+        .await
+}
+
+async fn run_write_command(
+    db: &mut Database,
+    pending: &mut Vec<PendingWrite>,
+    snapshots: &mut Vec<SnapshotReply>,
+    infos: &mut Vec<InfoReply>,
+    cmd: WriteCommand,
+) {
     match cmd {
-        WriteCommand::Set { key, value, resp } => match db.put(key, value).await {
-            Ok(event) => pending.push(PendingWrite { event, resp }),
-            Err(e) => {
-                let _ = resp.send(Err(e.to_string()));
+        WriteCommand::Set {
+            key,
+            value,
+            expected_version,
+            span,
+            resp,
+        } => {
+            if let Err(e) = db.check_version(&key, expected_version).await {
+                debug!(error = %e, "rejected");
+                let _ = resp.send(Err(e));
+                return;
             }
-        },
-        WriteCommand::Del { key, resp } => match db.delete(&key).await {
-            Ok(event) => pending.push(PendingWrite { event, resp }),
-            Err(e) => {
-                let _ = resp.send(Err(e.to_string()));
+            match db.put(key, value).await {
+                Ok(event) => pending.push(PendingWrite { event, span, resp }),
+                Err(e) => {
+                    error!(error = %e, "wal append failed");
+                    let _ = resp.send(Err(WriteError::Failed(e.to_string())));
+                }
             }
-        },
-        WriteCommand::Patch { key, delta, resp } => match db.patch(&key, delta).await {
-            Ok(event) => pending.push(PendingWrite { event, resp }),
-            Err(e) => {
-                let _ = resp.send(Err(e.to_string()));
+        }
+        WriteCommand::Del {
+            key,
+            expected_version,
+            span,
+            resp,
+        } => {
+            if let Err(e) = db.check_version(&key, expected_version).await {
+                debug!(error = %e, "rejected");
+                let _ = resp.send(Err(e));
+                return;
             }
-        },
-        WriteCommand::Snapshot { resp } => match db.checkpoint_payload().await {
-            Ok(snapshot) => {
-                let _ = resp.send(Ok(snapshot));
+            match db.delete(&key).await {
+                Ok(event) => pending.push(PendingWrite { event, span, resp }),
+                Err(e) => {
+                    error!(error = %e, "wal append failed");
+                    let _ = resp.send(Err(WriteError::Failed(e.to_string())));
+                }
             }
+        }
+        WriteCommand::Patch {
+            key,
+            delta,
+            expected_version,
+            span,
+            resp,
+        } => {
+            if let Err(e) = db.check_version(&key, expected_version).await {
+                debug!(error = %e, "rejected");
+                let _ = resp.send(Err(e));
+                return;
+            }
+            match db.patch(&key, delta).await {
+                Ok(event) => pending.push(PendingWrite { event, span, resp }),
+                Err(e) => {
+                    error!(error = %e, "wal append failed");
+                    let _ = resp.send(Err(WriteError::Failed(e.to_string())));
+                }
+            }
+        }
+        WriteCommand::FlushAll { span, resp } => match db.flush_all().await {
+            Ok(event) => pending.push(PendingWrite { event, span, resp }),
             Err(e) => {
-                let _ = resp.send(Err(e.to_string()));
+                error!(error = %e, "wal append failed");
+                let _ = resp.send(Err(WriteError::Failed(e.to_string())));
             }
         },
+        WriteCommand::Snapshot { resp } => snapshots.push(resp),
+        WriteCommand::Info { resp } => infos.push(resp),
         WriteCommand::InjectFailure { resp } => {
             db.fail_next_fsync = true;
             let _ = resp.send(());
@@ -7,6 +7,26 @@ pub struct Event {
     pub old: Value,
     pub new: Value,
     pub version: u64,
+    // left out for plain writes, so older wal records (and what subscribers get) look the same
+    #[serde(default, skip_serializing_if = "EventOp::is_write")]
+    pub op: EventOp,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventOp {
+    // key / old / new describe one key
+    #[default]
+    Write,
+    // empties the whole store. Only ever a wal record: key is "", old / new are null and
+    // subscribers are sent one delete per key instead (Store::flush_notifications)
+    FlushAll,
+}
+
+impl EventOp {
+    pub fn is_write(&self) -> bool {
+        *self == EventOp::Write
+    }
 }
 
 
@@ -1,3 +1,3 @@
 pub mod event;
 
-pub use event::Event;
\ No newline at end of file
+pub use event::{Event, EventOp};
\ No newline at end of file
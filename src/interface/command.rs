@@ -1,29 +1,55 @@
+use std::fmt;
+
 use serde_json::Value;
 use tokio::sync::oneshot;
+use tracing::Span;
 
+use crate::engine::info::WriterInfo;
 use crate::store::{kv::Document, snapshot::Snapshot};
 
 pub enum ReadCommand {
     Get {
         key: String,
+        span: Span,
         resp: oneshot::Sender<Option<Document>>,
     },
+    Keys {
+        pattern: Option<String>, // glob, None = every key
+        resp: oneshot::Sender<Vec<String>>,
+    },
+    DbSize {
+        resp: oneshot::Sender<u64>,
+    },
 }
 
+// `span` is the caller's (Span::current() in EngineHandle), usually the network request's.
+// It rides along through the writer, the pending batch and the notify actor, so everything
+// logged for the write happens inside it
 pub enum WriteCommand {
     Set {
         key: String,
         value: Value,
-        resp: oneshot::Sender<Result<(), String>>,
+        expected_version: Option<u64>,
+        span: Span,
+        resp: oneshot::Sender<Result<(), WriteError>>,
     },
     Del {
         key: String,
-        resp: oneshot::Sender<Result<(), String>>,
+        expected_version: Option<u64>,
+        span: Span,
+        resp: oneshot::Sender<Result<(), WriteError>>,
     },
     Patch {
         key: String,
         delta: Value,
-        resp: oneshot::Sender<Result<(), String>>,
+        expected_version: Option<u64>,
+        span: Span,
+        resp: oneshot::Sender<Result<(), WriteError>>,
+    },
+    // every key in one wal record (EventOp::FlushAll)
+    FlushAll {
+        span: Span,
+        resp: oneshot::Sender<Result<(), WriteError>>,
     },
     Snapshot {
         resp: oneshot::Sender<Result<Snapshot, String>>,
@@ -31,4 +57,32 @@ pub enum WriteCommand {
     InjectFailure {
         resp: oneshot::Sender<()>,
     },
+    // answered once the current batch is applied, like Snapshot
+    Info {
+        resp: oneshot::Sender<Result<WriterInfo, String>>,
+    },
+}
+
+// expected_version is compared against Document.version before the event is built (0 = key missing)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteError {
+    VersionConflict { expected: u64, actual: u64 },
+    Failed(String),
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::VersionConflict { expected, actual } => {
+                write!(f, "version conflict: expected {expected}, found {actual}")
+            }
+            WriteError::Failed(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl From<WriteError> for String {
+    fn from(e: WriteError) -> Self {
+        e.to_string()
+    }
 }
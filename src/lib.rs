@@ -1,6 +1,11 @@
+pub mod client;
+pub mod config;
+pub mod dump;
 pub mod engine;
 pub mod event;
 pub mod interface;
+pub mod logging;
+pub mod metrics;
 pub mod reactivity;
 pub mod store;
 pub mod net;
\ No newline at end of file
@@ -0,0 +1,59 @@
+use std::{fmt, str::FromStr};
+
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+// how the server writes its log lines (to stderr)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // one json object per line: timestamp, level, target, message, fields, and the spans it
+    // happened in (connection -> request with its trace_id -> engine stages)
+    #[default]
+    Json,
+    // human readable, for a terminal
+    Text,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(LogFormat::Json),
+            "text" => Ok(LogFormat::Text),
+            other => Err(format!("unknown log format {other:?}, expected json or text")),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogFormat::Json => "json",
+            LogFormat::Text => "text",
+        })
+    }
+}
+
+// `level` is a filter in the RUST_LOG syntax: "info", "warn,fluxdb::engine=debug", ...
+pub fn parse_level(level: &str) -> Result<EnvFilter, String> {
+    EnvFilter::try_new(level).map_err(|e| format!("bad log level {level:?}: {e}"))
+}
+
+// installs the global subscriber, once per process
+pub fn init(level: &str, format: LogFormat) -> Result<(), String> {
+    let filter = parse_level(level)?;
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    let installed = match format {
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(false) // the span list already ends with it
+            .with_span_list(true)
+            .try_init(),
+        LogFormat::Text => builder.try_init(),
+    };
+    installed.map_err(|e| format!("logging already initialized: {e}"))
+}
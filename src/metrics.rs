@@ -0,0 +1,249 @@
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{http::header, routing::get, Router};
+use serde_json::Value;
+use tokio::net::TcpListener;
+
+use crate::net::limits::COUNTERS;
+
+// engine wide metrics in the Prometheus text format, scraped from --metrics-addr (GET /metrics).
+// Like net::limits::COUNTERS these are process wide: plain atomics, updated inline by whoever
+// does the work (write_actor, the wal, snapshot_actor, notify_actor, net::dispatch)
+
+// upper bounds in seconds
+const LATENCY_BUCKETS: &[f64] = &[
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+const SNAPSHOT_BUCKETS: &[f64] = &[0.001, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+// events per fsync barrier
+const BATCH_BUCKETS: &[f64] = &[1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0, 1024.0];
+
+// the `command` label of fluxdb_command_duration_seconds, see Request::name
+const COMMANDS: &[&str] = &[
+    "set", "get", "del", "patch", "snapshot", "subscribe", "stats", "info", "db_size", "keys",
+    "flush_all", "backup", "auth", "hello",
+];
+
+// fixed buckets, cumulative only when rendered
+pub struct Histogram {
+    bounds: &'static [f64],
+    buckets: Vec<AtomicU64>, // one per bound, plus +Inf
+    count: AtomicU64,
+    sum: AtomicU64, // f64 bits
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let i = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+    }
+
+    pub fn observe_duration(&self, elapsed: Duration) {
+        self.observe(elapsed.as_secs_f64());
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (i, bound) in self.bounds.iter().enumerate() {
+            cumulative += self.buckets[i].load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        cumulative += self.buckets[self.bounds.len()].load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {cumulative}"
+        );
+        let sum = f64::from_bits(self.sum.load(Ordering::Relaxed));
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{labels}}}")
+        };
+        let _ = writeln!(out, "{name}_sum{labels} {sum}");
+        let _ = writeln!(
+            out,
+            "{name}_count{labels} {}",
+            self.count.load(Ordering::Relaxed)
+        );
+    }
+}
+
+pub struct Metrics {
+    // write_actor
+    pub write_batch_size: Histogram,
+    pub wal_fsync_seconds: Histogram,
+    pub write_queue_depth: AtomicU64, // commands queued for the writer + writes waiting on the barrier
+    // wal
+    pub wal_bytes_appended: AtomicU64,
+    pub wal_segment_rotations: AtomicU64,
+    // snapshot_actor
+    pub snapshot_seconds: Histogram,
+    pub snapshot_bytes: AtomicU64, // size of the last snapshot written
+    pub snapshot_failures: AtomicU64,
+    // store, kept by Database as events are applied
+    pub keys: AtomicU64,
+    pub memory_bytes: AtomicU64, // approximate, see approx_size
+    // Reactivity
+    pub subscribers: AtomicU64,
+    pub subscriber_evictions: AtomicU64, // slow subscribers dropped with a full buffer
+    // net::dispatch (tcp and websocket)
+    command_seconds: Vec<Histogram>,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(|| Metrics {
+    write_batch_size: Histogram::new(BATCH_BUCKETS),
+    wal_fsync_seconds: Histogram::new(LATENCY_BUCKETS),
+    write_queue_depth: AtomicU64::new(0),
+    wal_bytes_appended: AtomicU64::new(0),
+    wal_segment_rotations: AtomicU64::new(0),
+    snapshot_seconds: Histogram::new(SNAPSHOT_BUCKETS),
+    snapshot_bytes: AtomicU64::new(0),
+    snapshot_failures: AtomicU64::new(0),
+    keys: AtomicU64::new(0),
+    memory_bytes: AtomicU64::new(0),
+    subscribers: AtomicU64::new(0),
+    subscriber_evictions: AtomicU64::new(0),
+    command_seconds: COMMANDS
+        .iter()
+        .map(|_| Histogram::new(LATENCY_BUCKETS))
+        .collect(),
+});
+
+// records the command's latency when dropped, so early returns are counted too
+pub struct CommandTimer {
+    index: Option<usize>,
+    started: Instant,
+}
+
+impl Drop for CommandTimer {
+    fn drop(&mut self) {
+        if let Some(i) = self.index {
+            METRICS.command_seconds[i].observe_duration(self.started.elapsed());
+        }
+    }
+}
+
+impl Metrics {
+    pub fn command_timer(&self, command: &str) -> CommandTimer {
+        CommandTimer {
+            index: COMMANDS.iter().position(|c| *c == command),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let histograms = [
+            ("fluxdb_write_batch_size", "writes per wal fsync barrier", &self.write_batch_size),
+            ("fluxdb_wal_fsync_seconds", "time spent in wal fsync", &self.wal_fsync_seconds),
+            ("fluxdb_snapshot_duration_seconds", "time to take and persist a snapshot", &self.snapshot_seconds),
+        ];
+        for (name, text, histogram) in histograms {
+            help(&mut out, name, "histogram", text);
+            histogram.render(&mut out, name, "");
+        }
+        let name = "fluxdb_command_duration_seconds";
+        help(&mut out, name, "histogram", "time to run a tcp / websocket request");
+        for (command, histogram) in COMMANDS.iter().zip(&self.command_seconds) {
+            histogram.render(&mut out, name, &format!("command=\"{command}\""));
+        }
+
+        // the `stats` counters ride along, so one scrape has everything
+        let load = |a: &AtomicU64| a.load(Ordering::Relaxed);
+        let stats = COUNTERS.snapshot();
+        let values = [
+            ("fluxdb_write_queue_depth", "gauge", "writes queued for the writer or waiting on the fsync barrier", load(&self.write_queue_depth)),
+            ("fluxdb_wal_appended_bytes_total", "counter", "bytes appended to the wal", load(&self.wal_bytes_appended)),
+            ("fluxdb_wal_segment_rotations_total", "counter", "wal segments started because the active one was full", load(&self.wal_segment_rotations)),
+            ("fluxdb_snapshot_size_bytes", "gauge", "size of the last snapshot written", load(&self.snapshot_bytes)),
+            ("fluxdb_snapshot_failures_total", "counter", "snapshots that failed", load(&self.snapshot_failures)),
+            ("fluxdb_keys", "gauge", "keys in the store", load(&self.keys)),
+            ("fluxdb_memory_bytes", "gauge", "approximate size of the keys and values in the store", load(&self.memory_bytes)),
+            ("fluxdb_subscribers", "gauge", "open key and pattern subscriptions", load(&self.subscribers)),
+            ("fluxdb_subscriber_evictions_total", "counter", "subscribers dropped for falling behind", load(&self.subscriber_evictions)),
+            ("fluxdb_connections_open", "gauge", "open client connections", stats.connections_open),
+            ("fluxdb_connections_total", "counter", "client connections accepted", stats.connections_total),
+            ("fluxdb_connections_rejected_total", "counter", "connections refused over max connections", stats.connections_rejected),
+            ("fluxdb_requests_too_large_total", "counter", "requests over the size limit", stats.requests_too_large),
+            ("fluxdb_idle_timeouts_total", "counter", "connections closed for being idle", stats.idle_timeouts),
+            ("fluxdb_read_timeouts_total", "counter", "connections closed mid request", stats.read_timeouts),
+            ("fluxdb_subscriptions_rejected_total", "counter", "subscriptions over the per connection limit", stats.subscriptions_rejected),
+            ("fluxdb_rate_limited_total", "counter", "requests answered with rate_limited", stats.rate_limited),
+        ];
+        for (name, kind, text, value) in values {
+            help(&mut out, name, kind, text);
+            let _ = writeln!(out, "{name} {value}");
+        }
+        out
+    }
+}
+
+fn help(out: &mut String, name: &str, kind: &str, text: &str) {
+    let _ = writeln!(out, "# HELP {name} {text}\n# TYPE {name} {kind}");
+}
+
+// rough heap footprint of one entry: key + value bytes plus a fixed per entry / per node
+// overhead. Good enough to watch growth, not an exact allocator count
+pub fn approx_size(key: &str, value: &Value) -> u64 {
+    const ENTRY: u64 = 64; // hashmap slot, Document, String headers
+
+    fn value_size(value: &Value) -> u64 {
+        const NODE: u64 = 32;
+        NODE + match value {
+            Value::Null | Value::Bool(_) | Value::Number(_) => 0,
+            Value::String(s) => s.len() as u64,
+            Value::Array(items) => items.iter().map(value_size).sum(),
+            Value::Object(map) => map
+                .iter()
+                .map(|(k, v)| k.len() as u64 + value_size(v))
+                .sum(),
+        }
+    }
+
+    ENTRY + key.len() as u64 + value_size(value)
+}
+
+// the scrape endpoint. No auth: bind it to an address only the scraper can reach
+pub async fn serve(listener: TcpListener) -> std::io::Result<()> {
+    let app = Router::new().route(
+        "/metrics",
+        get(|| async {
+            (
+                [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+                METRICS.render(),
+            )
+        }),
+    );
+    axum::serve(listener, app).await
+}
@@ -0,0 +1,193 @@
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    net::{auth::Authenticator, protocol::Request, ratelimit::Budget},
+    store::glob::glob_match,
+};
+
+// what a request does, independent of the transport it came in on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandClass {
+    Read,      // get, keys, ttl, json.get ...
+    Write,     // set, del, patch, expire, json.set ...
+    Subscribe, // subscribe / psubscribe, events are filtered by key as well
+    Admin,     // snapshot and other whole-database operations
+}
+
+impl fmt::Display for CommandClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CommandClass::Read => "read",
+            CommandClass::Write => "write",
+            CommandClass::Subscribe => "subscribe",
+            CommandClass::Admin => "admin",
+        };
+        f.write_str(name)
+    }
+}
+
+pub fn all_classes() -> Vec<CommandClass> {
+    vec![
+        CommandClass::Read,
+        CommandClass::Write,
+        CommandClass::Subscribe,
+        CommandClass::Admin,
+    ]
+}
+
+pub fn all_keys() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+// one user's permissions, from the `commands` / `keys` fields of its [[users]] entry.
+// both default to everything, so auth files without acls keep working
+#[derive(Debug, Clone)]
+pub struct Acl {
+    pub commands: Vec<CommandClass>,
+    pub keys: Vec<String>, // glob patterns, e.g. "tenant:42:*"
+}
+
+impl Acl {
+    pub fn allows_class(&self, class: CommandClass) -> bool {
+        self.commands.contains(&class)
+    }
+
+    pub fn allows_key(&self, key: &str) -> bool {
+        self.keys.iter().any(|pattern| glob_match(pattern, key))
+    }
+}
+
+// the class and key (if it has one) of a json protocol request.
+// None = connection level requests (hello, auth) that every user may send
+pub fn classify(req: &Request) -> Option<(CommandClass, Option<&str>)> {
+    match req {
+        Request::Get { key } => Some((CommandClass::Read, Some(key))),
+        Request::Set { key, .. } | Request::Del { key } | Request::Patch { key, .. } => {
+            Some((CommandClass::Write, Some(key)))
+        }
+        // the keys are filtered by the user's patterns instead
+        Request::DbSize | Request::Keys { .. } => Some((CommandClass::Read, None)),
+        Request::Subscribe { key } => Some((CommandClass::Subscribe, Some(key))),
+        Request::Snapshot
+        | Request::Stats
+        | Request::Info
+        | Request::FlushAll
+        | Request::Backup { .. } => Some((CommandClass::Admin, None)),
+        Request::Auth { .. } | Request::Hello { .. } => None,
+    }
+}
+
+// an authenticated user on one connection. Permissions are looked up on every check, so a
+// reloaded auth file applies to connections that are already logged in
+#[derive(Clone)]
+pub struct Principal {
+    auth: Arc<Authenticator>,
+    user: String,
+}
+
+impl Principal {
+    pub fn new(auth: Arc<Authenticator>, user: String) -> Self {
+        Self { auth, user }
+    }
+
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn authorize(&self, class: CommandClass, key: Option<&str>) -> Result<(), String> {
+        // a user removed by a reload loses everything
+        let Some(acl) = self.auth.acl(&self.user) else {
+            return Err(format!("permission denied: unknown user {}", self.user));
+        };
+        if !acl.allows_class(class) {
+            return Err(format!(
+                "permission denied: {} may not run {class} commands",
+                self.user
+            ));
+        }
+        if let Some(key) = key {
+            if !acl.allows_key(key) {
+                return Err(format!(
+                    "permission denied: {} may not access key {key}",
+                    self.user
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // the user's own budget, on top of the connection's (net::ratelimit)
+    pub fn rate(&self) -> Option<Budget> {
+        self.auth.rate(&self.user)
+    }
+
+    // for filtering key lists and pattern subscription events
+    pub fn can_access(&self, class: CommandClass, key: &str) -> bool {
+        self.authorize(class, Some(key)).is_ok()
+    }
+
+    // no key pattern narrower than "*", so key lists need no filtering
+    pub fn sees_every_key(&self) -> bool {
+        self.auth
+            .acl(&self.user)
+            .is_some_and(|acl| acl.keys.iter().any(|pattern| pattern == "*"))
+    }
+}
+
+// the server's [admin] config section, see anonymous and backup_dest
+#[derive(Debug, Clone, Default)]
+pub struct AdminPolicy {
+    pub allow_anonymous: bool, // flush_all and backup without an authenticated user
+    pub backup_root: Option<PathBuf>, // backups go below it, None = the server takes no backups
+}
+
+impl AdminPolicy {
+    // a backup's dest_dir names a directory below backup_root, so a client can't have the server
+    // write anywhere else: relative, no `..`, and refused outright when there is no root
+    pub fn backup_dest(&self, dest_dir: &str) -> Result<PathBuf, String> {
+        let Some(root) = &self.backup_root else {
+            return Err("backups are disabled on this server (no admin.backup_root)".to_string());
+        };
+        let dest = Path::new(dest_dir);
+        let below_root = dest
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        if !below_root || !dest.components().any(|c| matches!(c, Component::Normal(_))) {
+            return Err(format!(
+                "backup dest_dir {dest_dir:?} must be a relative path below the backup root, without .."
+            ));
+        }
+        Ok(root.join(dest))
+    }
+}
+
+// flush_all wipes the database and backup copies it out, so a client nobody has authenticated
+// (auth is off) only gets them when the server config says so. Everything else is allowed
+pub fn anonymous(req: &Request, admin: &AdminPolicy) -> Result<(), String> {
+    match req {
+        Request::FlushAll | Request::Backup { .. } if !admin.allow_anonymous => Err(format!(
+            "permission denied: {} needs an authenticated user unless the server sets admin.allow_anonymous",
+            req.name()
+        )),
+        _ => Ok(()),
+    }
+}
+
+// auth disabled = no principal = everything allowed
+pub fn authorize(
+    principal: Option<&Principal>,
+    class: CommandClass,
+    key: Option<&str>,
+) -> Result<(), String> {
+    match principal {
+        Some(principal) => principal.authorize(class, key),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use argon2::{
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncBufRead, AsyncWrite, AsyncWriteExt},
+    sync::{Mutex, Semaphore},
+    time::Instant,
+};
+
+use crate::net::{
+    acl::{all_classes, all_keys, Acl, CommandClass, Principal},
+    codec::{encode, read_frame},
+    protocol::{Framing, Request, RequestFrame, Response, ResponseFrame},
+    ratelimit::Budget,
+};
+
+// failed attempts per peer ip before it is locked out, and for how long
+const MAX_FAILURES: u32 = 5;
+const LOCKOUT: Duration = Duration::from_secs(30);
+// every failure is answered this late, so one connection can't hammer the hasher
+const FAILURE_DELAY: Duration = Duration::from_millis(250);
+// unknown users are checked against this (same argon2 parameters as hash_secret), so they take
+// as long to reject as a wrong secret
+const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$ryYrzzEfMvxacNUO1587Qg$ffptzLZHKxp6xcbzzCTBxF5wSymY+53Bxve70ddCG5g";
+// argon2 verifications running at once, each takes ~19 MiB and tens of ms of cpu. More wait
+const MAX_VERIFYING: usize = 4;
+// a verified user/secret pair is accepted without hashing again for this long, so http
+// keep-alive traffic (credentials on every request) doesn't pay argon2 each time
+const VERIFIED_TTL: Duration = Duration::from_secs(30);
+const MAX_VERIFIED: usize = 1024;
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+// the auth file (toml):
+//
+//   timeout_secs = 10          # unauthenticated connections are closed after this
+//
+//   [[users]]
+//   name = "admin"
+//   hash = "$argon2id$v=19$..."  # from `fluxdb-passwd admin`
+//
+//   [[users]]
+//   name = "tenant42"
+//   hash = "$argon2id$v=19$..."
+//   commands = ["read", "subscribe"]  # default: read, write, subscribe, admin
+//   keys = ["tenant:42:*"]            # glob patterns, default: ["*"]
+//   rate = { write_ops = 100 }        # per second, default: default_rate (see net::ratelimit)
+//
+// passwords and api tokens are the same thing here, a token is just a generated secret.
+// the file is re-read on SIGHUP (see Authenticator::reload)
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default)]
+    pub default_rate: Option<Budget>, // for users without their own `rate`
+    #[serde(default)]
+    pub users: Vec<UserEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserEntry {
+    pub name: String,
+    pub hash: String, // argon2 PHC string, salt included
+    #[serde(default = "all_classes")]
+    pub commands: Vec<CommandClass>,
+    #[serde(default = "all_keys")]
+    pub keys: Vec<String>,
+    #[serde(default)]
+    pub rate: Option<Budget>,
+}
+
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+struct User {
+    hash: String,
+    acl: Arc<Acl>,
+    rate: Option<Budget>,
+}
+
+// everything that comes from the file, swapped as a whole on reload
+struct Users {
+    timeout: Duration,
+    by_name: HashMap<String, User>,
+}
+
+impl Users {
+    // hashes are parsed up front so a typo fails at startup (or reload), not at the first login
+    fn from_config(config: AuthConfig) -> Result<Self, String> {
+        let mut by_name = HashMap::new();
+        for user in config.users {
+            PasswordHash::new(&user.hash)
+                .map_err(|e| format!("bad hash for user {}: {e}", user.name))?;
+            let entry = User {
+                hash: user.hash,
+                acl: Arc::new(Acl {
+                    commands: user.commands,
+                    keys: user.keys,
+                }),
+                rate: user.rate.or(config.default_rate),
+            };
+            if by_name.insert(user.name.clone(), entry).is_some() {
+                return Err(format!("user {} is listed twice", user.name));
+            }
+        }
+
+        Ok(Self {
+            timeout: Duration::from_secs(config.timeout_secs),
+            by_name,
+        })
+    }
+}
+
+pub struct Authenticator {
+    path: Option<PathBuf>, // None when built from an in-memory config
+    users: RwLock<Users>,
+    failures: Mutex<HashMap<IpAddr, Failures>>,
+    verifying: Semaphore,
+    // digest of user + secret -> when it was verified. Keyed with a per process random key,
+    // so the cache holds nothing a secret could be brute forced from cheaply
+    verified: std::sync::Mutex<HashMap<[u8; 32], Instant>>,
+    cache_key: [u8; 32],
+}
+
+fn read_config(path: &Path) -> Result<AuthConfig, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("reading auth file {}: {e}", path.display()))?;
+    toml::from_str(&text).map_err(|e| format!("parsing auth file {}: {e}", path.display()))
+}
+
+impl Authenticator {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut auth = Self::from_config(read_config(path)?)?;
+        auth.path = Some(path.to_path_buf());
+        Ok(auth)
+    }
+
+    pub fn from_config(config: AuthConfig) -> Result<Self, String> {
+        let mut cache_key = [0u8; 32];
+        OsRng.fill_bytes(&mut cache_key);
+        Ok(Self {
+            path: None,
+            users: RwLock::new(Users::from_config(config)?),
+            failures: Mutex::new(HashMap::new()),
+            verifying: Semaphore::new(MAX_VERIFYING),
+            verified: std::sync::Mutex::new(HashMap::new()),
+            cache_key,
+        })
+    }
+
+    // re-reads the file this was loaded from. On any error the old users and acls stay in
+    // place. Logged in connections keep their login but see the new acl on their next request
+    pub fn reload(&self) -> Result<usize, String> {
+        let Some(path) = &self.path else {
+            return Err("auth was not loaded from a file".to_string());
+        };
+        self.replace(read_config(path)?)
+    }
+
+    pub fn replace(&self, config: AuthConfig) -> Result<usize, String> {
+        let users = Users::from_config(config)?;
+        let count = users.by_name.len();
+        *self.users.write().unwrap() = users;
+        // a changed or removed secret must not stay valid through the cache
+        self.verified.lock().unwrap().clear();
+        Ok(count)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.users.read().unwrap().timeout
+    }
+
+    pub fn acl(&self, user: &str) -> Option<Arc<Acl>> {
+        self.users
+            .read()
+            .unwrap()
+            .by_name
+            .get(user)
+            .map(|u| u.acl.clone())
+    }
+
+    pub fn rate(&self, user: &str) -> Option<Budget> {
+        self.users.read().unwrap().by_name.get(user)?.rate
+    }
+
+    // checks user/secret for a connection coming from `peer`. Unknown users and wrong secrets
+    // get the same error after the same argon2 work, so user names can't be probed.
+    // At most MAX_VERIFYING hashes run at once, and a pair verified in the last VERIFIED_TTL
+    // is accepted without hashing
+    pub async fn authenticate(&self, peer: IpAddr, user: &str, secret: &str) -> Result<(), String> {
+        self.check_lockout(peer).await?;
+
+        let digest = self.digest(user, secret);
+        if self.verified.lock().unwrap().get(&digest).is_some_and(|at| at.elapsed() < VERIFIED_TTL) {
+            return Ok(());
+        }
+
+        let permit = self.verifying.acquire().await.expect("never closed");
+        // queued attempts from a peer that got locked out meanwhile don't hash either
+        self.check_lockout(peer).await?;
+
+        let hash = self
+            .users
+            .read()
+            .unwrap()
+            .by_name
+            .get(user)
+            .map(|u| u.hash.clone());
+        let known = hash.is_some();
+        let hash = hash.unwrap_or_else(|| DUMMY_HASH.to_string());
+        // argon2 is deliberately slow, keep it off the runtime threads
+        let secret = secret.to_string();
+        let verified = tokio::task::spawn_blocking(move || verify_secret(&hash, &secret))
+            .await
+            .unwrap_or(false);
+        drop(permit);
+        let ok = known && verified;
+
+        if ok {
+            self.failures.lock().await.remove(&peer);
+            let mut cache = self.verified.lock().unwrap();
+            cache.retain(|_, at| at.elapsed() < VERIFIED_TTL);
+            if cache.len() >= MAX_VERIFIED {
+                cache.clear();
+            }
+            cache.insert(digest, Instant::now());
+            return Ok(());
+        }
+
+        let mut failures = self.failures.lock().await;
+        failures.retain(|_, f| f.last.elapsed() < LOCKOUT); // forget old offenders
+        let entry = failures.entry(peer).or_insert(Failures {
+            count: 0,
+            last: Instant::now(),
+        });
+        entry.count += 1;
+        entry.last = Instant::now();
+        drop(failures);
+
+        tokio::time::sleep(FAILURE_DELAY).await;
+        Err("invalid user or secret".to_string())
+    }
+
+    async fn check_lockout(&self, peer: IpAddr) -> Result<(), String> {
+        if let Some(f) = self.failures.lock().await.get(&peer) {
+            let elapsed = f.last.elapsed();
+            if f.count >= MAX_FAILURES && elapsed < LOCKOUT {
+                let wait = (LOCKOUT - elapsed).as_secs() + 1;
+                return Err(format!(
+                    "too many failed auth attempts, try again in {wait}s"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn digest(&self, user: &str, secret: &str) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.cache_key);
+        hasher.update((user.len() as u64).to_be_bytes());
+        hasher.update(user.as_bytes());
+        hasher.update(secret.as_bytes());
+        hasher.finalize().into()
+    }
+}
+
+pub fn hash_secret(secret: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(secret.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+// 32 random bytes as hex, for api tokens
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn verify_secret(hash: &str, secret: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(secret.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+// per connection login state. With no Authenticator configured everything is allowed
+pub struct Gate {
+    auth: Option<Arc<Authenticator>>,
+    peer: IpAddr,
+    user: Option<String>,
+    deadline: Option<Instant>,
+}
+
+impl Gate {
+    pub fn new(auth: Option<Arc<Authenticator>>, peer: IpAddr) -> Self {
+        let deadline = auth.as_ref().map(|a| Instant::now() + a.timeout());
+        Self {
+            auth,
+            peer,
+            user: None,
+            deadline,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.auth.is_none() || self.user.is_some()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.auth.is_some()
+    }
+
+    // who is logged in, None when auth is off (or nobody yet). Requests are authorized
+    // against it in net::dispatch
+    pub fn principal(&self) -> Option<Principal> {
+        match (&self.auth, &self.user) {
+            (Some(auth), Some(user)) => Some(Principal::new(auth.clone(), user.clone())),
+            _ => None,
+        }
+    }
+
+    // when the connection has to be authenticated by, None once logged in (or auth is off)
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.is_open() {
+            None
+        } else {
+            self.deadline
+        }
+    }
+
+    // a failed attempt keeps the previous login, like redis AUTH
+    pub async fn login(&mut self, user: &str, secret: &str) -> Result<(), String> {
+        let Some(auth) = &self.auth else {
+            return Err("authentication is not enabled on this server".to_string());
+        };
+        auth.authenticate(self.peer, user, secret).await?;
+        self.user = Some(user.to_string());
+        Ok(())
+    }
+
+    // mutual tls: a verified client certificate naming a known user logs the connection in
+    pub fn login_with_certificate(&mut self, user: &str) -> bool {
+        let known = self.auth.as_ref().is_some_and(|auth| auth.acl(user).is_some());
+        if known {
+            self.user = Some(user.to_string());
+        }
+        known
+    }
+
+    // for the json protocol: Some(resp) = the gate answered the request itself (an Auth, or a
+    // refusal because the connection isn't logged in yet), None = go ahead and dispatch it
+    pub async fn check(&mut self, req: &Request) -> Option<Response> {
+        if !self.enabled() {
+            return None;
+        }
+        match req {
+            Request::Auth { user, secret } => Some(match self.login(user, secret).await {
+                Ok(()) => Response::Ok,
+                Err(message) => Response::Error { message },
+            }),
+            _ if !self.is_open() => Some(Response::Error {
+                message: "authentication required".to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// client side: send Auth on an already negotiated connection and wait for the Ok
+pub async fn login<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    framing: Framing,
+    user: &str,
+    secret: &str,
+) -> std::io::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let frame = RequestFrame::from(Request::Auth {
+        user: user.to_string(),
+        secret: secret.to_string(),
+    });
+    writer
+        .write_all(&encode(framing, &frame).map_err(std::io::Error::other)?)
+        .await?;
+
+    let mut buf = Vec::new();
+    let reply: ResponseFrame = match read_frame(reader, framing, &mut buf).await? {
+        Some(Ok(reply)) => reply,
+        Some(Err(e)) => return Err(std::io::Error::other(format!("bad auth reply: {e}"))),
+        None => return Err(std::io::Error::other("server closed during auth")),
+    };
+
+    match reply.resp {
+        Response::Ok => Ok(()),
+        Response::Error { message } => Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            message,
+        )),
+        other => Err(std::io::Error::other(format!(
+            "unexpected auth reply: {other:?}"
+        ))),
+    }
+}
@@ -0,0 +1,238 @@
+use std::io::{BufRead, Read, Write};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use crate::net::protocol::Framing;
+
+// a length prefix is attacker controlled, never allocate more than this for one frame
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+// encoding only lives here, the message types stay in net::protocol
+//
+// Json:    {"kind":"get","key":"a"}\n
+// Msgpack: [u32 len BE][msgpack map with the same field names]
+// MsgpackDeflate: [u32 len BE][deflate(msgpack map)]
+pub fn encode<T: Serialize>(framing: Framing, msg: &T) -> Result<Vec<u8>, String> {
+    match framing {
+        Framing::Json => {
+            let mut bytes = serde_json::to_vec(msg).map_err(|e| e.to_string())?;
+            bytes.push(b'\n');
+            Ok(bytes)
+        }
+        Framing::Msgpack | Framing::MsgpackDeflate => {
+            // named = maps instead of arrays, needed for the tagged/flattened protocol enums
+            let mut body = rmp_serde::to_vec_named(msg).map_err(|e| e.to_string())?;
+            if framing == Framing::MsgpackDeflate {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+                encoder.write_all(&body).map_err(|e| e.to_string())?;
+                body = encoder.finish().map_err(|e| e.to_string())?;
+            }
+            let mut bytes = Vec::with_capacity(4 + body.len());
+            bytes.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&body);
+            Ok(bytes)
+        }
+    }
+}
+
+// Ok(None) = clean EOF, Ok(Some(Err)) = the frame arrived but didn't decode (connection is
+// still usable), Err = transport broken / oversized frame
+pub async fn read_frame<R, T>(
+    reader: &mut R,
+    framing: Framing,
+    buf: &mut Vec<u8>,
+) -> std::io::Result<Option<Result<T, String>>>
+where
+    R: AsyncBufRead + Unpin,
+    T: DeserializeOwned,
+{
+    read_frame_limited(reader, framing, buf, MAX_FRAME_LEN).await
+}
+
+// read_frame with a caller chosen size limit (the server's --max-request-bytes). A json line
+// longer than max_len is refused before it is buffered, not after. Err(FrameTooLarge) = too
+// big (see is_frame_too_large), the stream is in the middle of that frame so the connection has
+// to go
+pub async fn read_frame_limited<R, T>(
+    reader: &mut R,
+    framing: Framing,
+    buf: &mut Vec<u8>,
+    max_len: usize,
+) -> std::io::Result<Option<Result<T, String>>>
+where
+    R: AsyncBufRead + Unpin,
+    T: DeserializeOwned,
+{
+    match framing {
+        Framing::Json => loop {
+            buf.clear();
+            let n = read_line_limited(reader, buf, max_len).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+
+            let line = buf.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+            return Ok(Some(
+                serde_json::from_slice(line).map_err(|e| e.to_string()),
+            ));
+        },
+        Framing::Msgpack | Framing::MsgpackDeflate => {
+            let mut len_buf = [0u8; 4];
+            match reader.read_exact(&mut len_buf).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+
+            let len = u32::from_be_bytes(len_buf) as usize;
+            if len > max_len {
+                return Err(too_large(format!(
+                    "frame of {len} bytes exceeds limit of {max_len}"
+                )));
+            }
+
+            buf.resize(len, 0);
+            reader.read_exact(buf).await?;
+            Ok(Some(decode_packed(framing, buf, max_len)))
+        }
+    }
+}
+
+// read_frame for std::io readers (the blocking client), same framing and frame limit
+pub fn read_frame_blocking<R, T>(
+    reader: &mut R,
+    framing: Framing,
+    buf: &mut Vec<u8>,
+) -> std::io::Result<Option<Result<T, String>>>
+where
+    R: std::io::BufRead,
+    T: DeserializeOwned,
+{
+    match framing {
+        Framing::Json => loop {
+            buf.clear();
+            // one byte past the limit, so an oversized line is refused without buffering it all
+            let n = (&mut *reader)
+                .take(MAX_FRAME_LEN as u64 + 2)
+                .read_until(b'\n', buf)?;
+            if n == 0 {
+                return Ok(None);
+            }
+            if buf.trim_ascii_end().len() > MAX_FRAME_LEN {
+                return Err(too_large(format!(
+                    "line exceeds limit of {MAX_FRAME_LEN} bytes"
+                )));
+            }
+
+            let line = buf.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+            return Ok(Some(
+                serde_json::from_slice(line).map_err(|e| e.to_string()),
+            ));
+        },
+        Framing::Msgpack | Framing::MsgpackDeflate => {
+            let mut len_buf = [0u8; 4];
+            match reader.read_exact(&mut len_buf) {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+
+            let len = u32::from_be_bytes(len_buf) as usize;
+            if len > MAX_FRAME_LEN {
+                return Err(too_large(format!(
+                    "frame of {len} bytes exceeds limit of {MAX_FRAME_LEN}"
+                )));
+            }
+
+            buf.resize(len, 0);
+            reader.read_exact(buf)?;
+            Ok(Some(decode_packed(framing, buf, MAX_FRAME_LEN)))
+        }
+    }
+}
+
+// the body of a msgpack frame, inflated first for MsgpackDeflate
+fn decode_packed<T: DeserializeOwned>(
+    framing: Framing,
+    buf: &[u8],
+    max_len: usize,
+) -> Result<T, String> {
+    if framing == Framing::Msgpack {
+        return rmp_serde::from_slice(buf).map_err(|e| e.to_string());
+    }
+
+    // the inflated size is attacker controlled too, stop one byte past the limit
+    let mut body = Vec::new();
+    let inflated = DeflateDecoder::new(buf)
+        .take(max_len as u64 + 1)
+        .read_to_end(&mut body);
+    match inflated {
+        Ok(n) if n > max_len => Err(format!(
+            "inflated frame exceeds limit of {max_len} bytes"
+        )),
+        Ok(_) => rmp_serde::from_slice(&body).map_err(|e| e.to_string()),
+        Err(e) => Err(format!("bad deflate body: {e}")),
+    }
+}
+
+// the io error payload for an oversized frame, so callers can tell it from a broken stream
+#[derive(Debug)]
+pub struct FrameTooLarge(pub String);
+
+impl std::fmt::Display for FrameTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for FrameTooLarge {}
+
+fn too_large(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, FrameTooLarge(message))
+}
+
+pub fn is_frame_too_large(e: &std::io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<FrameTooLarge>())
+}
+
+// read_until('\n') that stops once the line (newline not counted) gets longer than max_len
+async fn read_line_limited<R>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max_len: usize,
+) -> std::io::Result<usize>
+where
+    R: AsyncBufRead + Unpin,
+{
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(buf.len()); // EOF, possibly after a partial line
+        }
+
+        let (done, used) = match available.iter().position(|b| *b == b'\n') {
+            Some(i) => (true, i + 1),
+            None => (false, available.len()),
+        };
+        let line_len = buf.len() + used - usize::from(done);
+        if line_len > max_len {
+            return Err(too_large(format!(
+                "request line exceeds limit of {max_len} bytes"
+            )));
+        }
+
+        buf.extend_from_slice(&available[..used]);
+        reader.consume(used);
+        if done {
+            return Ok(buf.len());
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, Semaphore};
+use tracing::{debug, info_span, Instrument};
+
+use crate::{
+    engine::handler::EngineHandle,
+    metrics::METRICS,
+    net::{
+        acl::{anonymous, authorize, classify, AdminPolicy, CommandClass, Principal},
+        limits::COUNTERS,
+        protocol::{Info, Request, RequestFrame, Response, ResponseFrame},
+    },
+};
+
+// upper bound on concurrently running id'd requests per connection. once reached the
+// transport stops reading the socket until one finishes (backpressure instead of unbounded tasks)
+pub const MAX_IN_FLIGHT: usize = 128;
+
+// requests carrying an id run on their own task and may answer out of order (pipelining).
+// requests without an id run inline, so id-less clients keep the old strict request/response order
+pub async fn dispatch_pipelined(
+    handle: &EngineHandle,
+    frame: RequestFrame,
+    out_tx: &mpsc::Sender<ResponseFrame>,
+    in_flight: &Arc<Semaphore>,
+    principal: Option<Principal>,
+    subscriptions: &Arc<Semaphore>,
+    admin: &Arc<AdminPolicy>,
+) {
+    if frame.id.is_none() {
+        dispatch(handle, frame, out_tx, principal, subscriptions, admin).await;
+        return;
+    }
+
+    let Ok(permit) = in_flight.clone().acquire_owned().await else {
+        return; // semaphore closed, connection is shutting down
+    };
+    let handle = handle.clone();
+    let out_tx = out_tx.clone();
+    let subscriptions = subscriptions.clone();
+    let admin = admin.clone();
+    tokio::spawn(
+        async move {
+            dispatch(&handle, frame, &out_tx, principal, &subscriptions, &admin).await;
+            drop(permit);
+        }
+        .in_current_span(), // the connection's span
+    );
+}
+
+// shared by every transport (tcp, websocket): runs one request against the engine and pushes
+// the response(s) into the connection's outbound channel. The transport only owns framing.
+// the request id (if any) is copied onto every response it produces, including subscription events.
+// `principal` is the logged in user (None = auth disabled); its acl is checked before the engine
+// is touched. `subscriptions` holds one permit per subscription the connection may still open
+// (ServerLimits::max_subscriptions), each forwarder keeps its permit until it ends. `admin` decides
+// what a connection without a principal may do to the whole database (acl::anonymous).
+// Runs inside a `request` span carrying the client's trace_id; the engine hands that span on
+// through its actors (see WriteCommand), so a write's log lines all end up under it
+pub async fn dispatch(
+    handle: &EngineHandle,
+    frame: RequestFrame,
+    out_tx: &mpsc::Sender<ResponseFrame>,
+    principal: Option<Principal>,
+    subscriptions: &Arc<Semaphore>,
+    admin: &AdminPolicy,
+) {
+    let span = info_span!(
+        "request",
+        id = frame.id,
+        kind = frame.req.name(),
+        key = classify(&frame.req).and_then(|(_, key)| key),
+        trace_id = frame.trace_id.as_deref(),
+    );
+    async {
+        run(handle, frame, out_tx, principal, subscriptions, admin).await;
+        debug!("done");
+    }
+    .instrument(span)
+    .await
+}
+
+async fn run(
+    handle: &EngineHandle,
+    frame: RequestFrame,
+    out_tx: &mpsc::Sender<ResponseFrame>,
+    principal: Option<Principal>,
+    subscriptions: &Arc<Semaphore>,
+    admin: &AdminPolicy,
+) {
+    let id = frame.id;
+    let reply = |resp: Response| ResponseFrame { id, resp };
+    // until the (first) response is queued; a subscription's events aren't part of it
+    let _timer = METRICS.command_timer(frame.req.name());
+
+    if let Some((class, key)) = classify(&frame.req) {
+        let allowed = match &principal {
+            Some(principal) => principal.authorize(class, key),
+            None => anonymous(&frame.req, admin),
+        };
+        if let Err(message) = allowed {
+            debug!(reason = message, "denied");
+            let _ = out_tx.send(reply(Response::Error { message })).await;
+            return;
+        }
+    }
+
+    match frame.req {
+        Request::Set { key, value } => {
+            let resp: Response = match handle.set(key, value).await {
+                Ok(()) => Response::Ok,
+                Err(message) => Response::Error { message },
+            };
+            let _ = out_tx.send(reply(resp)).await;
+        }
+        Request::Get { key } => {
+            let resp = match handle.get(key).await {
+                Ok(doc) => Response::Value { doc },
+                Err(message) => Response::Error { message },
+            };
+            let _ = out_tx.send(reply(resp)).await;
+        }
+        Request::Del { key } => {
+            let resp = match handle.delete(key).await {
+                Ok(()) => Response::Ok,
+                Err(message) => Response::Error { message },
+            };
+            let _ = out_tx.send(reply(resp)).await;
+        }
+        Request::Patch { key, delta } => {
+            let resp = match handle.patch(key, delta).await {
+                Ok(()) => Response::Ok,
+                Err(message) => Response::Error { message },
+            };
+            let _ = out_tx.send(reply(resp)).await;
+        }
+        Request::Snapshot => {
+            let resp = match handle.snapshot().await {
+                Ok(()) => Response::Ok,
+                Err(message) => Response::Error { message },
+            };
+            let _ = out_tx.send(reply(resp)).await;
+        }
+        Request::Subscribe { key } => {
+            let Ok(slot) = subscriptions.clone().try_acquire_owned() else {
+                COUNTERS.subscription_rejected();
+                let message = "subscription limit reached for this connection".to_string();
+                let _ = out_tx.send(reply(Response::Error { message })).await;
+                return;
+            };
+            let mut sub_rx = match handle.subscribe(key.clone()).await {
+                Ok(sub_rx) => sub_rx,
+                Err(message) => {
+                    let _ = out_tx.send(reply(Response::Error { message })).await;
+                    return;
+                }
+            };
+            let _ = out_tx.send(reply(Response::Subscribed { key })).await;
+
+            // events go through the same outbound channel, so a slow client fills it up and
+            // the forwarder stops draining sub_rx -> Reactivity evicts the subscriber
+            let sub_tx = out_tx.clone();
+            tokio::spawn(
+                async move {
+                    while let Some(event) = sub_rx.recv().await {
+                        // re-checked per event so a reloaded acl also ends open subscriptions
+                        if let Err(message) =
+                            authorize(principal.as_ref(), CommandClass::Subscribe, Some(&event.key))
+                        {
+                            let resp = Response::Error { message };
+                            let _ = sub_tx.send(ResponseFrame { id, resp }).await;
+                            break;
+                        }
+                        let frame = ResponseFrame {
+                            id,
+                            resp: Response::Event { event },
+                        };
+                        if sub_tx.send(frame).await.is_err() {
+                            break;
+                        }
+                    }
+                    drop(slot); // frees the subscription for the connection's cap
+                }
+                .in_current_span(),
+            );
+        }
+        Request::Stats => {
+            let stats = COUNTERS.snapshot();
+            let _ = out_tx.send(reply(Response::Stats { stats })).await;
+        }
+        Request::DbSize => {
+            let size = match &principal {
+                Some(p) if !p.sees_every_key() => handle.keys(None).await.map(|mut keys| {
+                    keys.retain(|key| p.can_access(CommandClass::Read, key));
+                    keys.len() as u64
+                }),
+                _ => handle.db_size().await,
+            };
+            let resp = match size {
+                Ok(size) => Response::DbSize { size },
+                Err(message) => Response::Error { message },
+            };
+            let _ = out_tx.send(reply(resp)).await;
+        }
+        Request::Keys { pattern, limit } => {
+            let resp = match handle.keys(pattern).await {
+                Ok(mut keys) => {
+                    if let Some(p) = &principal {
+                        keys.retain(|key| p.can_access(CommandClass::Read, key));
+                    }
+                    let limit = limit.unwrap_or(usize::MAX);
+                    let truncated = keys.len() > limit;
+                    keys.truncate(limit);
+                    Response::Keys { keys, truncated }
+                }
+                Err(message) => Response::Error { message },
+            };
+            let _ = out_tx.send(reply(resp)).await;
+        }
+        Request::FlushAll => {
+            let resp = match handle.flush_all().await {
+                Ok(()) => Response::Ok,
+                Err(message) => Response::Error { message },
+            };
+            let _ = out_tx.send(reply(resp)).await;
+        }
+        Request::Backup { dest_dir } => {
+            let backup = match admin.backup_dest(&dest_dir) {
+                Ok(dest) => handle.backup(dest).await,
+                Err(message) => Err(message),
+            };
+            let resp = match backup {
+                Ok(manifest) => Response::Backup { manifest },
+                Err(message) => Response::Error { message },
+            };
+            let _ = out_tx.send(reply(resp)).await;
+        }
+        Request::Info => {
+            let resp = match handle.info().await {
+                Ok(engine) => Response::Info {
+                    info: Info {
+                        clients: COUNTERS.snapshot().connections_open,
+                        engine,
+                    },
+                },
+                Err(message) => Response::Error { message },
+            };
+            let _ = out_tx.send(reply(resp)).await;
+        }
+        // with auth enabled the connection's Gate answers these before they get here
+        Request::Auth { .. } => {
+            let message = "authentication is not enabled on this server".to_string();
+            let _ = out_tx.send(reply(Response::Error { message })).await;
+        }
+        Request::Hello { .. } => {
+            let message = "hello must be the first request on a connection".to_string();
+            let _ = out_tx.send(reply(Response::Error { message })).await;
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt};
+
+use crate::net::{
+    codec::{encode, read_frame},
+    protocol::{Capability, Framing, Limits, Request, RequestFrame, Response, ResponseFrame},
+};
+
+// bump on any change an older peer can't ignore (new required field, changed meaning).
+// new optional fields / variants / capabilities don't need a bump, they are negotiated
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+// what a connection runs with after the opening exchange
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Negotiated {
+    pub framing: Framing,
+    pub pipelining: bool,
+}
+
+impl Negotiated {
+    // clients that never send Hello get what the protocol did before the handshake existed:
+    // json lines, id'd requests pipelined
+    pub fn legacy() -> Self {
+        Self {
+            framing: Framing::Json,
+            pipelining: true,
+        }
+    }
+
+    pub fn from_capabilities(capabilities: &[Capability]) -> Self {
+        let msgpack = capabilities.contains(&Capability::Msgpack);
+        let deflate = capabilities.contains(&Capability::Deflate);
+        let framing = match (msgpack, deflate) {
+            (true, true) => Framing::MsgpackDeflate,
+            (true, false) => Framing::Msgpack,
+            _ => Framing::Json,
+        };
+        Self {
+            framing,
+            pipelining: capabilities.contains(&Capability::Pipelining),
+        }
+    }
+}
+
+// server side: answer a Hello. `offered` is what the transport supports (websocket has its own
+// framing, so it only offers pipelining). `limits` are the server's configured ones
+// (ServerLimits::wire). Err = unsupported version, the caller sends it as an Error and closes
+// the connection
+pub fn welcome(
+    protocol_version: u32,
+    wanted: &[Capability],
+    offered: &[Capability],
+    limits: Limits,
+) -> Result<(Negotiated, Response), String> {
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&protocol_version) {
+        return Err(format!(
+            "unsupported protocol version {protocol_version}, server speaks {MIN_PROTOCOL_VERSION}..={PROTOCOL_VERSION}"
+        ));
+    }
+
+    let mut granted: Vec<Capability> = Vec::new();
+    for cap in wanted {
+        if *cap != Capability::Unknown && offered.contains(cap) && !granted.contains(cap) {
+            granted.push(*cap);
+        }
+    }
+    // deflate only exists as a wrapper around msgpack frames
+    if !granted.contains(&Capability::Msgpack) {
+        granted.retain(|cap| *cap != Capability::Deflate);
+    }
+
+    let negotiated = Negotiated::from_capabilities(&granted);
+    let resp = Response::Welcome {
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol_version,
+        capabilities: granted,
+        limits,
+    };
+    Ok((negotiated, resp))
+}
+
+// client side: send Hello as a json line and wait for the Welcome (also json). Everything
+// after it uses the framing the server granted, which may be less than what was asked for
+pub async fn hello<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    client_name: &str,
+    capabilities: &[Capability],
+) -> std::io::Result<Negotiated>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let hello = RequestFrame::from(Request::Hello {
+        protocol_version: PROTOCOL_VERSION,
+        client_name: Some(client_name.to_string()),
+        capabilities: capabilities.to_vec(),
+    });
+    let bytes = encode(Framing::Json, &hello).map_err(std::io::Error::other)?;
+    writer.write_all(&bytes).await?;
+
+    let mut buf = Vec::new();
+    let reply: ResponseFrame = match read_frame(reader, Framing::Json, &mut buf).await? {
+        Some(Ok(reply)) => reply,
+        Some(Err(e)) => return Err(std::io::Error::other(format!("bad welcome reply: {e}"))),
+        None => return Err(std::io::Error::other("server closed during handshake")),
+    };
+
+    match reply.resp {
+        Response::Welcome { capabilities, .. } => Ok(Negotiated::from_capabilities(&capabilities)),
+        Response::Error { message } => Err(std::io::Error::other(message)),
+        other => Err(std::io::Error::other(format!(
+            "unexpected handshake reply: {other:?}"
+        ))),
+    }
+}
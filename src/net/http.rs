@@ -0,0 +1,406 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    body::{Bytes, HttpBody},
+    extract::{ConnectInfo, DefaultBodyLimit, Extension, MatchedPath, Path, RawPathParams, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    middleware::{self, Next},
+    routing::{get, post},
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::Stream;
+use serde_json::{json, Value};
+use hyper::server::conn::http1;
+use hyper_util::{
+    rt::{TokioIo, TokioTimer},
+    service::TowerToHyperService,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::{
+    engine::handler::EngineHandle,
+    interface::command::WriteError,
+    net::{
+        acl::{authorize, CommandClass, Principal},
+        auth::Authenticator,
+        limits::{ConnectionLimiter, ServerLimits, COUNTERS, TOO_MANY_CONNECTIONS},
+        ratelimit::ConnectionRate,
+    },
+};
+
+// HTTP/REST front-end over the same EngineHandle the tcp server uses
+//
+//   GET    /kv/{key}     -> 200 {value, version} + ETag | 404
+//   PUT    /kv/{key}     -> 204 | 400 bad json | 409 If-Match mismatch
+//   PATCH  /kv/{key}     -> 204 | 400 | 409
+//   DELETE /kv/{key}     -> 204 | 404 | 409
+//   POST   /snapshot     -> 204
+//   GET    /watch/{key}  -> text/event-stream of Event json
+pub fn router(handle: EngineHandle) -> Router {
+    Router::new()
+        .route(
+            "/kv/{key}",
+            get(get_key)
+                .put(put_key)
+                .patch(patch_key)
+                .delete(delete_key),
+        )
+        .route("/snapshot", post(snapshot))
+        .route("/watch/{key}", get(watch_key))
+        .with_state(handle)
+}
+
+// with an Authenticator every request needs `Authorization: Basic base64(user:secret)`.
+// http is stateless, so there is no login timeout here: each request is checked on its own
+pub async fn serve(
+    listener: TcpListener,
+    handle: EngineHandle,
+    auth: Option<Arc<Authenticator>>,
+) -> std::io::Result<()> {
+    let limits = ServerLimits::default();
+    let limiter = ConnectionLimiter::new(limits.max_connections);
+    serve_with(listener, handle, auth, limits, limiter).await
+}
+
+// connections draw from the server's shared --max-connections pool, extra ones get a 503. The
+// read timeout bounds the request head (hyper's header timer, which also runs while a
+// keep-alive connection waits, so an idle one goes after the shorter of the two timeouts) and
+// the handler, body included
+pub async fn serve_with(
+    listener: TcpListener,
+    handle: EngineHandle,
+    auth: Option<Arc<Authenticator>>,
+    limits: ServerLimits,
+    limiter: ConnectionLimiter,
+) -> std::io::Result<()> {
+    // route_layer: runs after routing, so the acl and rate checks can see which route and key
+    // it is. The last one added runs first: auth, then the rate check with its principal
+    let mut app = router(handle).route_layer(middleware::from_fn(rate_limit));
+    if let Some(auth) = auth {
+        app = app.route_layer(middleware::from_fn_with_state(auth, require_auth));
+    }
+    if let Some(read_timeout) = limits.read_timeout {
+        app = app.layer(middleware::from_fn_with_state(read_timeout, request_timeout));
+    }
+    // bodies are capped like a request on the other transports instead of axum's 2 MiB default
+    let app = app
+        .layer(DefaultBodyLimit::max(limits.max_request_bytes))
+        .layer(middleware::from_fn(count_too_large));
+    let header_timeout = match (limits.idle_timeout, limits.read_timeout) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!(error = %e, "http accept failed");
+                continue;
+            }
+        };
+        let _ = stream.set_nodelay(true);
+        let slot = limiter.try_acquire();
+        let app = app.clone();
+
+        let span = info_span!("connection", transport = "http", peer = %peer);
+        tokio::spawn(
+            async move {
+                let Some(_slot) = slot else {
+                    let body = json!({ "error": TOO_MANY_CONNECTIONS }).to_string();
+                    let resp = format!(
+                        "HTTP/1.1 503 Service Unavailable\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(resp.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                    // closing with the request unread would reset the connection, and the
+                    // client might never see the 503
+                    let mut buf = [0u8; 1024];
+                    let drain = async { while matches!(stream.read(&mut buf).await, Ok(n) if n > 0) {} };
+                    let _ = tokio::time::timeout(Duration::from_secs(1), drain).await;
+                    return;
+                };
+                // keep-alive requests share the connection's budget, like a tcp connection's
+                let rate = Arc::new(ConnectionRate::new(limits.conn_rate));
+                let app = app.layer(Extension(ConnectInfo(peer))).layer(Extension(rate));
+                let service = TowerToHyperService::new(app);
+                let mut builder = http1::Builder::new();
+                builder.timer(TokioTimer::new()).header_read_timeout(header_timeout);
+                let conn = builder.serve_connection(TokioIo::new(stream), service);
+                if let Err(e) = conn.with_upgrades().await {
+                    info!(error = %e, "connection closed with error");
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
+
+// a request that doesn't get its response started within the read timeout, e.g. a body sent
+// too slowly. A watch responds right away, its stream isn't limited
+async fn request_timeout(State(read_timeout): State<Duration>, req: Request, next: Next) -> Response {
+    match tokio::time::timeout(read_timeout, next.run(req)).await {
+        Ok(resp) => resp,
+        Err(_) => {
+            COUNTERS.read_timeout();
+            error_response(StatusCode::REQUEST_TIMEOUT, "read timeout")
+        }
+    }
+}
+
+// the 413s the body limit answers with, for stats
+async fn count_too_large(req: Request, next: Next) -> Response {
+    let resp = next.run(req).await;
+    if resp.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        COUNTERS.request_too_large();
+    }
+    resp
+}
+
+async fn require_auth(
+    State(auth): State<Arc<Authenticator>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    route: MatchedPath,
+    params: RawPathParams,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let Some((user, secret)) = basic_credentials(req.headers()) else {
+        return unauthorized("authentication required");
+    };
+    if let Err(message) = auth.authenticate(peer.ip(), &user, &secret).await {
+        return unauthorized(&message);
+    }
+
+    let class = route_class(&route, req.method());
+    let key = params
+        .iter()
+        .find(|(name, _)| *name == "key")
+        .map(|(_, key)| key);
+    let principal = Principal::new(auth, user);
+    if let Err(message) = principal.authorize(class, key) {
+        return error_response(StatusCode::FORBIDDEN, &message);
+    }
+
+    // a watch outlives this check, it re-checks with the principal per event
+    req.extensions_mut().insert(principal);
+    next.run(req).await
+}
+
+fn route_class(route: &MatchedPath, method: &Method) -> CommandClass {
+    match (route.as_str(), method) {
+        ("/kv/{key}", &Method::GET) => CommandClass::Read,
+        ("/kv/{key}", _) => CommandClass::Write,
+        ("/watch/{key}", _) => CommandClass::Subscribe,
+        _ => CommandClass::Admin,
+    }
+}
+
+// the connection's and the user's budgets (net::ratelimit), 429 when over. Writes are charged
+// their Content-Length up front, reads the size of the body that goes back
+async fn rate_limit(
+    Extension(rate): Extension<Arc<ConnectionRate>>,
+    principal: Option<Extension<Principal>>,
+    route: MatchedPath,
+    req: Request,
+    next: Next,
+) -> Response {
+    let class = route_class(&route, req.method());
+    let request_bytes = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse().ok())
+        .unwrap_or(0);
+    let principal = principal.map(|Extension(p)| p);
+    if let Err(retry_after_ms) = rate.admit(principal, class, request_bytes) {
+        let body = json!({ "error": "rate limited", "retry_after_ms": retry_after_ms });
+        let retry_after = retry_after_ms.div_ceil(1000).to_string();
+        return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after)], Json(body))
+            .into_response();
+    }
+
+    let resp = next.run(req).await;
+    if class == CommandClass::Read {
+        if let Some(n) = resp.body().size_hint().exact() {
+            rate.charge_read_bytes(n as usize);
+        }
+    }
+    resp
+}
+
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let encoded = value.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(BASE64.decode(encoded.trim()).ok()?).ok()?;
+    let (user, secret) = decoded.split_once(':')?;
+    Some((user.to_string(), secret.to_string()))
+}
+
+fn unauthorized(message: &str) -> Response {
+    let mut resp = error_response(StatusCode::UNAUTHORIZED, message);
+    resp.headers_mut().insert(
+        header::WWW_AUTHENTICATE,
+        header::HeaderValue::from_static("Basic realm=\"fluxdb\""),
+    );
+    resp
+}
+
+async fn get_key(State(handle): State<EngineHandle>, Path(key): Path<String>) -> Response {
+    match handle.get(key).await {
+        Ok(Some(doc)) => {
+            ([(header::ETAG, format!("\"{}\"", doc.version))], Json(doc)).into_response()
+        }
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "key not found"),
+        Err(message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &message),
+    }
+}
+
+async fn put_key(
+    State(handle): State<EngineHandle>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let expected = match expected_version(&handle, &key, &headers).await {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+    let value = match serde_json::from_slice::<Value>(&body) {
+        Ok(v) => v,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("invalid json: {e}")),
+    };
+
+    write_response(handle.set_if_version(key, value, expected).await)
+}
+
+async fn patch_key(
+    State(handle): State<EngineHandle>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let expected = match expected_version(&handle, &key, &headers).await {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+    let delta = match serde_json::from_slice::<Value>(&body) {
+        Ok(v) => v,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("invalid json: {e}")),
+    };
+
+    write_response(handle.patch_if_version(key, delta, expected).await)
+}
+
+async fn delete_key(
+    State(handle): State<EngineHandle>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let expected = match expected_version(&handle, &key, &headers).await {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+
+    // without If-Match, pin the delete to the version we just saw so a missing key is a 404
+    // instead of a tombstone write
+    let expected = match expected {
+        Some(v) => Some(v),
+        None => match handle.get(key.clone()).await {
+            Ok(Some(doc)) => Some(doc.version),
+            Ok(None) => return error_response(StatusCode::NOT_FOUND, "key not found"),
+            Err(message) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, &message),
+        },
+    };
+
+    write_response(handle.delete_if_version(key, expected).await)
+}
+
+async fn snapshot(State(handle): State<EngineHandle>) -> Response {
+    match handle.snapshot().await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &message),
+    }
+}
+
+async fn watch_key(
+    State(handle): State<EngineHandle>,
+    Path(key): Path<String>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, Response> {
+    let rx = handle
+        .subscribe(key)
+        .await
+        .map_err(|message| error_response(StatusCode::INTERNAL_SERVER_ERROR, &message))?;
+    let principal = principal.map(|Extension(p)| p);
+
+    // the stream ends when Reactivity evicts us (slow reader) or the engine shuts down. The
+    // acl is re-checked per event so a reloaded auth file also ends open watches, with an
+    // error event
+    let stream = futures::stream::unfold(Some((rx, principal)), |state| async move {
+        let (mut rx, principal) = state?;
+        let event = rx.recv().await?;
+        if let Err(message) = authorize(principal.as_ref(), CommandClass::Subscribe, Some(&event.key)) {
+            let sse = SseEvent::default().event("error").data(message);
+            return Some((Ok(sse), None));
+        }
+        let sse = SseEvent::default()
+            .event("event")
+            .json_data(&event)
+            .unwrap_or_else(|_| SseEvent::default().event("error"));
+        Some((Ok(sse), Some((rx, principal))))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))))
+}
+
+fn write_response(result: Result<(), WriteError>) -> Response {
+    match result {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e @ WriteError::VersionConflict { .. }) => {
+            error_response(StatusCode::CONFLICT, &e.to_string())
+        }
+        Err(WriteError::Failed(message)) => {
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, &message)
+        }
+    }
+}
+
+// If-Match: "3" (or W/"3", or a bare 3) -> Some(3). If-Match: * means the key must exist: the
+// write is pinned to the version it has now, 409 if it has none
+async fn expected_version(
+    handle: &EngineHandle,
+    key: &str,
+    headers: &HeaderMap,
+) -> Result<Option<u64>, Response> {
+    let Some(raw) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let raw = raw.to_str().map(str::trim).unwrap_or_default();
+    if raw == "*" {
+        return match handle.get(key.to_string()).await {
+            Ok(Some(doc)) => Ok(Some(doc.version)),
+            Ok(None) => Err(error_response(StatusCode::CONFLICT, "If-Match: * but the key does not exist")),
+            Err(message) => Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, &message)),
+        };
+    }
+
+    raw.trim_start_matches("W/")
+        .trim_matches('"')
+        .parse::<u64>()
+        .map(Some)
+        .map_err(|_| error_response(StatusCode::BAD_REQUEST, "If-Match must be a version number or *"))
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
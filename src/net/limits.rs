@@ -0,0 +1,165 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::net::{
+    codec::MAX_FRAME_LEN,
+    dispatch::MAX_IN_FLIGHT,
+    protocol::{Limits, Stats},
+    ratelimit::Budget,
+};
+
+// per server knobs for the listeners. All of them apply to every listener, except that http
+// holds no subscriptions on a connection to cap. Set from the server's command line
+#[derive(Debug, Clone, Copy)]
+pub struct ServerLimits {
+    pub max_request_bytes: usize, // one json line / msgpack frame / websocket message / resp command / http body
+    pub max_connections: usize, // shared by all listeners, extra ones get an error and are closed
+    pub max_subscriptions: usize, // per connection
+    pub idle_timeout: Option<Duration>, // no request started for this long (connections with subscriptions are exempt)
+    pub read_timeout: Option<Duration>, // a started request has to arrive completely within this
+    pub conn_rate: Budget, // per connection, users can have their own on top (auth file)
+}
+
+impl Default for ServerLimits {
+    fn default() -> Self {
+        Self {
+            max_request_bytes: MAX_FRAME_LEN,
+            max_connections: 1024,
+            max_subscriptions: 1024,
+            idle_timeout: Some(Duration::from_secs(300)),
+            read_timeout: Some(Duration::from_secs(30)),
+            conn_rate: Budget::default(),
+        }
+    }
+}
+
+impl ServerLimits {
+    // what the client is told in Welcome
+    pub fn wire(&self) -> Limits {
+        Limits {
+            max_frame_len: self.max_request_bytes,
+            max_in_flight: MAX_IN_FLIGHT,
+            max_subscriptions: self.max_subscriptions,
+        }
+    }
+}
+
+// max_connections across the listeners that share it. A slot is held for the lifetime of the
+// connection task
+#[derive(Clone)]
+pub struct ConnectionLimiter {
+    slots: Arc<Semaphore>,
+}
+
+pub struct ConnectionSlot {
+    _permit: OwnedSemaphorePermit,
+    _open: OpenConnection,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_connections: usize) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(max_connections)),
+        }
+    }
+
+    // None = full, the caller answers with TOO_MANY_CONNECTIONS and closes
+    pub fn try_acquire(&self) -> Option<ConnectionSlot> {
+        match self.slots.clone().try_acquire_owned() {
+            Ok(permit) => Some(ConnectionSlot {
+                _permit: permit,
+                _open: COUNTERS.connection_opened(),
+            }),
+            Err(_) => {
+                COUNTERS.connection_rejected();
+                None
+            }
+        }
+    }
+}
+
+pub const TOO_MANY_CONNECTIONS: &str = "too many connections, try again later";
+
+// process wide counters, read through the `stats` request
+pub struct Counters {
+    connections_open: AtomicU64,
+    connections_total: AtomicU64,
+    connections_rejected: AtomicU64,
+    requests_too_large: AtomicU64,
+    idle_timeouts: AtomicU64,
+    read_timeouts: AtomicU64,
+    subscriptions_rejected: AtomicU64,
+    rate_limited: AtomicU64,
+}
+
+pub static COUNTERS: Counters = Counters {
+    connections_open: AtomicU64::new(0),
+    connections_total: AtomicU64::new(0),
+    connections_rejected: AtomicU64::new(0),
+    requests_too_large: AtomicU64::new(0),
+    idle_timeouts: AtomicU64::new(0),
+    read_timeouts: AtomicU64::new(0),
+    subscriptions_rejected: AtomicU64::new(0),
+    rate_limited: AtomicU64::new(0),
+};
+
+// keeps connections_open right however the connection task ends
+pub struct OpenConnection(());
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        COUNTERS.connections_open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Counters {
+    pub fn connection_opened(&self) -> OpenConnection {
+        self.connections_open.fetch_add(1, Ordering::Relaxed);
+        self.connections_total.fetch_add(1, Ordering::Relaxed);
+        OpenConnection(())
+    }
+
+    pub fn connection_rejected(&self) {
+        self.connections_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn request_too_large(&self) {
+        self.requests_too_large.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn idle_timeout(&self) {
+        self.idle_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn read_timeout(&self) {
+        self.read_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn subscription_rejected(&self) {
+        self.subscriptions_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rate_limited(&self) {
+        self.rate_limited.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Stats {
+        Stats {
+            connections_open: self.connections_open.load(Ordering::Relaxed),
+            connections_total: self.connections_total.load(Ordering::Relaxed),
+            connections_rejected: self.connections_rejected.load(Ordering::Relaxed),
+            requests_too_large: self.requests_too_large.load(Ordering::Relaxed),
+            idle_timeouts: self.idle_timeouts.load(Ordering::Relaxed),
+            read_timeouts: self.read_timeouts.load(Ordering::Relaxed),
+            subscriptions_rejected: self.subscriptions_rejected.load(Ordering::Relaxed),
+            rate_limited: self.rate_limited.load(Ordering::Relaxed),
+        }
+    }
+}
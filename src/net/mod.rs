@@ -1 +1,12 @@
-pub mod protocol;
\ No newline at end of file
+pub mod protocol;
+pub mod codec;
+pub mod limits;
+pub mod ratelimit;
+pub mod handshake;
+pub mod auth;
+pub mod acl;
+pub mod tls;
+pub mod dispatch;
+pub mod ws;
+pub mod http;
+pub mod resp;
@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::{event::Event, store::kv::Document};
+use crate::{
+    engine::{backup::Manifest, info::EngineInfo},
+    event::Event,
+    store::kv::Document,
+};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,6 +17,34 @@ pub enum Request {
     Patch { key: String, delta: Value },
     Snapshot,
     Subscribe { key: String },
+    // server counters (connections, limits hit), admin only
+    Stats,
+    // engine introspection (wal position, snapshots, fsyncs, subscribers), admin only
+    Info,
+    // number of keys (the ones the user may read, with key acls)
+    DbSize,
+    // sorted key names matching the glob, every key when left out. At most `limit` of them
+    Keys {
+        #[serde(default)]
+        pattern: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    // deletes every key in one wal record, admin only. Subscribers get a delete per key
+    FlushAll,
+    // consistent copy of the data dir into dest_dir (relative to the server's backup root, must
+    // be empty or missing) while writes go on, admin only. See engine::backup
+    Backup { dest_dir: String },
+    // must succeed before anything else when the server has an auth file
+    Auth { user: String, secret: String },
+    // only valid as the first request on a connection, see net::handshake
+    Hello {
+        protocol_version: u32,
+        #[serde(default)]
+        client_name: Option<String>,
+        #[serde(default)]
+        capabilities: Vec<Capability>,
+    },
 }
 
 
@@ -24,6 +56,133 @@ pub enum Response {
     Subscribed { key: String },
     Event { event: Event },
     Error { message: String },
+    // reply to Hello: the server's version plus the subset of the asked capabilities it granted
+    Welcome {
+        server_version: String,
+        protocol_version: u32,
+        capabilities: Vec<Capability>,
+        limits: Limits,
+    },
+    Stats { stats: Stats },
+    Info { info: Info },
+    DbSize { size: u64 },
+    // truncated = more keys matched than `limit`
+    Keys { keys: Vec<String>, truncated: bool },
+    Backup { manifest: Manifest },
+    // over the connection's or the user's rate limit, the request was not run
+    RateLimited { retry_after_ms: u64 },
+}
+
+// optional features a client can ask for in Hello. Only the ones echoed back in Welcome are on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Msgpack,    // switch to length-prefixed MessagePack frames after the Welcome
+    Deflate,    // deflate every msgpack frame body (only together with msgpack)
+    Pipelining, // id'd requests may run concurrently and answer out of order
+    // anything a newer client knows about and we don't, never granted
+    #[serde(other)]
+    Unknown,
+}
+
+// server side limits so a client can size its frames / in flight window up front
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limits {
+    pub max_frame_len: usize,
+    pub max_in_flight: usize,
+    pub max_subscriptions: usize, // per connection
+}
+
+// process wide counters, see net::limits
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    pub connections_open: u64,
+    pub connections_total: u64,
+    pub connections_rejected: u64, // over max connections
+    pub requests_too_large: u64,
+    pub idle_timeouts: u64,
+    pub read_timeouts: u64,
+    pub subscriptions_rejected: u64, // over max subscriptions per connection
+    pub rate_limited: u64, // requests answered with RateLimited
+}
+
+// reply to Info: the engine's view plus the connected clients
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Info {
+    pub clients: u64, // open tcp / websocket / unix connections
+    #[serde(flatten)]
+    pub engine: EngineInfo,
+}
+
+// how frames are delimited on a stream connection. Json is the default; the others are picked
+// through the capabilities in Hello. Never sent on the wire itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Framing {
+    #[default]
+    Json,           // one json document per line
+    Msgpack,        // u32 big-endian length + MessagePack body
+    MsgpackDeflate, // u32 big-endian length + deflate(MessagePack body)
+}
+
+// what actually goes over the wire: the request plus an optional client chosen id.
+// the id is echoed on the matching response (and on every event of a subscription), so a
+// client can keep many requests in flight on one connection and match replies out of order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestFrame {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    // client chosen, recorded on the server's request span so every log line the request
+    // causes (down to the wal fsync) can be found by it. Not echoed back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    #[serde(flatten)]
+    pub req: Request,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseFrame {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub resp: Response,
+}
+
+impl Request {
+    // the wire `kind`, used as a metrics label
+    pub fn name(&self) -> &'static str {
+        match self {
+            Request::Set { .. } => "set",
+            Request::Get { .. } => "get",
+            Request::Del { .. } => "del",
+            Request::Patch { .. } => "patch",
+            Request::Snapshot => "snapshot",
+            Request::Subscribe { .. } => "subscribe",
+            Request::Stats => "stats",
+            Request::Info => "info",
+            Request::DbSize => "db_size",
+            Request::Keys { .. } => "keys",
+            Request::FlushAll => "flush_all",
+            Request::Backup { .. } => "backup",
+            Request::Auth { .. } => "auth",
+            Request::Hello { .. } => "hello",
+        }
+    }
+}
+
+impl From<Request> for RequestFrame {
+    fn from(req: Request) -> Self {
+        Self {
+            id: None,
+            trace_id: None,
+            req,
+        }
+    }
+}
+
+impl From<Response> for ResponseFrame {
+    fn from(resp: Response) -> Self {
+        Self { id: None, resp }
+    }
 }
 
 
@@ -38,5 +197,15 @@ instead of this
 
 { "Set": { "key": "a", "value": 1 } }
 
+with an id (optional, echoed back):
+
+{ "id": 7, "kind": "get", "key": "a" }  ->  { "id": 7, "kind": "value", "doc": null }
+
+and the optional opening exchange (see net::handshake):
+
+{ "kind": "hello", "protocol_version": 1, "client_name": "cli", "capabilities": ["msgpack", "pipelining"] }
+->  { "kind": "welcome", "server_version": "0.1.0", "protocol_version": 1,
+      "capabilities": ["msgpack", "pipelining"], "limits": { "max_frame_len": 16777216, "max_in_flight": 128, "max_subscriptions": 1024 } }
+
 and this is the contract between client and server.rs  the official communication protocol between the two 
 */
\ No newline at end of file
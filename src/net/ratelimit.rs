@@ -0,0 +1,256 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+use crate::net::{
+    acl::{classify, CommandClass, Principal},
+    limits::COUNTERS,
+    protocol::{Request, Response},
+};
+
+// per second budgets, None = unlimited. Used for connections (server flags) and for users
+// (`rate` / `default_rate` in the auth file):
+//
+//   [default_rate]
+//   write_ops = 500
+//   write_bytes = 1048576
+//
+//   [[users]]
+//   name = "batch"
+//   hash = "..."
+//   rate = { read_ops = 100, write_ops = 50 }
+//
+// a bucket holds one second worth of tokens, so that is also the burst
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Budget {
+    pub read_ops: Option<u64>,
+    pub write_ops: Option<u64>,
+    pub read_bytes: Option<u64>, // charged with the size of the values sent back
+    pub write_bytes: Option<u64>, // charged with the size of the request
+}
+
+impl Budget {
+    pub fn is_unlimited(&self) -> bool {
+        *self == Budget::default()
+    }
+}
+
+// reads and writes draw from separate budgets. Subscribing counts as a read, admin
+// requests (snapshot, stats) as writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+impl From<CommandClass> for Direction {
+    fn from(class: CommandClass) -> Self {
+        match class {
+            CommandClass::Read | CommandClass::Subscribe => Direction::Read,
+            CommandClass::Write | CommandClass::Admin => Direction::Write,
+        }
+    }
+}
+
+struct Bucket {
+    rate: f64,
+    tokens: f64, // may go negative, see charge
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+    }
+
+    // how long until `n` can be taken. Anything bigger than the bucket only needs a full one,
+    // otherwise a single large value could never be written
+    fn wait(&mut self, n: u64) -> Duration {
+        self.refill();
+        let needed = (n as f64).min(self.rate);
+        if self.tokens >= needed {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((needed - self.tokens) / self.rate)
+    }
+
+    // goes into debt past zero, later requests wait it off
+    fn charge(&mut self, n: u64) {
+        self.tokens -= n as f64;
+    }
+}
+
+struct Buckets {
+    budget: Budget,
+    read_ops: Option<Bucket>,
+    write_ops: Option<Bucket>,
+    read_bytes: Option<Bucket>,
+    write_bytes: Option<Bucket>,
+}
+
+impl Buckets {
+    fn new(budget: Budget) -> Self {
+        Self {
+            budget,
+            read_ops: budget.read_ops.map(Bucket::new),
+            write_ops: budget.write_ops.map(Bucket::new),
+            read_bytes: budget.read_bytes.map(Bucket::new),
+            write_bytes: budget.write_bytes.map(Bucket::new),
+        }
+    }
+
+    // (bucket, tokens) pairs a request of `bytes` in `direction` draws from
+    fn for_request(&mut self, direction: Direction, bytes: u64) -> [(Option<&mut Bucket>, u64); 2] {
+        match direction {
+            Direction::Read => [(self.read_ops.as_mut(), 1), (self.read_bytes.as_mut(), 0)],
+            Direction::Write => [
+                (self.write_ops.as_mut(), 1),
+                (self.write_bytes.as_mut(), bytes),
+            ],
+        }
+    }
+
+    fn wait(&mut self, direction: Direction, bytes: u64) -> Duration {
+        self.for_request(direction, bytes)
+            .into_iter()
+            .filter_map(|(bucket, n)| bucket.map(|b| b.wait(n)))
+            .max()
+            .unwrap_or(Duration::ZERO)
+    }
+
+    fn take(&mut self, direction: Direction, bytes: u64) {
+        for (bucket, n) in self.for_request(direction, bytes) {
+            if let Some(bucket) = bucket {
+                bucket.charge(n);
+            }
+        }
+    }
+}
+
+// a user's buckets are shared by all of its connections, over every listener in the process
+// (tcp, unix socket, websocket, http and resp all admit through ConnectionRate)
+static USER_BUCKETS: LazyLock<Mutex<HashMap<String, Buckets>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// rate limiting state of one connection: its own buckets plus whoever is logged in on it
+pub struct ConnectionRate {
+    conn: Mutex<Buckets>,
+    principal: Mutex<Option<Principal>>,
+}
+
+impl ConnectionRate {
+    pub fn new(budget: Budget) -> Self {
+        Self {
+            conn: Mutex::new(Buckets::new(budget)),
+            principal: Mutex::new(None),
+        }
+    }
+
+    // Err(retry_after_ms) = over the connection's or the user's budget, answer with
+    // Response::RateLimited and don't run the request. Nothing is taken in that case
+    pub fn admit(
+        &self,
+        principal: Option<Principal>,
+        class: CommandClass,
+        request_bytes: usize,
+    ) -> Result<(), u64> {
+        let direction = Direction::from(class);
+        let bytes = request_bytes as u64;
+        let user_budget = principal.as_ref().and_then(|p| p.rate());
+        *self.principal.lock().unwrap() = principal.clone();
+
+        let mut conn = self.conn.lock().unwrap();
+        if conn.budget.is_unlimited() && user_budget.is_none() {
+            return Ok(()); // the default, no locking of the shared user map
+        }
+        let mut users = USER_BUCKETS.lock().unwrap();
+        let mut user = match (&principal, user_budget) {
+            (Some(principal), Some(budget)) => {
+                Some(user_buckets(&mut users, principal.user(), budget))
+            }
+            _ => None,
+        };
+
+        let mut wait = conn.wait(direction, bytes);
+        if let Some(user) = user.as_mut() {
+            wait = wait.max(user.wait(direction, bytes));
+        }
+        if !wait.is_zero() {
+            COUNTERS.rate_limited();
+            return Err((wait.as_millis() as u64).max(1));
+        }
+
+        conn.take(direction, bytes);
+        if let Some(user) = user {
+            user.take(direction, bytes);
+        }
+        Ok(())
+    }
+
+    // for the json transports, like Gate::check: Some(resp) = over budget, send resp instead of
+    // dispatching. Connection level requests (hello, auth) are never limited
+    pub fn check(
+        &self,
+        principal: Option<Principal>,
+        req: &Request,
+        request_bytes: usize,
+    ) -> Option<Response> {
+        let (class, _) = classify(req)?;
+        match self.admit(principal, class, request_bytes) {
+            Ok(()) => None,
+            Err(retry_after_ms) => Some(Response::RateLimited { retry_after_ms }),
+        }
+    }
+
+    // the size of a value that was read, after the fact (it isn't known up front)
+    pub fn charge_read_bytes(&self, n: usize) {
+        let n = n as u64;
+        if let Some(bucket) = self.conn.lock().unwrap().read_bytes.as_mut() {
+            bucket.charge(n);
+        }
+
+        let principal = self.principal.lock().unwrap().clone();
+        let Some(principal) = principal else {
+            return;
+        };
+        let Some(budget) = principal.rate() else {
+            return;
+        };
+        let mut users = USER_BUCKETS.lock().unwrap();
+        let user = user_buckets(&mut users, principal.user(), budget);
+        if let Some(bucket) = user.read_bytes.as_mut() {
+            bucket.charge(n);
+        }
+    }
+}
+
+// a reloaded auth file with a different budget starts the user over with full buckets
+fn user_buckets<'a>(
+    users: &'a mut HashMap<String, Buckets>,
+    user: &str,
+    budget: Budget,
+) -> &'a mut Buckets {
+    let buckets = users
+        .entry(user.to_string())
+        .or_insert_with(|| Buckets::new(budget));
+    if buckets.budget != budget {
+        *buckets = Buckets::new(budget);
+    }
+    buckets
+}
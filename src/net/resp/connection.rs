@@ -0,0 +1,875 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::JoinHandle,
+    time::{timeout_at, Instant},
+};
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::{
+    engine::handler::EngineHandle,
+    event::Event,
+    interface::command::WriteError,
+    net::{
+        acl::{authorize, CommandClass, Principal},
+        auth::{Authenticator, Gate},
+        codec::is_frame_too_large,
+        limits::{ConnectionLimiter, ServerLimits, COUNTERS, TOO_MANY_CONNECTIONS},
+        ratelimit::ConnectionRate,
+        resp::{
+            expiry::{deadline, Expiry},
+            json::{json_get, json_merge, json_set, SetCondition},
+            value::{read_command, RespValue},
+        },
+    },
+};
+
+const OUTBOUND_BUFFER: usize = 128;
+
+pub async fn serve(
+    listener: TcpListener,
+    handle: EngineHandle,
+    auth: Option<Arc<Authenticator>>,
+) -> std::io::Result<()> {
+    let limits = ServerLimits::default();
+    let limiter = ConnectionLimiter::new(limits.max_connections);
+    serve_with(listener, handle, auth, limits, limiter).await
+}
+
+// connections draw from the server's shared --max-connections pool and get the same idle and
+// read timeouts as the json listeners
+pub async fn serve_with(
+    listener: TcpListener,
+    handle: EngineHandle,
+    auth: Option<Arc<Authenticator>>,
+    limits: ServerLimits,
+    limiter: ConnectionLimiter,
+) -> std::io::Result<()> {
+    // one ttl table for the whole listener so EXPIRE/TTL agree across connections
+    let expiry = Expiry::new(handle.clone());
+
+    loop {
+        let (mut stream, addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!(error = %e, "resp accept failed");
+                continue;
+            }
+        };
+        let _ = stream.set_nodelay(true);
+        let handle = handle.clone();
+        let expiry = expiry.clone();
+        let gate = Gate::new(auth.clone(), addr.ip());
+        let slot = limiter.try_acquire();
+
+        let span = info_span!("connection", transport = "resp", peer = %addr);
+        tokio::spawn(
+            async move {
+                let Some(_slot) = slot else {
+                    let mut out = Vec::new();
+                    RespValue::err(format!("ERR {TOO_MANY_CONNECTIONS}")).encode(false, &mut out);
+                    let _ = stream.write_all(&out).await;
+                    return;
+                };
+                if let Err(e) = handle_resp_connection(stream, handle, expiry, gate, limits).await {
+                    info!(error = %e, "connection closed with error");
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
+
+// same layout as the json server: read loop -> out channel -> writer task, so pubsub forwarders
+// and command replies share one ordered socket writer. Replies are encoded when queued, so
+// HELLO switching protocols never re-encodes a reply that was produced before it
+async fn handle_resp_connection(
+    stream: TcpStream,
+    handle: EngineHandle,
+    expiry: Expiry,
+    gate: Gate,
+    limits: ServerLimits,
+) -> std::io::Result<()> {
+    let (read_half, mut write_half) = stream.into_split();
+    let mut reader = BufReader::new(read_half);
+
+    let (out_tx, mut out_rx) = mpsc::channel::<Vec<u8>>(OUTBOUND_BUFFER);
+    // flipped by HELLO 3; shared with the pubsub forwarders so pushes use the current protocol
+    let resp3 = Arc::new(AtomicBool::new(false));
+
+    let writer_task = tokio::spawn(async move {
+        while let Some(bytes) = out_rx.recv().await {
+            if write_half.write_all(&bytes).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session {
+        handle,
+        expiry,
+        out_tx,
+        resp3,
+        gate,
+        rate: ConnectionRate::new(limits.conn_rate),
+        max_subscriptions: limits.max_subscriptions,
+        channels: HashMap::new(),
+        patterns: HashMap::new(),
+    };
+
+    loop {
+        // connections holding subscriptions may stay quiet forever, they are waiting for events
+        let idle = session.subscription_count() == 0;
+        let read = match read_timed(&mut reader, &limits, idle, session.gate.deadline()).await {
+            Ok(read) => read,
+            Err(message) => {
+                session.reply(RespValue::err(format!("ERR {message}"))).await;
+                break;
+            }
+        };
+        let args = match read {
+            Ok(Some(args)) => args,
+            Ok(None) => break,
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                if is_frame_too_large(&e) {
+                    COUNTERS.request_too_large();
+                }
+                // redis answers protocol errors once and hangs up
+                session.reply(RespValue::err(format!("ERR {e}"))).await;
+                break;
+            }
+            Err(e) => {
+                session.close();
+                return Err(e);
+            }
+        };
+        if args.is_empty() {
+            continue;
+        }
+
+        if session.execute(args).await == Flow::Quit {
+            break;
+        }
+    }
+
+    session.close();
+    drop(session);
+    let _ = writer_task.await;
+    Ok(())
+}
+
+// read_command with the connection's deadlines, like the tcp server's read_request: the login
+// deadline while AUTH is pending, the idle timeout while waiting for a command to start and the
+// read timeout once it has. Err is the message the client is told before the connection closes
+async fn read_timed<R>(
+    reader: &mut R,
+    limits: &ServerLimits,
+    idle: bool,
+    deadline: Option<Instant>,
+) -> Result<std::io::Result<Option<Vec<Vec<u8>>>>, &'static str>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let login_expired = |at: Instant| deadline.is_some_and(|d| d <= at);
+
+    let idle_deadline = limits.idle_timeout.filter(|_| idle).map(|t| Instant::now() + t);
+    if let Some(at) = earliest(deadline, idle_deadline) {
+        match timeout_at(at, reader.fill_buf()).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return Ok(Err(e)),
+            Err(_) if login_expired(at) => return Err("authentication timeout"),
+            Err(_) => {
+                COUNTERS.idle_timeout();
+                return Err("idle timeout");
+            }
+        }
+    }
+
+    let read_deadline = limits.read_timeout.map(|t| Instant::now() + t);
+    match earliest(deadline, read_deadline) {
+        Some(at) => match timeout_at(at, read_command(reader, limits.max_request_bytes)).await {
+            Ok(read) => Ok(read),
+            Err(_) if login_expired(at) => Err("authentication timeout"),
+            Err(_) => {
+                COUNTERS.read_timeout();
+                Err("read timeout")
+            }
+        },
+        None => Ok(read_command(reader, limits.max_request_bytes).await),
+    }
+}
+
+fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[derive(PartialEq)]
+enum Flow {
+    Continue,
+    Quit,
+}
+
+struct Session {
+    handle: EngineHandle,
+    expiry: Expiry,
+    out_tx: mpsc::Sender<Vec<u8>>,
+    resp3: Arc<AtomicBool>,
+    gate: Gate, // login state, open when the server has no auth file
+    rate: ConnectionRate, // --rate-* budgets and the user's own, same as the json transports
+    channels: HashMap<String, JoinHandle<()>>, // SUBSCRIBE key -> event forwarder
+    patterns: HashMap<String, JoinHandle<()>>, // PSUBSCRIBE pattern -> event forwarder
+    max_subscriptions: usize, // channels and patterns together, like a tcp connection's
+}
+
+impl Session {
+    async fn reply(&self, value: RespValue) {
+        let _ = send(&self.out_tx, &self.resp3, value).await;
+    }
+
+    fn subscription_count(&self) -> i64 {
+        (self.channels.len() + self.patterns.len()) as i64
+    }
+
+    // aborting a forwarder drops its receiver, Reactivity prunes it on the next dispatch
+    fn close(&mut self) {
+        for (_, task) in self.channels.drain().chain(self.patterns.drain()) {
+            task.abort();
+        }
+    }
+
+    async fn execute(&mut self, args: Vec<Vec<u8>>) -> Flow {
+        let name = String::from_utf8_lossy(&args[0]).to_ascii_uppercase();
+        let args: Vec<String> = match args[1..]
+            .iter()
+            .map(|a| String::from_utf8(a.clone()))
+            .collect()
+        {
+            Ok(args) => args,
+            Err(_) => {
+                self.reply(RespValue::err("ERR arguments must be valid utf-8"))
+                    .await;
+                return Flow::Continue;
+            }
+        };
+
+        if !self.gate.is_open() && !matches!(name.as_str(), "AUTH" | "HELLO" | "QUIT") {
+            self.reply(RespValue::err("NOAUTH Authentication required."))
+                .await;
+            return Flow::Continue;
+        }
+
+        // RESP2 connections in subscribed mode may only manage subscriptions
+        let subscribed = self.subscription_count() > 0 && !self.resp3.load(Ordering::Relaxed);
+        if subscribed
+            && !matches!(
+                name.as_str(),
+                "SUBSCRIBE"
+                    | "PSUBSCRIBE"
+                    | "UNSUBSCRIBE"
+                    | "PUNSUBSCRIBE"
+                    | "PING"
+                    | "QUIT"
+                    | "RESET"
+            )
+        {
+            self.reply(RespValue::err(format!(
+                "ERR Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                name.to_ascii_lowercase()
+            )))
+            .await;
+            return Flow::Continue;
+        }
+
+        // reads are charged the size of their reply once it is known
+        let class = match self.admit(&name, &args) {
+            Ok(class) => class,
+            Err(retry_after_ms) => {
+                let message = format!("ERR rate limited, retry after {retry_after_ms} ms");
+                self.reply(RespValue::err(message)).await;
+                return Flow::Continue;
+            }
+        };
+
+        match name.as_str() {
+            "QUIT" => {
+                self.reply(RespValue::ok()).await;
+                return Flow::Quit;
+            }
+            "RESET" => {
+                self.close();
+                self.resp3.store(false, Ordering::Relaxed);
+                self.reply(RespValue::Simple("RESET".to_string())).await;
+            }
+            "SUBSCRIBE" => self.subscribe(args, false).await,
+            "PSUBSCRIBE" => self.subscribe(args, true).await,
+            "UNSUBSCRIBE" => self.unsubscribe(args, false).await,
+            "PUNSUBSCRIBE" => self.unsubscribe(args, true).await,
+            "PING" if subscribed => {
+                let msg = args.first().cloned().unwrap_or_default();
+                self.reply(RespValue::Array(vec![
+                    RespValue::bulk("pong"),
+                    RespValue::bulk(msg),
+                ]))
+                .await;
+            }
+            _ => {
+                let reply = match self.command(&name, args).await {
+                    Ok(value) => value,
+                    Err(message) => RespValue::Error(message),
+                };
+                let mut bytes = Vec::new();
+                reply.encode(self.resp3.load(Ordering::Relaxed), &mut bytes);
+                if class == Some(CommandClass::Read) {
+                    self.rate.charge_read_bytes(bytes.len());
+                }
+                let _ = self.out_tx.send(bytes).await;
+            }
+        }
+
+        Flow::Continue
+    }
+
+    // connection level commands (AUTH, HELLO, PING...) are never limited. Err(retry_after_ms)
+    fn admit(&self, name: &str, args: &[String]) -> Result<Option<CommandClass>, u64> {
+        let class = match name {
+            "SUBSCRIBE" | "PSUBSCRIBE" => CommandClass::Subscribe,
+            _ => match command_class(name) {
+                Some(class) => class,
+                None => return Ok(None),
+            },
+        };
+        let request_bytes = name.len() + args.iter().map(String::len).sum::<usize>();
+        self.rate.admit(self.gate.principal(), class, request_bytes)?;
+        Ok(Some(class))
+    }
+
+    // plain request/response commands
+    async fn command(&mut self, name: &str, args: Vec<String>) -> Result<RespValue, String> {
+        let arity = |min: usize, max: Option<usize>| {
+            if args.len() < min || max.is_some_and(|max| args.len() > max) {
+                Err(format!(
+                    "ERR wrong number of arguments for '{}' command",
+                    name.to_ascii_lowercase()
+                ))
+            } else {
+                Ok(())
+            }
+        };
+
+        if let Some(class) = command_class(name) {
+            self.authorize(class, None)?;
+            // KEYS / SCAN take patterns, their results are filtered instead
+            let keys = match name {
+                "DEL" | "EXISTS" => &args[..],
+                "KEYS" | "SCAN" => &[],
+                _ => &args[..args.len().min(1)],
+            };
+            for key in keys {
+                self.authorize(class, Some(key))?;
+            }
+        }
+
+        match name {
+            "PING" => {
+                arity(0, Some(1))?;
+                Ok(match args.into_iter().next() {
+                    Some(msg) => RespValue::bulk(msg),
+                    None => RespValue::Simple("PONG".to_string()),
+                })
+            }
+            "ECHO" => {
+                arity(1, Some(1))?;
+                Ok(RespValue::bulk(args[0].clone()))
+            }
+            "AUTH" => {
+                arity(1, Some(2))?;
+                // AUTH <secret> is the redis "default" user
+                let (user, secret) = match args.as_slice() {
+                    [secret] => ("default", secret),
+                    [user, secret] => (user.as_str(), secret),
+                    _ => unreachable!(),
+                };
+                self.auth(user, secret).await?;
+                Ok(RespValue::ok())
+            }
+            "HELLO" => self.hello(args).await,
+            "SELECT" => {
+                arity(1, Some(1))?;
+                if args[0] == "0" {
+                    Ok(RespValue::ok())
+                } else {
+                    Err("ERR DB index is out of range".to_string())
+                }
+            }
+            // redis-cli and most libraries probe these on connect
+            "COMMAND" => Ok(RespValue::Array(vec![])),
+            "CLIENT" => match args.first().map(|s| s.to_ascii_uppercase()).as_deref() {
+                Some("GETNAME") => Ok(RespValue::Null),
+                Some(_) => Ok(RespValue::ok()),
+                None => arity(1, None).map(|_| RespValue::Null),
+            },
+
+            "GET" => {
+                arity(1, Some(1))?;
+                let doc = self.handle.get(args[0].clone()).await?;
+                Ok(doc
+                    .map(|d| value_to_resp(&d.value))
+                    .unwrap_or(RespValue::Null))
+            }
+            "SET" => {
+                arity(2, None)?;
+                self.set(args).await
+            }
+            "DEL" => {
+                arity(1, None)?;
+                let mut deleted = 0;
+                for key in args {
+                    if self.delete_existing(key).await? {
+                        deleted += 1;
+                    }
+                }
+                Ok(RespValue::Integer(deleted))
+            }
+            "EXISTS" => {
+                arity(1, None)?;
+                let mut found = 0;
+                for key in args {
+                    if self.handle.get(key).await?.is_some() {
+                        found += 1;
+                    }
+                }
+                Ok(RespValue::Integer(found))
+            }
+            "KEYS" => {
+                arity(1, Some(1))?;
+                let mut keys = self.handle.keys(Some(args[0].clone())).await?;
+                keys.retain(|key| self.can_read(key));
+                Ok(RespValue::Array(
+                    keys.into_iter().map(RespValue::bulk).collect(),
+                ))
+            }
+            "SCAN" => {
+                arity(1, None)?;
+                self.scan(args).await
+            }
+            "EXPIRE" => {
+                arity(2, Some(2))?;
+                let secs: u64 = args[1]
+                    .parse()
+                    .map_err(|_| "ERR value is not an integer or out of range".to_string())?;
+                let set = self
+                    .expiry
+                    .expire(args[0].clone(), Duration::from_secs(secs))
+                    .await?;
+                Ok(RespValue::Integer(set as i64))
+            }
+            "TTL" => {
+                arity(1, Some(1))?;
+                Ok(RespValue::Integer(self.expiry.ttl(args[0].clone()).await?))
+            }
+
+            "JSON.GET" => {
+                arity(1, Some(2))?;
+                let path = args.get(1).map(String::as_str).unwrap_or("$");
+                json_get(&self.handle, args[0].clone(), path).await
+            }
+            "JSON.SET" => {
+                arity(3, Some(4))?;
+                let cond = match args.get(3).map(|s| s.to_ascii_uppercase()).as_deref() {
+                    None => SetCondition::Always,
+                    Some("NX") => SetCondition::Nx,
+                    Some("XX") => SetCondition::Xx,
+                    Some(_) => return Err("ERR syntax error".to_string()),
+                };
+                json_set(&self.handle, args[0].clone(), &args[1], &args[2], cond).await
+            }
+            "JSON.MERGE" => {
+                arity(3, Some(3))?;
+                json_merge(&self.handle, args[0].clone(), &args[1], &args[2]).await
+            }
+
+            _ => Err(format!(
+                "ERR unknown command '{}'",
+                name.to_ascii_lowercase()
+            )),
+        }
+    }
+
+    // HELLO [protover [AUTH user secret] [SETNAME name]]
+    async fn hello(&mut self, args: Vec<String>) -> Result<RespValue, String> {
+        let resp3 = match args.first().map(|v| v.as_str()) {
+            None => None,
+            Some("2") => Some(false),
+            Some("3") => Some(true),
+            Some(_) => return Err("NOPROTO unsupported protocol version".to_string()),
+        };
+
+        let mut opts = args.iter().skip(1);
+        while let Some(opt) = opts.next() {
+            match opt.to_ascii_uppercase().as_str() {
+                "AUTH" => match (opts.next(), opts.next()) {
+                    (Some(user), Some(secret)) => self.auth(user, secret).await?,
+                    _ => return Err("ERR syntax error".to_string()),
+                },
+                "SETNAME" if opts.next().is_some() => {}
+                _ => return Err("ERR syntax error".to_string()),
+            }
+        }
+        if !self.gate.is_open() {
+            return Err("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".to_string());
+        }
+
+        if let Some(resp3) = resp3 {
+            self.resp3.store(resp3, Ordering::Relaxed);
+        }
+
+        let proto = if self.resp3.load(Ordering::Relaxed) {
+            3
+        } else {
+            2
+        };
+        Ok(RespValue::Map(vec![
+            (RespValue::bulk("server"), RespValue::bulk("fluxdb")),
+            (
+                RespValue::bulk("version"),
+                RespValue::bulk(env!("CARGO_PKG_VERSION")),
+            ),
+            (RespValue::bulk("proto"), RespValue::Integer(proto)),
+            (RespValue::bulk("id"), RespValue::Integer(0)),
+            (RespValue::bulk("mode"), RespValue::bulk("standalone")),
+            (RespValue::bulk("role"), RespValue::bulk("master")),
+            (RespValue::bulk("modules"), RespValue::Array(vec![])),
+        ]))
+    }
+
+    // acl check for the logged in user (always Ok with auth off)
+    fn authorize(&self, class: CommandClass, key: Option<&str>) -> Result<(), String> {
+        authorize(self.gate.principal().as_ref(), class, key).map_err(|e| format!("NOPERM {e}"))
+    }
+
+    fn can_read(&self, key: &str) -> bool {
+        self.authorize(CommandClass::Read, Some(key)).is_ok()
+    }
+
+    async fn auth(&mut self, user: &str, secret: &str) -> Result<(), String> {
+        if !self.gate.enabled() {
+            return Err("ERR AUTH called without any password configured for the default user. Are you sure your configuration is correct?".to_string());
+        }
+        self.gate
+            .login(user, secret)
+            .await
+            .map_err(|e| format!("WRONGPASS {e}"))
+    }
+
+    // SET key value [NX|XX] [EX seconds|PX milliseconds]
+    // values are stored as JSON strings; NX/XX map onto version-checked writes
+    async fn set(&mut self, args: Vec<String>) -> Result<RespValue, String> {
+        let key = args[0].clone();
+        let value = Value::String(args[1].clone());
+
+        let mut nx = false;
+        let mut xx = false;
+        let mut ttl = None;
+        let mut opts = args[2..].iter();
+        while let Some(opt) = opts.next() {
+            match opt.to_ascii_uppercase().as_str() {
+                "NX" => nx = true,
+                "XX" => xx = true,
+                unit @ ("EX" | "PX") => {
+                    let n: u64 = opts
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .filter(|n| *n > 0)
+                        .ok_or("ERR invalid expire time in 'set' command")?;
+                    let after = if unit == "EX" {
+                        Duration::from_secs(n)
+                    } else {
+                        Duration::from_millis(n)
+                    };
+                    // checked before the write, not when the ttl is set after it
+                    if deadline(after).is_none() {
+                        return Err("ERR invalid expire time in 'set' command".to_string());
+                    }
+                    ttl = Some(after);
+                }
+                _ => return Err("ERR syntax error".to_string()),
+            }
+        }
+        if nx && xx {
+            return Err("ERR syntax error".to_string());
+        }
+
+        let expected = if nx {
+            Some(0) // must not exist
+        } else if xx {
+            match self.handle.get(key.clone()).await? {
+                Some(doc) => Some(doc.version),
+                None => return Ok(RespValue::Null),
+            }
+        } else {
+            None
+        };
+
+        match self
+            .handle
+            .set_if_version(key.clone(), value, expected)
+            .await
+        {
+            Ok(()) => {}
+            Err(WriteError::VersionConflict { .. }) => return Ok(RespValue::Null),
+            Err(WriteError::Failed(message)) => return Err(message),
+        }
+
+        if let Some(ttl) = ttl {
+            self.expiry.expire(key, ttl).await?;
+        }
+        Ok(RespValue::ok())
+    }
+
+    // Ok(false) if the key was not there; pinned to the version we saw so it's never a
+    // blind tombstone write
+    async fn delete_existing(&self, key: String) -> Result<bool, String> {
+        let Some(doc) = self.handle.get(key.clone()).await? else {
+            return Ok(false);
+        };
+        match self.handle.delete_if_version(key, Some(doc.version)).await {
+            Ok(()) => Ok(true),
+            Err(WriteError::VersionConflict { actual: 0, .. }) => Ok(false),
+            // rewritten in between: still counts, the key existed and is going away
+            Err(WriteError::VersionConflict { .. }) => Ok(true),
+            Err(WriteError::Failed(message)) => Err(message),
+        }
+    }
+
+    // SCAN cursor [MATCH pattern] [COUNT n]
+    // COUNT is only a hint in redis; we always finish the iteration in one call (cursor 0),
+    // which satisfies SCAN's guarantees
+    async fn scan(&self, args: Vec<String>) -> Result<RespValue, String> {
+        if args[0].parse::<u64>().is_err() {
+            return Err("ERR invalid cursor".to_string());
+        }
+
+        let mut pattern = None;
+        let mut opts = args[1..].iter();
+        while let Some(opt) = opts.next() {
+            match opt.to_ascii_uppercase().as_str() {
+                "MATCH" => pattern = Some(opts.next().ok_or("ERR syntax error")?.clone()),
+                "COUNT" => {
+                    opts.next()
+                        .and_then(|n| n.parse::<u64>().ok())
+                        .ok_or("ERR value is not an integer or out of range")?;
+                }
+                _ => return Err("ERR syntax error".to_string()),
+            }
+        }
+
+        let mut keys = self.handle.keys(pattern).await?;
+        keys.retain(|key| self.can_read(key));
+        Ok(RespValue::Array(vec![
+            RespValue::bulk("0"),
+            RespValue::Array(keys.into_iter().map(RespValue::bulk).collect()),
+        ]))
+    }
+
+    // SUBSCRIBE key... / PSUBSCRIBE pattern...
+    // channels are FluxDB keys; the message payload is the Event as json
+    async fn subscribe(&mut self, targets: Vec<String>, pattern: bool) {
+        if targets.is_empty() {
+            let name = if pattern { "psubscribe" } else { "subscribe" };
+            self.reply(RespValue::err(format!(
+                "ERR wrong number of arguments for '{name}' command"
+            )))
+            .await;
+            return;
+        }
+
+        for target in targets {
+            let already = if pattern {
+                self.patterns.contains_key(&target)
+            } else {
+                self.channels.contains_key(&target)
+            };
+
+            // a pattern may be wider than the acl allows, so its events are filtered per key
+            // by the forwarder instead
+            let key = if pattern { None } else { Some(target.as_str()) };
+            if let Err(message) = self.authorize(CommandClass::Subscribe, key) {
+                self.reply(RespValue::Error(message)).await;
+                continue;
+            }
+
+            if !already && self.subscription_count() as usize >= self.max_subscriptions {
+                COUNTERS.subscription_rejected();
+                self.reply(RespValue::err(
+                    "ERR subscription limit reached for this connection",
+                ))
+                .await;
+                continue;
+            }
+
+            if !already {
+                let rx = if pattern {
+                    self.handle.subscribe_pattern(target.clone()).await
+                } else {
+                    self.handle.subscribe(target.clone()).await
+                };
+                let rx = match rx {
+                    Ok(rx) => rx,
+                    Err(message) => {
+                        self.reply(RespValue::Error(message)).await;
+                        continue;
+                    }
+                };
+
+                let task = spawn_forwarder(
+                    rx,
+                    self.out_tx.clone(),
+                    self.resp3.clone(),
+                    target.clone(),
+                    pattern,
+                    self.gate.principal(),
+                );
+                if pattern {
+                    self.patterns.insert(target.clone(), task);
+                } else {
+                    self.channels.insert(target.clone(), task);
+                }
+            }
+
+            let kind = if pattern { "psubscribe" } else { "subscribe" };
+            self.reply(RespValue::Push(vec![
+                RespValue::bulk(kind),
+                RespValue::bulk(target),
+                RespValue::Integer(self.subscription_count()),
+            ]))
+            .await;
+        }
+    }
+
+    // no arguments = drop every subscription of that kind
+    async fn unsubscribe(&mut self, targets: Vec<String>, pattern: bool) {
+        let kind = if pattern {
+            "punsubscribe"
+        } else {
+            "unsubscribe"
+        };
+        let targets = if targets.is_empty() {
+            let subs = if pattern {
+                &self.patterns
+            } else {
+                &self.channels
+            };
+            subs.keys().cloned().collect()
+        } else {
+            targets
+        };
+
+        if targets.is_empty() {
+            self.reply(RespValue::Push(vec![
+                RespValue::bulk(kind),
+                RespValue::Null,
+                RespValue::Integer(self.subscription_count()),
+            ]))
+            .await;
+            return;
+        }
+
+        for target in targets {
+            let subs = if pattern {
+                &mut self.patterns
+            } else {
+                &mut self.channels
+            };
+            if let Some(task) = subs.remove(&target) {
+                task.abort();
+            }
+
+            self.reply(RespValue::Push(vec![
+                RespValue::bulk(kind),
+                RespValue::bulk(target),
+                RespValue::Integer(self.subscription_count()),
+            ]))
+            .await;
+        }
+    }
+}
+
+async fn send(
+    out_tx: &mpsc::Sender<Vec<u8>>,
+    resp3: &AtomicBool,
+    value: RespValue,
+) -> Result<(), mpsc::error::SendError<Vec<u8>>> {
+    let mut bytes = Vec::new();
+    value.encode(resp3.load(Ordering::Relaxed), &mut bytes);
+    out_tx.send(bytes).await
+}
+
+fn spawn_forwarder(
+    mut rx: mpsc::Receiver<Event>,
+    out_tx: mpsc::Sender<Vec<u8>>,
+    resp3: Arc<AtomicBool>,
+    target: String,
+    pattern: bool,
+    principal: Option<Principal>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            // checked per event: pattern matches outside the acl and acls reloaded since SUBSCRIBE
+            if principal
+                .as_ref()
+                .is_some_and(|p| !p.can_access(CommandClass::Subscribe, &event.key))
+            {
+                continue;
+            }
+            let payload = serde_json::to_string(&event).unwrap_or_default();
+            let msg = if pattern {
+                RespValue::Push(vec![
+                    RespValue::bulk("pmessage"),
+                    RespValue::bulk(target.clone()),
+                    RespValue::bulk(event.key),
+                    RespValue::bulk(payload),
+                ])
+            } else {
+                RespValue::Push(vec![
+                    RespValue::bulk("message"),
+                    RespValue::bulk(target.clone()),
+                    RespValue::bulk(payload),
+                ])
+            };
+
+            if send(&out_tx, &resp3, msg).await.is_err() {
+                break;
+            }
+        }
+    })
+}
+
+fn command_class(name: &str) -> Option<CommandClass> {
+    match name {
+        "GET" | "EXISTS" | "KEYS" | "SCAN" | "TTL" | "JSON.GET" => Some(CommandClass::Read),
+        "SET" | "DEL" | "EXPIRE" | "JSON.SET" | "JSON.MERGE" => Some(CommandClass::Write),
+        _ => None,
+    }
+}
+
+// strings come back as-is (what SET stored), any other document as its json text
+fn value_to_resp(value: &Value) -> RespValue {
+    match value {
+        Value::String(s) => RespValue::bulk(s.clone()),
+        other => RespValue::bulk(other.to_string()),
+    }
+}
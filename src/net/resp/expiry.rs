@@ -0,0 +1,89 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::engine::handler::EngineHandle;
+
+// EXPIRE support for the RESP listener.
+//
+// A ttl is pinned to the Document.version seen when it was set. When the timer fires the key is
+// deleted with delete_if_version, so any write in between (from any protocol) cancels the expiry,
+// same as SET clearing a ttl in redis.
+//
+// Deadlines live in memory only: they are not written to the WAL and are lost on restart.
+#[derive(Clone)]
+pub struct Expiry {
+    handle: EngineHandle,
+    deadlines: Arc<Mutex<HashMap<String, (Instant, u64)>>>, // key -> (deadline, pinned version)
+}
+
+impl Expiry {
+    pub fn new(handle: EngineHandle) -> Self {
+        Self {
+            handle,
+            deadlines: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Ok(false) when the key does not exist (redis returns 0 then)
+    pub async fn expire(&self, key: String, after: Duration) -> Result<bool, String> {
+        let Some(deadline) = deadline(after) else {
+            return Err("ERR invalid expire time in 'expire' command".to_string());
+        };
+        let Some(doc) = self.handle.get(key.clone()).await? else {
+            return Ok(false);
+        };
+
+        self.deadlines
+            .lock()
+            .unwrap()
+            .insert(key.clone(), (deadline, doc.version));
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep_until(deadline.into()).await;
+
+            // a later EXPIRE on the same key replaces the entry, only the newest timer acts
+            let pinned = {
+                let mut deadlines = this.deadlines.lock().unwrap();
+                match deadlines.get(&key) {
+                    Some(&(d, version)) if d == deadline => {
+                        deadlines.remove(&key);
+                        Some(version)
+                    }
+                    _ => None,
+                }
+            };
+
+            if let Some(version) = pinned {
+                // VersionConflict = key was rewritten after EXPIRE, keep it
+                let _ = this.handle.delete_if_version(key, Some(version)).await;
+            }
+        });
+
+        Ok(true)
+    }
+
+    // redis TTL semantics: -2 missing key, -1 no ttl, otherwise seconds left
+    pub async fn ttl(&self, key: String) -> Result<i64, String> {
+        let Some(doc) = self.handle.get(key.clone()).await? else {
+            return Ok(-2);
+        };
+
+        let deadlines = self.deadlines.lock().unwrap();
+        match deadlines.get(&key) {
+            Some(&(deadline, version)) if version == doc.version => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                Ok(left.as_secs_f64().ceil() as i64)
+            }
+            _ => Ok(-1),
+        }
+    }
+}
+
+// None when `after` is too far out to be a point in time (adding it would overflow)
+pub fn deadline(after: Duration) -> Option<Instant> {
+    Instant::now().checked_add(after)
+}
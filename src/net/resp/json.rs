@@ -0,0 +1,233 @@
+use serde_json::Value;
+
+use crate::{
+    engine::handler::EngineHandle,
+    interface::command::WriteError,
+    net::resp::value::RespValue,
+    store::kv::{merge_json, Document},
+};
+
+// nested JSON.SET / JSON.MERGE are read-modify-write against Document.version; on a conflict
+// we re-read and try again this many times before giving up
+const MAX_RETRIES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+// subset of RedisJSON paths: "$" / "." for the root, then .member and [index] steps
+// ($.user.tags[0], .user.name, user.name). "$" paths answer with an array of matches,
+// legacy paths with the value itself, like RedisJSON
+struct JsonPath {
+    dollar: bool,
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    fn parse(raw: &str) -> Result<Self, String> {
+        // legacy paths may omit the leading dot ("user.name")
+        let dotted;
+        let (dollar, mut rest) = match raw.strip_prefix('$') {
+            Some(rest) => (true, rest),
+            None if raw.is_empty() || raw.starts_with(['.', '[']) => (false, raw),
+            None => {
+                dotted = format!(".{raw}");
+                (false, dotted.as_str())
+            }
+        };
+
+        let mut segments = Vec::new();
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('[') {
+                let end = after
+                    .find(']')
+                    .ok_or_else(|| format!("ERR invalid path '{raw}'"))?;
+                let index = after[..end]
+                    .parse::<usize>()
+                    .map_err(|_| format!("ERR invalid array index in path '{raw}'"))?;
+                segments.push(Segment::Index(index));
+                rest = &after[end + 1..];
+            } else if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end > 0 {
+                    segments.push(Segment::Key(after[..end].to_string()));
+                }
+                rest = &after[end..];
+            } else {
+                return Err(format!("ERR invalid path '{raw}'"));
+            }
+        }
+
+        Ok(Self { dollar, segments })
+    }
+
+    fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+fn lookup<'a>(mut v: &'a Value, segments: &[Segment]) -> Option<&'a Value> {
+    for seg in segments {
+        v = match seg {
+            Segment::Key(k) => v.as_object()?.get(k)?,
+            Segment::Index(i) => v.as_array()?.get(*i)?,
+        };
+    }
+    Some(v)
+}
+
+fn lookup_mut<'a>(mut v: &'a mut Value, segments: &[Segment]) -> Option<&'a mut Value> {
+    for seg in segments {
+        v = match seg {
+            Segment::Key(k) => v.as_object_mut()?.get_mut(k)?,
+            Segment::Index(i) => v.as_array_mut()?.get_mut(*i)?,
+        };
+    }
+    Some(v)
+}
+
+// sets the value at path; the parent must exist (members are created, indexes must be in range)
+fn set_at(root: &mut Value, segments: &[Segment], new: Value) -> Result<(), String> {
+    let Some((last, parent_path)) = segments.split_last() else {
+        *root = new;
+        return Ok(());
+    };
+
+    let parent = lookup_mut(root, parent_path).ok_or("ERR path does not exist")?;
+    match (last, parent) {
+        (Segment::Key(k), Value::Object(map)) => {
+            map.insert(k.clone(), new);
+            Ok(())
+        }
+        (Segment::Index(i), Value::Array(items)) if *i < items.len() => {
+            items[*i] = new;
+            Ok(())
+        }
+        _ => Err("ERR path does not exist".to_string()),
+    }
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    serde_json::from_str(text).map_err(|e| format!("ERR invalid JSON: {e}"))
+}
+
+// JSON.GET key [path]
+pub async fn json_get(handle: &EngineHandle, key: String, path: &str) -> Result<RespValue, String> {
+    let path = JsonPath::parse(path)?;
+    let Some(doc) = handle.get(key).await? else {
+        return Ok(RespValue::Null);
+    };
+
+    let found = lookup(&doc.value, &path.segments);
+    let out = match (path.dollar, found) {
+        (true, Some(v)) => Value::Array(vec![v.clone()]),
+        (true, None) => Value::Array(vec![]),
+        (false, Some(v)) => v.clone(),
+        (false, None) => return Err("ERR path does not exist".to_string()),
+    };
+    Ok(RespValue::bulk(out.to_string()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetCondition {
+    Always,
+    Nx, // only if the path does not exist yet
+    Xx, // only if it already exists
+}
+
+// JSON.SET key path value [NX|XX] -> OK, or Null when the condition did not hold
+pub async fn json_set(
+    handle: &EngineHandle,
+    key: String,
+    path: &str,
+    value: &str,
+    cond: SetCondition,
+) -> Result<RespValue, String> {
+    let path = JsonPath::parse(path)?;
+    let new = parse_value(value)?;
+
+    let applied = read_modify_write(handle, &key, |doc| {
+        let exists = doc.is_some_and(|d| lookup(&d.value, &path.segments).is_some());
+        match cond {
+            SetCondition::Nx if exists => return Ok(None),
+            SetCondition::Xx if !exists => return Ok(None),
+            _ => {}
+        }
+
+        match doc {
+            Some(d) => {
+                let mut value = d.value.clone();
+                set_at(&mut value, &path.segments, new.clone())?;
+                Ok(Some(value))
+            }
+            None if path.is_root() => Ok(Some(new.clone())),
+            None => Err("ERR new objects must be created at the root".to_string()),
+        }
+    })
+    .await?;
+
+    Ok(if applied {
+        RespValue::ok()
+    } else {
+        RespValue::Null
+    })
+}
+
+// JSON.MERGE key path value, same merge rules as a FluxDB patch
+pub async fn json_merge(
+    handle: &EngineHandle,
+    key: String,
+    path: &str,
+    value: &str,
+) -> Result<RespValue, String> {
+    let path = JsonPath::parse(path)?;
+    let delta = parse_value(value)?;
+
+    // root merge is exactly a patch, done atomically inside the writer
+    if path.is_root() {
+        handle.patch(key, delta).await?;
+        return Ok(RespValue::ok());
+    }
+
+    read_modify_write(handle, &key, |doc| {
+        let doc = doc.ok_or("ERR new objects must be created at the root")?;
+        let mut value = doc.value.clone();
+        match lookup_mut(&mut value, &path.segments) {
+            Some(target) => merge_json(target, &delta),
+            None => set_at(&mut value, &path.segments, delta.clone())?,
+        }
+        Ok(Some(value))
+    })
+    .await?;
+
+    Ok(RespValue::ok())
+}
+
+// optimistic loop: compute the new document from the current one and write it only if the
+// version did not move in between. `f` returning None means "condition not met, don't write"
+async fn read_modify_write<F>(handle: &EngineHandle, key: &str, f: F) -> Result<bool, String>
+where
+    F: Fn(Option<&Document>) -> Result<Option<Value>, String>,
+{
+    for _ in 0..MAX_RETRIES {
+        let doc = handle.get(key.to_string()).await?;
+        let Some(new) = f(doc.as_ref())? else {
+            return Ok(false);
+        };
+
+        let expected = doc.map(|d| d.version).unwrap_or(0);
+        match handle
+            .set_if_version(key.to_string(), new, Some(expected))
+            .await
+        {
+            Ok(()) => return Ok(true),
+            Err(WriteError::VersionConflict { .. }) => continue,
+            Err(WriteError::Failed(message)) => return Err(message),
+        }
+    }
+
+    Err("ERR too many concurrent updates, try again".to_string())
+}
@@ -0,0 +1,6 @@
+pub mod connection;
+mod expiry;
+mod json;
+pub mod value;
+
+pub use connection::{serve, serve_with};
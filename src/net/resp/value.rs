@@ -0,0 +1,193 @@
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use crate::net::codec::FrameTooLarge;
+
+const MAX_ARGS: usize = 1024 * 1024;
+
+// one RESP reply. Map and Push only exist in RESP3; on a RESP2 connection they are
+// written as flat arrays, which is what redis itself does
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespValue {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    Array(Vec<RespValue>),
+    Map(Vec<(RespValue, RespValue)>),
+    Push(Vec<RespValue>),
+}
+
+impl RespValue {
+    pub fn ok() -> Self {
+        RespValue::Simple("OK".to_string())
+    }
+
+    pub fn err(message: impl Into<String>) -> Self {
+        RespValue::Error(message.into())
+    }
+
+    pub fn bulk(s: impl Into<String>) -> Self {
+        RespValue::Bulk(s.into().into_bytes())
+    }
+
+    pub fn encode(&self, resp3: bool, out: &mut Vec<u8>) {
+        match self {
+            RespValue::Simple(s) => {
+                out.push(b'+');
+                out.extend_from_slice(s.as_bytes());
+                out.extend_from_slice(b"\r\n");
+            }
+            RespValue::Error(s) => {
+                out.push(b'-');
+                // errors are single line, a newline in a message would break framing
+                out.extend_from_slice(s.replace(['\r', '\n'], " ").as_bytes());
+                out.extend_from_slice(b"\r\n");
+            }
+            RespValue::Integer(n) => {
+                out.extend_from_slice(format!(":{n}\r\n").as_bytes());
+            }
+            RespValue::Bulk(bytes) => {
+                out.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+                out.extend_from_slice(bytes);
+                out.extend_from_slice(b"\r\n");
+            }
+            RespValue::Null => {
+                if resp3 {
+                    out.extend_from_slice(b"_\r\n");
+                } else {
+                    out.extend_from_slice(b"$-1\r\n");
+                }
+            }
+            RespValue::Array(items) => encode_aggregate(b'*', items, resp3, out),
+            RespValue::Push(items) => {
+                encode_aggregate(if resp3 { b'>' } else { b'*' }, items, resp3, out)
+            }
+            RespValue::Map(pairs) => {
+                if resp3 {
+                    out.extend_from_slice(format!("%{}\r\n", pairs.len()).as_bytes());
+                } else {
+                    out.extend_from_slice(format!("*{}\r\n", pairs.len() * 2).as_bytes());
+                }
+                for (k, v) in pairs {
+                    k.encode(resp3, out);
+                    v.encode(resp3, out);
+                }
+            }
+        }
+    }
+}
+
+fn encode_aggregate(prefix: u8, items: &[RespValue], resp3: bool, out: &mut Vec<u8>) {
+    out.push(prefix);
+    out.extend_from_slice(format!("{}\r\n", items.len()).as_bytes());
+    for item in items {
+        item.encode(resp3, out);
+    }
+}
+
+// reads one client command: either a RESP array of bulk strings (what every client library
+// sends) or an inline command ("PING\r\n", what you type into telnet).
+// `max_len` caps the whole command as sent, every header and argument counted, so neither one
+// huge argument nor many small ones can make it buffer more than the server's max_request_bytes.
+// Ok(None) = clean EOF, Err(InvalidData) = protocol error, the connection should be closed.
+// codec::is_frame_too_large tells the size error apart
+pub async fn read_command<R>(
+    reader: &mut R,
+    max_len: usize,
+) -> std::io::Result<Option<Vec<Vec<u8>>>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = Vec::new();
+    loop {
+        if read_line(reader, &mut line, max_len, max_len).await? == 0 {
+            return Ok(None);
+        }
+
+        let trimmed = line.trim_ascii();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if trimmed[0] != b'*' {
+            let args = trimmed
+                .split(|b| b.is_ascii_whitespace())
+                .filter(|part| !part.is_empty())
+                .map(|part| part.to_vec())
+                .collect();
+            return Ok(Some(args));
+        }
+
+        let mut left = max_len - line.len().min(max_len);
+        let count = parse_len(&trimmed[1..])?;
+        if count > MAX_ARGS {
+            return Err(protocol_error("too many arguments"));
+        }
+
+        // grown as arguments arrive, a count alone must not reserve memory
+        let mut args = Vec::new();
+        for _ in 0..count {
+            left -= read_line(reader, &mut line, left, max_len).await?;
+            let header = line.trim_ascii();
+            if header.first() != Some(&b'$') {
+                return Err(protocol_error("expected '$'"));
+            }
+
+            let len = parse_len(&header[1..])?;
+            if len.saturating_add(2) > left {
+                return Err(too_large(max_len));
+            }
+            left -= len + 2;
+
+            let mut data = vec![0u8; len + 2]; // payload + \r\n
+            reader.read_exact(&mut data).await?;
+            data.truncate(len);
+            args.push(data);
+        }
+        return Ok(Some(args));
+    }
+}
+
+// one line into `line`, refused once it is past the `left` bytes the command may still use
+// instead of buffered whole
+async fn read_line<R>(
+    reader: &mut R,
+    line: &mut Vec<u8>,
+    left: usize,
+    max_len: usize,
+) -> std::io::Result<usize>
+where
+    R: AsyncBufRead + Unpin,
+{
+    line.clear();
+    let n = (&mut *reader)
+        .take(left as u64 + 1)
+        .read_until(b'\n', line)
+        .await?;
+    if n > left {
+        return Err(too_large(max_len));
+    }
+    Ok(n)
+}
+
+fn parse_len(digits: &[u8]) -> std::io::Result<usize> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .ok_or_else(|| protocol_error("invalid length"))
+}
+
+fn too_large(max_len: usize) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        FrameTooLarge(format!("Protocol error: command larger than {max_len} bytes")),
+    )
+}
+
+fn protocol_error(message: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Protocol error: {message}"),
+    )
+}
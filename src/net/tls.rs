@@ -0,0 +1,106 @@
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use tokio_rustls::rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    server::WebPkiClientVerifier,
+    ClientConfig, RootCertStore, ServerConfig, ServerConnection,
+};
+
+pub use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+// TLS for the tcp listener (rustls, pem files on disk)
+//
+//   server: --tls-cert server.pem --tls-key server.key [--tls-client-ca clients-ca.pem]
+//   client: --tls [--ca ca.pem] [--cert client.pem --key client.key]
+//
+// with a client ca every client must present a certificate signed by it (mutual tls). When the
+// server also has an auth file, a certificate whose subject CN names a user logs the
+// connection in as that user, no Auth request needed
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = File::open(path).map_err(|e| format!("opening {}: {e}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("reading certificates from {}: {e}", path.display()))?;
+    if certs.is_empty() {
+        return Err(format!("no certificates in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let file = File::open(path).map_err(|e| format!("opening {}: {e}", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("reading private key from {}: {e}", path.display()))?
+        .ok_or_else(|| format!("no private key in {}", path.display()))
+}
+
+fn load_roots(path: &Path) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(cert)
+            .map_err(|e| format!("bad ca certificate in {}: {e}", path.display()))?;
+    }
+    Ok(roots)
+}
+
+pub fn server_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> Result<Arc<ServerConfig>, String> {
+    let builder = ServerConfig::builder();
+    let builder = match client_ca {
+        Some(ca) => {
+            let verifier = WebPkiClientVerifier::builder(Arc::new(load_roots(ca)?))
+                .build()
+                .map_err(|e| format!("client ca {}: {e}", ca.display()))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let config = builder
+        .with_single_cert(load_certs(cert)?, load_key(key)?)
+        .map_err(|e| format!("server certificate: {e}"))?;
+    Ok(Arc::new(config))
+}
+
+// no ca file = the public webpki roots, for servers with a real certificate
+pub fn client_config(
+    ca: Option<&Path>,
+    identity: Option<(&Path, &Path)>,
+) -> Result<Arc<ClientConfig>, String> {
+    let roots = match ca {
+        Some(ca) => load_roots(ca)?,
+        None => RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    };
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+
+    let config = match identity {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+            .map_err(|e| format!("client certificate: {e}"))?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+// the name the server certificate has to match: the host part of host:port
+pub fn server_name(addr: &str) -> Result<ServerName<'static>, String> {
+    let host = match addr.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host,
+        _ => addr,
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']'); // [::1]:7000
+    ServerName::try_from(host.to_string()).map_err(|e| format!("bad server name {host}: {e}"))
+}
+
+// subject CN of the verified client certificate, if the client sent one
+pub fn certificate_user(conn: &ServerConnection) -> Option<String> {
+    let cert = conn.peer_certificates()?.first()?;
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let cn = parsed.subject().iter_common_name().next()?;
+    cn.as_str().ok().map(str::to_string)
+}
@@ -0,0 +1,231 @@
+use std::sync::Arc;
+
+use futures::{SinkExt, StreamExt};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, Semaphore},
+    time::{timeout_at, Instant},
+};
+use tokio_tungstenite::{
+    accept_async, accept_async_with_config,
+    tungstenite::{protocol::WebSocketConfig, Error as WsError, Message},
+};
+use tracing::{error, Instrument};
+
+use crate::{
+    engine::handler::EngineHandle,
+    net::{
+        acl::AdminPolicy,
+        auth::{Authenticator, Gate},
+        dispatch::{dispatch, dispatch_pipelined, MAX_IN_FLIGHT},
+        handshake::{welcome, Negotiated},
+        limits::{ServerLimits, COUNTERS},
+        protocol::{Capability, Request, RequestFrame, Response, ResponseFrame},
+        ratelimit::ConnectionRate,
+    },
+};
+
+const OUTBOUND_BUFFER: usize = 128;
+
+// frames are always websocket text, so the only thing left to negotiate is pipelining
+const OFFERED: [Capability; 1] = [Capability::Pipelining];
+
+// WebSocket transport: one text frame = one JSON Request / Response, same schema as the tcp
+// line protocol. Browsers can talk to FluxDB directly without the node bridge.
+// `limits`: max_request_bytes caps a message, max_subscriptions, the idle timeout and the rate
+// limits work like on tcp. max_connections is enforced by the accept loop (see reject_ws_connection).
+// `admin` is the server's policy for flush_all / backup without auth, see dispatch
+pub async fn handle_ws_connection(
+    stream: TcpStream,
+    handle: EngineHandle,
+    auth: Option<Arc<Authenticator>>,
+    limits: ServerLimits,
+    admin: Arc<AdminPolicy>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut gate = Gate::new(auth, stream.peer_addr()?.ip());
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(limits.max_request_bytes))
+        .max_frame_size(Some(limits.max_request_bytes));
+    let ws = accept_async_with_config(stream, Some(config)).await?; // http upgrade handshake
+    let (mut sink, mut source) = ws.split();
+
+    // same shape as the tcp server: everything outbound goes through one bounded channel
+    let (out_tx, mut out_rx) = mpsc::channel::<ResponseFrame>(OUTBOUND_BUFFER);
+    let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT));
+    let subscriptions = Arc::new(Semaphore::new(limits.max_subscriptions));
+    let rate = Arc::new(ConnectionRate::new(limits.conn_rate));
+
+    let writer_rate = rate.clone();
+    let writer_task = tokio::spawn(async move {
+        while let Some(resp) = out_rx.recv().await {
+            let text = match serde_json::to_string(&resp) {
+                Ok(t) => t,
+                Err(e) => {
+                    error!(error = %e, "response serialization failed");
+                    break;
+                }
+            };
+            if let Response::Value { .. } = resp.resp {
+                writer_rate.charge_read_bytes(text.len());
+            }
+
+            if sink.send(Message::Text(text.into())).await.is_err() {
+                break;
+            }
+        }
+        let _ = sink.close().await;
+    }.in_current_span());
+
+    let mut session = Negotiated::legacy();
+    let mut first_message = true;
+
+    loop {
+        // until the connection is authenticated, reads give up at the login deadline. An idle
+        // connection is closed too, unless it is only there to receive subscription events
+        let idle = limits.idle_timeout.filter(|_| {
+            subscriptions.available_permits() == limits.max_subscriptions
+        });
+        let idle_deadline = idle.map(|idle| Instant::now() + idle);
+        let wait = match (gate.deadline(), idle_deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let next = match wait {
+            Some(wait) => match timeout_at(wait, source.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    let message = if gate.deadline().is_some_and(|d| d <= wait) {
+                        "authentication timeout"
+                    } else {
+                        COUNTERS.idle_timeout();
+                        "idle timeout"
+                    };
+                    let message = message.to_string();
+                    let _ = out_tx.send(Response::Error { message }.into()).await;
+                    break;
+                }
+            },
+            None => source.next().await,
+        };
+        let Some(msg) = next else {
+            break;
+        };
+
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(WsError::Capacity(e)) => {
+                COUNTERS.request_too_large();
+                let message = format!("request too large: {e}");
+                let _ = out_tx.send(Response::Error { message }.into()).await;
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let text = match msg {
+            Message::Text(text) => text,
+            Message::Binary(bytes) => match String::from_utf8(bytes.to_vec()) {
+                Ok(text) => text.into(),
+                Err(_) => {
+                    let _ = out_tx
+                        .send(
+                            Response::Error {
+                                message: "binary frame is not utf-8 json".to_string(),
+                            }
+                            .into(),
+                        )
+                        .await;
+                    continue;
+                }
+            },
+            Message::Close(_) => break,
+            // ping/pong are answered by tungstenite itself
+            _ => continue,
+        };
+
+        let frame: RequestFrame = match serde_json::from_str(text.trim()) {
+            Ok(frame) => frame,
+            Err(e) => {
+                let _ = out_tx
+                    .send(
+                        Response::Error {
+                            message: format!("invalid request json: {e}"),
+                        }
+                        .into(),
+                    )
+                    .await;
+                continue;
+            }
+        };
+
+        // same handshake rules as tcp: Hello is only understood as the very first message
+        let is_first = std::mem::replace(&mut first_message, false);
+        if let (
+            true,
+            Request::Hello {
+                protocol_version,
+                capabilities,
+                ..
+            },
+        ) = (is_first, &frame.req)
+        {
+            match welcome(*protocol_version, capabilities, &OFFERED, limits.wire()) {
+                Ok((negotiated, resp)) => {
+                    session = negotiated;
+                    let _ = out_tx.send(ResponseFrame { id: frame.id, resp }).await;
+                    continue;
+                }
+                Err(message) => {
+                    let resp = Response::Error { message };
+                    let _ = out_tx.send(ResponseFrame { id: frame.id, resp }).await;
+                    break;
+                }
+            }
+        }
+
+        if let Some(resp) = gate.check(&frame.req).await {
+            let _ = out_tx.send(ResponseFrame { id: frame.id, resp }).await;
+            continue;
+        }
+
+        let principal = gate.principal();
+        if let Some(resp) = rate.check(principal.clone(), &frame.req, text.len()) {
+            let _ = out_tx.send(ResponseFrame { id: frame.id, resp }).await;
+            continue;
+        }
+
+        if session.pipelining {
+            dispatch_pipelined(
+                &handle,
+                frame,
+                &out_tx,
+                &in_flight,
+                principal,
+                &subscriptions,
+                &admin,
+            )
+            .await;
+        } else {
+            dispatch(&handle, frame, &out_tx, principal, &subscriptions, &admin).await;
+        }
+    }
+
+    drop(out_tx);
+    let _ = writer_task.await;
+    Ok(())
+}
+
+// over max_connections: finish the upgrade so the client gets a readable reason, then close
+pub async fn reject_ws_connection(
+    stream: TcpStream,
+    message: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut ws = accept_async(stream).await?;
+    let resp = ResponseFrame::from(Response::Error {
+        message: message.to_string(),
+    });
+    ws.send(Message::Text(serde_json::to_string(&resp)?.into()))
+        .await?;
+    ws.close(None).await?;
+    Ok(())
+}
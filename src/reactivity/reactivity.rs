@@ -1,8 +1,11 @@
 use std::collections::HashMap;
-use tokio::sync::mpsc;
+use std::sync::atomic::Ordering;
+use tokio::sync::mpsc::{self, error::TrySendError};
 
 use crate::event::Event;
+use crate::metrics::METRICS;
 use crate::reactivity::subscriber::Subscriber;
+use crate::store::glob::glob_match;
 
 const SUB_BUFFER: usize = 64;
 
@@ -11,6 +14,7 @@ const SUB_BUFFER: usize = 64;
 pub struct Reactivity {
     next_id: u64,                                        // the next id
     pub subscriptions: HashMap<String, Vec<Subscriber>>, // this is the hash map of the string (keys, and those who subscribed it )
+    pub pattern_subscriptions: Vec<(String, Subscriber)>, // glob pattern -> subscriber, checked against every event
 }
 
 impl Reactivity {
@@ -20,6 +24,7 @@ impl Reactivity {
         Self {
             next_id: 0,
             subscriptions: HashMap::new(),
+            pattern_subscriptions: Vec::new(),
         }
     }
 
@@ -51,10 +56,43 @@ impl Reactivity {
             .entry(key.to_string())
             .or_default()
             .push(subscriber);
+        METRICS.subscribers.fetch_add(1, Ordering::Relaxed);
 
         rx
     }
 
+    // same as subscribe but the subscriber gets every key matching the glob pattern (user:*)
+    pub fn subscribe_pattern(&mut self, pattern: &str) -> mpsc::Receiver<Event> {
+        let (tx, rx) = mpsc::channel(SUB_BUFFER);
+
+        let subscriber: Subscriber = Subscriber {
+            id: self.next_subscriber_id(),
+            tx,
+        };
+
+        self.pattern_subscriptions.push((pattern.to_string(), subscriber));
+        METRICS.subscribers.fetch_add(1, Ordering::Relaxed);
+
+        rx
+    }
+
+    // (key, pattern) subscribers still listening. Closed ones are skipped even if the next
+    // event for their key hasn't cleaned them up yet
+    pub fn open_subscriptions(&self) -> (u64, u64) {
+        let keys = self
+            .subscriptions
+            .values()
+            .flatten()
+            .filter(|sub| !sub.tx.is_closed())
+            .count();
+        let patterns = self
+            .pattern_subscriptions
+            .iter()
+            .filter(|(_, sub)| !sub.tx.is_closed())
+            .count();
+        (keys as u64, patterns as u64)
+    }
+
     // Dispatch Event
     /*
     Dispatch is to be called for a key and event is to be sent from the dispatch. For example key 1 has some changes
@@ -77,13 +115,46 @@ impl Reactivity {
 
                     Err(tokio::sync::mpsc::error::TrySendError::Full(_))=> {
                         // slow subscriber -> evict immediately (backpressure)
+                        METRICS.subscriber_evictions.fetch_add(1, Ordering::Relaxed);
                         dead_ids.push(sub.id);
                     }
                 }
             }
             // cleanup closed receivers
-            list.retain(|sub| !dead_ids.contains(&sub.id))
+            list.retain(|sub| !dead_ids.contains(&sub.id));
+            // a closed subscriber is only noticed (and uncounted) on the next event for its key
+            METRICS.subscribers.fetch_sub(dead_ids.len() as u64, Ordering::Relaxed);
         }
 
+        // pattern subscribers follow the same eviction rules
+        let mut dead_ids = Vec::new();
+        for (pattern, sub) in self.pattern_subscriptions.iter() {
+            if !glob_match(pattern, key) {
+                continue;
+            }
+            match sub.tx.try_send(event.clone()) {
+                Ok(_) => {}
+                Err(TrySendError::Closed(_)) => dead_ids.push(sub.id),
+                Err(TrySendError::Full(_)) => {
+                    METRICS.subscriber_evictions.fetch_add(1, Ordering::Relaxed);
+                    dead_ids.push(sub.id);
+                }
+            }
+        }
+        if !dead_ids.is_empty() {
+            self.pattern_subscriptions
+                .retain(|(_, sub)| !dead_ids.contains(&sub.id));
+            METRICS.subscribers.fetch_sub(dead_ids.len() as u64, Ordering::Relaxed);
+        }
+
+    }
+}
+
+// an engine that shuts down takes its subscribers with it
+impl Drop for Reactivity {
+    fn drop(&mut self) {
+        let open: usize = self.subscriptions.values().map(Vec::len).sum();
+        let open = open + self.pattern_subscriptions.len();
+        METRICS.subscribers.fetch_sub(open as u64, Ordering::Relaxed);
     }
 }
@@ -0,0 +1,127 @@
+// redis style glob matching, used for KEYS/SCAN MATCH and pattern subscriptions
+//
+//   *      any run of characters (including none)
+//   ?      exactly one character
+//   [abc]  one of a, b, c    [^a] / [!a] negated    [a-z] ranges
+//   \x     literal x
+// a pattern that matches `literal` and nothing else
+pub fn escape(literal: &str) -> String {
+    let mut escaped = String::with_capacity(literal.len());
+    for c in literal.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+pub fn glob_match(pattern: &str, key: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let k: Vec<char> = key.chars().collect();
+    match_from(&p, &k)
+}
+
+fn match_from(p: &[char], k: &[char]) -> bool {
+    let (mut pi, mut ki) = (0, 0);
+    // position of the last '*' and the key index it is currently absorbing up to (backtracking point)
+    let mut star: Option<(usize, usize)> = None;
+
+    while ki < k.len() {
+        if pi < p.len() {
+            match p[pi] {
+                '*' => {
+                    star = Some((pi, ki));
+                    pi += 1;
+                    continue;
+                }
+                '?' => {
+                    pi += 1;
+                    ki += 1;
+                    continue;
+                }
+                '[' => {
+                    if let Some((matched, next)) = match_class(p, pi, k[ki]) {
+                        if matched {
+                            pi = next;
+                            ki += 1;
+                            continue;
+                        }
+                    } else if k[ki] == '[' {
+                        // unterminated class, treat '[' literally
+                        pi += 1;
+                        ki += 1;
+                        continue;
+                    }
+                }
+                '\\' if pi + 1 < p.len() => {
+                    if p[pi + 1] == k[ki] {
+                        pi += 2;
+                        ki += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == k[ki] {
+                        pi += 1;
+                        ki += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        // mismatch: let the last '*' swallow one more character, or fail
+        match star {
+            Some((spi, ski)) => {
+                pi = spi + 1;
+                ki = ski + 1;
+                star = Some((spi, ski + 1));
+            }
+            None => return false,
+        }
+    }
+
+    // key consumed, the rest of the pattern must be all '*'
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+// returns (does c match the class starting at p[start] == '[', index after the closing ']')
+fn match_class(p: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = matches!(p.get(i), Some('^') | Some('!'));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while i < p.len() {
+        if p[i] == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+
+        let lo = if p[i] == '\\' && i + 1 < p.len() {
+            i += 1;
+            p[i]
+        } else {
+            p[i]
+        };
+
+        if i + 2 < p.len() && p[i + 1] == '-' && p[i + 2] != ']' {
+            let hi = p[i + 2];
+            if lo <= c && c <= hi {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if lo == c {
+                matched = true;
+            }
+            i += 1;
+        }
+    }
+
+    None
+}
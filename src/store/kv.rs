@@ -2,7 +2,8 @@ use serde_json::Value;
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 
-use crate::event::Event;
+use crate::event::{Event, EventOp};
+use crate::store::glob::glob_match;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Document {
@@ -23,6 +24,10 @@ impl Store {
     }
 
     pub fn apply_event(&mut self, event: Event) {
+        if event.op == EventOp::FlushAll {
+            self.data.clear();
+            return;
+        }
         // this takes in event and mutate the state the memory
         match event.new {
             Value::Null => {
@@ -44,10 +49,11 @@ impl Store {
     /*
      * Instead of mutating the state directly it should return the event and that event will go to the apply_event after it is written by WAL in a
      *WAL file and then it will be applid in the memory by apply_event
+     * `current` is the key's document as the writer sees it, which can be ahead of the store (see Database::latest)
      */
 
-    pub fn put(&self, key: String, value: Value) -> Event {
-        let (previous_state, version) = self.previous_state_info(&key);
+    pub fn put(current: Option<&Document>, key: String, value: Value) -> Event {
+        let (previous_state, version) = previous_state_info(current);
 
         let new_version = version;
 
@@ -56,6 +62,7 @@ impl Store {
             old: previous_state,
             new: value,
             version: new_version,
+            op: EventOp::Write,
         }
     }
 
@@ -63,18 +70,56 @@ impl Store {
         self.data.get(key)
     }
 
-    pub fn delete(&self, key: &str) -> Event {
-        let (previous_state, version) = self.previous_state_info(&key);
+    // sorted so repeated listings of an unchanged store come back in the same order
+    pub fn keys(&self, pattern: Option<&str>) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .data
+            .keys()
+            .filter(|k| pattern.is_none_or(|p| glob_match(p, k)))
+            .cloned()
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    pub fn delete(current: Option<&Document>, key: &str) -> Event {
+        let (previous_state, version) = previous_state_info(current);
         Event {
             key: key.to_string(),
             old: previous_state,
             new: Value::Null,
             version,
+            op: EventOp::Write,
+        }
+    }
+
+    // one wal record for the whole keyspace, see EventOp::FlushAll
+    pub fn flush_all() -> Event {
+        Event {
+            key: String::new(),
+            old: Value::Null,
+            new: Value::Null,
+            version: 0,
+            op: EventOp::FlushAll,
         }
     }
 
-    pub fn patch(&self, key: &str, delta: Value) -> Event {
-        let (previous_state, version) = self.previous_state_info(&key);
+    // what subscribers see of a flush: every key deleted, shaped like delete() events
+    pub fn flush_notifications(&self) -> Vec<Event> {
+        self.data
+            .iter()
+            .map(|(key, doc)| Event {
+                key: key.clone(),
+                old: doc.value.clone(),
+                new: Value::Null,
+                version: doc.version + 1,
+                op: EventOp::Write,
+            })
+            .collect()
+    }
+
+    pub fn patch(current: Option<&Document>, key: &str, delta: Value) -> Event {
+        let (previous_state, version) = previous_state_info(current);
 
         // create merged value without mutating store
 
@@ -86,19 +131,20 @@ impl Store {
             old: previous_state,
             new: new_value,
             version,
+            op: EventOp::Write,
         }
     }
+}
 
-    // Event struct must own the previous values so passing the reference to them and cloning them in the individual fuinction is sucha terible idea
-    fn previous_state_info(&self, key: &str) -> (Value, u64) {
-        match self.data.get(key) {
-            Some(doc) => (doc.value.clone(), doc.version + 1),
-            None => (Value::Null, 1),
-        }
+// Event struct must own the previous values so passing the reference to them and cloning them in the individual fuinction is sucha terible idea
+fn previous_state_info(current: Option<&Document>) -> (Value, u64) {
+    match current {
+        Some(doc) => (doc.value.clone(), doc.version + 1),
+        None => (Value::Null, 1),
     }
 }
 
-fn merge_json(target: &mut Value, delta: &Value) {
+pub(crate) fn merge_json(target: &mut Value, delta: &Value) {
     match (target, delta) {
         // If both are JSON objects → recursively merge fields
         (Value::Object(t), Value::Object(d)) => {
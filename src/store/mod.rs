@@ -1,3 +1,4 @@
 pub mod kv;
 pub mod wal;
-pub mod snapshot;
\ No newline at end of file
+pub mod snapshot;
+pub mod glob;
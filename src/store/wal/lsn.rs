@@ -1,3 +1,5 @@
+use std::{fmt, str::FromStr};
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -16,6 +18,24 @@ impl Lsn {
         Self { segment, offset }
     }
 }
+
+// "segment:offset", how the tools print and take an lsn
+impl fmt::Display for Lsn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.segment, self.offset)
+    }
+}
+
+impl FromStr for Lsn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s
+            .split_once(':')
+            .and_then(|(segment, offset)| Some(Lsn::new(segment.parse().ok()?, offset.parse().ok()?)));
+        parsed.ok_or_else(|| format!("bad lsn {s:?}, expected segment:offset"))
+    }
+}
 // lsn - log sequence number used to keep track of the position in the WAL for recovery and checkpointing
 // it is a tuple of (segment, offset) where segment is the index of the WAL file and offset is the byte offset within that file
 // for example, if we have wal files named wal_0, wal_1, wal_2, then the LSN (1, 100) would refer to the file wal_1 and the byte offset 100 within that file
\ No newline at end of file
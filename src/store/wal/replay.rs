@@ -1,8 +1,16 @@
-use std::{io, path::PathBuf};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
 
 use crate::{
     event::Event,
-    store::wal::{lsn::Lsn, segment::Segment, wal::Wal},
+    store::wal::{
+        lsn::Lsn,
+        segment::{RawRead, Segment},
+        wal::Wal,
+    },
 };
 
 pub struct WalIterator {
@@ -10,32 +18,144 @@ pub struct WalIterator {
     current_segment: Segment,
     current_segment_id: u64,
     last_segment_id: u64,
+    segment_done: bool, // at its end, or a torn record was hit: nothing more can be read from it
+}
+
+// one record as next_record sees it
+#[derive(Debug)]
+pub struct WalRecord {
+    pub lsn: Lsn,
+    pub entry: Result<Event, Damage>,
+}
+
+// see segment::RawRead for what each one means
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Damage {
+    // an append cut short at the end of its segment
+    Torn { needed: u64, available: u64 },
+    // a length that doesn't fit the data. The rest of the segment is skipped
+    TooLong { len: u64 },
+    // a whole record whose checksum doesn't match, skipped
+    Checksum { len: u64, stored: u32, computed: u32 },
+    // a whole record that isn't an event, skipped
+    Corrupt { len: u64, error: String },
+}
+
+impl fmt::Display for Damage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Damage::Torn { needed, available } => {
+                write!(f, "torn: record needs {needed} bytes, {available} left in the segment")
+            }
+            Damage::TooLong { len } => write!(f, "record length {len} doesn't fit the data"),
+            Damage::Checksum { len, stored, computed } => {
+                write!(f, "checksum mismatch in {len} byte record (stored {stored:08x}, computed {computed:08x})")
+            }
+            Damage::Corrupt { len, error } => write!(f, "corrupt {len} byte record: {error}"),
+        }
+    }
 }
 
 impl WalIterator {
-    // this will read only a single event and return, and if it is at the end, it will shift to next segment
-    pub fn next_event(&mut self) -> io::Result<Option<Event>> {
+    // every record with its Lsn, damaged ones included: there is no lenient reader that skips
+    // damage quietly, whoever replays decides what it means (db::replay). A corrupt record or a checksum failure is stepped over, a
+    // torn record or an impossible length ends its segment. Used by db::replay (open, fluxdb-dump),
+    // fsck and fluxdb-wal
+    pub fn next_record(&mut self) -> io::Result<Option<WalRecord>> {
         loop {
-            if let Some(event) = Segment::read_next(&mut self.current_segment)? {
-                return Ok(Some(event));
+            if !self.segment_done {
+                let lsn = Lsn::new(self.current_segment_id, self.current_segment.position()?);
+                let entry = match self.current_segment.read_next()? {
+                    RawRead::Event { event, .. } => Ok(event),
+                    RawRead::Corrupt { len, error } => Err(Damage::Corrupt { len, error }),
+                    RawRead::Checksum { len, stored, computed } => Err(Damage::Checksum { len, stored, computed }),
+                    RawRead::Torn { needed, available } => {
+                        self.segment_done = true;
+                        Err(Damage::Torn { needed, available })
+                    }
+                    RawRead::TooLong { len } => {
+                        self.segment_done = true;
+                        Err(Damage::TooLong { len })
+                    }
+                    RawRead::End => {
+                        self.segment_done = true;
+                        continue;
+                    }
+                };
+                return Ok(Some(WalRecord { lsn, entry }));
             }
 
-            // EOF reached -> check if more segment exists
             if self.current_segment_id >= self.last_segment_id {
                 return Ok(None);
             }
-
             self.current_segment_id += 1;
-            self.current_segment = Segment::open(&self.dir, self.current_segment_id)?;
+            self.current_segment = Segment::open_read_only(&self.dir, self.current_segment_id)?;
+            self.segment_done = false;
+        }
+    }
+
+    // reads <data_dir>/wal from `lsn` without a Wal, so nothing is created or written. Safe next
+    // to a running server: a record it is in the middle of appending reads as a torn tail
+    pub fn open(data_dir: &Path, lsn: Lsn) -> io::Result<Self> {
+        let dir = data_dir.join("wal");
+        let Some(&last_segment_id) = segment_ids(data_dir)?.last() else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no wal segments in {}", dir.display())));
+        };
+
+        let mut segment = Segment::open_read_only(&dir, lsn.segment)?;
+        segment.seek(lsn.offset)?;
+        Ok(WalIterator {
+            dir,
+            current_segment: segment,
+            current_segment_id: lsn.segment,
+            last_segment_id,
+            segment_done: false,
+        })
+    }
+
+    // only segment `id`, from its start. For checks that go segment by segment and must not
+    // trip over a missing one
+    pub fn segment(data_dir: &Path, id: u64) -> io::Result<Self> {
+        let dir = data_dir.join("wal");
+        Ok(WalIterator {
+            current_segment: Segment::open_read_only(&dir, id)?,
+            dir,
+            current_segment_id: id,
+            last_segment_id: id,
+            segment_done: false,
+        })
+    }
+}
+
+// `trailing` is the damage read after the last good record, up to the end of the wal. It can be
+// cut off (at the returned lsn) only if it is one append a crash interrupted: a single torn
+// record. Anything else, a bad checksum or length included, may be written data and is
+// corruption. Database::open, fluxdb-fsck and fluxdb-dump all go by this
+pub fn torn_tail(trailing: &[(Lsn, Damage)]) -> Option<Lsn> {
+    match trailing {
+        [(lsn, Damage::Torn { .. })] => Some(*lsn),
+        _ => None,
+    }
+}
+
+// ids of the <data_dir>/wal/N.log files, sorted
+pub fn segment_ids(data_dir: &Path) -> io::Result<Vec<u64>> {
+    let mut ids = vec![];
+    for entry in std::fs::read_dir(data_dir.join("wal"))? {
+        let name = entry?.file_name();
+        if let Some(id) = name.to_string_lossy().strip_suffix(".log").and_then(|id| id.parse().ok()) {
+            ids.push(id);
         }
     }
+    ids.sort_unstable();
+    Ok(ids)
 }
 
 impl Wal {
     pub fn replay_from(&self, lsn: Lsn) -> io::Result<WalIterator> {
         // open the starting postion from the lsn (segmnet id + offset)
 
-        let mut segment = Segment::open(&self.dir, lsn.segment)?;
+        let mut segment = Segment::open_read_only(&self.dir, lsn.segment)?;
 
         // seek to correct offset
         segment.seek(lsn.offset)?;
@@ -47,6 +167,7 @@ impl Wal {
             current_segment: segment,
             current_segment_id:lsn.segment,
             last_segment_id,
+            segment_done: false,
         })
     }
 
@@ -3,10 +3,29 @@ use std::io::{self, Read, SeekFrom};
 use std::io::{Seek, Write};
 use std::path::Path;
 
+use serde::de::IgnoredAny;
+
 use crate::event::Event;
 
+// On disk, a segment is a header and then records:
+//
+//   header  "FXWL" | format u16 | 0u16 | segment id u64        (16 bytes, big endian)
+//   record  len u32 | crc32c u32 | payload (json Event)         (crc over len + payload)
+//
+// Segments written before the header existed (format 0) are bare `len u32 | payload` records from
+// offset 0. They are told apart by their first 4 bytes: as a length, "FXWL" is over
+// MAX_RECORD_LEN, so no format 0 segment starts with it. They stay readable but are never appended
+// to, Wal::open starts a new segment instead.
+pub const MAGIC: [u8; 4] = *b"FXWL";
+pub const FORMAT: u16 = 1;
+pub const HEADER_LEN: u64 = 16;
+pub const RECORD_PREFIX_LEN: u64 = 8;
+// an event carries the old and the new value, each up to a max size request
+pub const MAX_RECORD_LEN: u64 = 64 * 1024 * 1024;
+
 pub struct Segment {
     pub id: u64,
+    pub format: u16,
     file: File,
 }
 
@@ -20,7 +39,9 @@ impl Segment {
             .read(true)
             .open(&path)?;
 
-        Ok(Self { id, file })
+        let mut segment = Self { id, format: FORMAT, file };
+        segment.write_header()?;
+        Ok(segment)
     }
 
     pub fn open<P: AsRef<Path>>(dir: P, id: u64) -> io::Result<Self> {
@@ -28,21 +49,92 @@ impl Segment {
 
         let file = OpenOptions::new().write(true).read(true).open(&path)?;
 
-        Ok(Self { id, file })
+        Self::from_file(id, file)
+    }
+
+    // for replay, which never appends
+    pub fn open_read_only<P: AsRef<Path>>(dir: P, id: u64) -> io::Result<Self> {
+        let file = File::open(dir.as_ref().join(format!("{}.log", id)))?;
+
+        Self::from_file(id, file)
+    }
+
+    // works out the format from the first bytes and leaves the cursor on the first record
+    fn from_file(id: u64, mut file: File) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        (&mut file).take(HEADER_LEN).read_to_end(&mut header)?;
+
+        let seen = header.len().min(MAGIC.len());
+        let mut segment = Self { id, format: 0, file };
+        if header[..seen] != MAGIC[..seen] {
+            // format 0, records from offset 0
+        } else if header.len() < HEADER_LEN as usize {
+            // empty, or a crash while the header was written. read_next reports the latter as
+            // torn at offset 0, Wal::open writes the header again
+            segment.format = FORMAT;
+        } else {
+            let format = u16::from_be_bytes([header[4], header[5]]);
+            let header_id = u64::from_be_bytes(header[8..16].try_into().unwrap());
+            if format != FORMAT {
+                return Err(invalid(format!("wal segment {id}: unknown format {format}")));
+            }
+            if header_id != id {
+                return Err(invalid(format!("wal segment {id}: header says it is segment {header_id}")));
+            }
+            segment.format = format;
+        }
+        segment.seek(0)?;
+        Ok(segment)
+    }
+
+    // (re)writes the header of an empty segment, or one whose header write was cut short
+    pub fn write_header(&mut self) -> io::Result<()> {
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&FORMAT.to_be_bytes());
+        header.extend_from_slice(&0u16.to_be_bytes());
+        header.extend_from_slice(&self.id.to_be_bytes());
+
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.sync_all()?;
+        self.format = FORMAT;
+        Ok(())
+    }
+
+    fn header_len(&self) -> u64 {
+        if self.format == 0 {
+            0
+        } else {
+            HEADER_LEN
+        }
     }
 
     pub fn append(&mut self, event: &Event) -> std::io::Result<u64> {
         let bytes = serde_json::to_vec(event).expect("event serialization must not fail");
+        if self.format == 0 {
+            return Err(invalid(format!("wal segment {} is format 0 and read only", self.id)));
+        }
+        if bytes.len() as u64 > MAX_RECORD_LEN {
+            return Err(invalid(format!("event of {} bytes exceeds the wal record limit of {MAX_RECORD_LEN}", bytes.len())));
+        }
+
+        let len = (bytes.len() as u32).to_be_bytes(); // store the bytes in u32 number (4 bytes)
+        let crc = crc32c::crc32c_append(crc32c::crc32c(&len), &bytes);
 
-        let len = bytes.len() as u32; // store the bytes in u32 number (4 bytes)
+        // one write for the whole record, so a crash leaves at most one torn record at the end
+        let mut record = Vec::with_capacity(RECORD_PREFIX_LEN as usize + bytes.len());
+        record.extend_from_slice(&len);
+        record.extend_from_slice(&crc.to_be_bytes());
+        record.extend_from_slice(&bytes);
 
         self.file.seek(SeekFrom::End(0))?; // making sure pointer is at the nd at the time of appending 
 
         // Capture starting offset (LSN offset part)
         let start_offset = self.file.stream_position()?;
-        self.file.write_all(&len.to_be_bytes())?;
 
-        self.file.write_all(&bytes)?; // modifying the page of the file object from segment struct, making it dirty and then flusing it using self.fsync command later
+        self.file.write_all(&record)?; // modifying the page of the file object from segment struct, making it dirty and then flusing it using self.fsync command later
 
         Ok(start_offset)
 
@@ -63,54 +155,111 @@ impl Segment {
         self.file.sync_all() // OS fsync api call // flush the OS page cache to disk
     }
 
-    // move the pointer of the file to the end
+    // move the pointer of the file to `offset`. Anything inside the header means the first
+    // record (the start of a torn header, so that read_next can report it)
     pub fn seek(&mut self, offset: u64) -> std::io::Result<()> {
+        let offset = if offset < self.header_len() {
+            self.header_len().min(self.size()?)
+        } else {
+            offset
+        };
         self.file.seek(std::io::SeekFrom::Start(offset))?;
         Ok(())
     }
 
+    // drops everything from `offset` on (a torn tail), returns how many bytes that was
+    pub fn truncate(&mut self, offset: u64) -> io::Result<u64> {
+        let bytes = self.size()?.saturating_sub(offset);
+        self.file.set_len(offset)?;
+        self.file.sync_all()?;
+        self.seek(offset)?;
+        Ok(bytes)
+    }
+
     pub fn size(&self) -> std::io::Result<u64> {
         Ok(self.file.metadata()?.len())
     }
 
-    // read only a single event at a time (the offset is controlled by the wal.rs)
-    pub fn read_next(&mut self) -> io::Result<Option<Event>> {
-        // ---- 1. Read 4-byte length prefix ----
-        let mut len_buf = [0u8; 4];
+    // where the next read starts
+    pub fn position(&mut self) -> io::Result<u64> {
+        self.file.stream_position()
+    }
 
-        match self.file.read_exact(&mut len_buf) {
-            Ok(_) => {}
-            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
-                // Clean EOF or torn tail → safe stop
-                return Ok(None);
-            }
-            Err(e) => return Err(e),
+    // read only a single record at a time (the offset is controlled by the wal.rs), and what it
+    // looked like: replay stops a segment at anything but an event, tools report it and go on
+    pub fn read_next(&mut self) -> io::Result<RawRead> {
+        let start = self.position()?;
+        let left = self.size()?.saturating_sub(start);
+        if left == 0 {
+            return Ok(RawRead::End);
+        }
+        if start < self.header_len() {
+            // only seek stops here, when the header itself is cut short
+            return Ok(RawRead::Torn { needed: self.header_len() - start, available: left });
         }
 
-        let len = u32::from_be_bytes(len_buf) as usize;
+        // ---- 1. Read the length prefix (and the checksum) ----
+        let prefix_len = if self.format == 0 { 4 } else { RECORD_PREFIX_LEN };
+        if left < prefix_len {
+            return Ok(RawRead::Torn { needed: prefix_len, available: left });
+        }
+        let mut prefix = [0u8; RECORD_PREFIX_LEN as usize];
+        self.file.read_exact(&mut prefix[..prefix_len as usize])?;
+        let len = u32::from_be_bytes(prefix[..4].try_into().unwrap()) as u64;
 
         // ---- 2. Read payload bytes ----
-        let mut data = vec![0u8; len];
+        // checked first, a garbage length must not turn into a huge allocation
+        if len > MAX_RECORD_LEN {
+            return Ok(RawRead::TooLong { len });
+        }
+        if len > left - prefix_len {
+            // one unfinished append leaves the start of its payload, a cut-off json value. A
+            // flipped length over whole records reads a complete value with more after it
+            let mut rest = vec![0u8; (left - prefix_len) as usize];
+            self.file.read_exact(&mut rest)?;
+            return Ok(match serde_json::from_slice::<IgnoredAny>(&rest) {
+                Err(e) if e.is_eof() => RawRead::Torn { needed: prefix_len + len, available: left },
+                _ => RawRead::TooLong { len },
+            });
+        }
+        let mut data = vec![0u8; len as usize];
+        self.file.read_exact(&mut data)?;
+        // Cursor already advanced by read_exact, past a bad payload too
+        let record_len = prefix_len + len;
 
-        match self.file.read_exact(&mut data) {
-            Ok(_) => {}
-            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
-                // Partial record at end → ignore safely
-                return Ok(None);
+        // ---- 3. Check it and deserialize into Event ----
+        if self.format != 0 {
+            let stored = u32::from_be_bytes(prefix[4..8].try_into().unwrap());
+            let computed = crc32c::crc32c_append(crc32c::crc32c(&prefix[..4]), &data);
+            if stored != computed {
+                return Ok(RawRead::Checksum { len: record_len, stored, computed });
             }
-            Err(e) => return Err(e),
         }
+        Ok(match serde_json::from_slice(&data) {
+            Ok(event) => RawRead::Event { event, len: record_len },
+            Err(e) => RawRead::Corrupt { len: record_len, error: e.to_string() },
+        })
+    }
+}
 
-        // ---- 3. Deserialize into Event ----
-        let event: Event = match serde_json::from_slice(&data) {
-            Ok(event) => event,
-            Err(e) => {
-                eprintln!("Warning: corrupt or torn WAL record at end: {}. Stopping replay.", e);
-                return Ok(None);
-            }
-        };
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
 
-        // Cursor already advanced by read_exact
-        Ok(Some(event))
-    }
+// lengths include the length / checksum prefix
+#[derive(Debug)]
+pub enum RawRead {
+    Event { event: Event, len: u64 },
+    End, // nothing left in the segment
+    // the record runs past the end of the file and what is there is the start of it: an append
+    // cut short. Nothing after it in the segment can be framed
+    Torn { needed: u64, available: u64 },
+    // a length that doesn't fit the data: over MAX_RECORD_LEN (append never writes one), or past
+    // the end of the file over bytes that aren't one cut-off record. Nothing after it can be framed
+    TooLong { len: u64 },
+    // whole, but the checksum doesn't match: flipped bits somewhere in the length or the payload.
+    // The next record is read where the length says it starts
+    Checksum { len: u64, stored: u32, computed: u32 },
+    // checksum fine (or format 0), but the payload isn't an event
+    Corrupt { len: u64, error: String },
 }
@@ -1,10 +1,13 @@
 use std::fs::create_dir_all;
 use std::io;
 use std::path::Path;
+use std::sync::atomic::Ordering;
 
 use crate::event::Event;
+use crate::metrics::METRICS;
+use tracing::{debug, info, warn};
 use crate::store::wal::lsn::Lsn;
-use crate::store::wal::segment::Segment;
+use crate::store::wal::segment::{Segment, HEADER_LEN, RECORD_PREFIX_LEN};
 
 pub struct Wal {
     pub dir: std::path::PathBuf, // because diferent os has different reprsentation strategy // Buf here means growable buffer
@@ -51,9 +54,21 @@ impl Wal {
             active_segment = seg;
         } else {
             let id = *segment_ids.last().unwrap();
-            let seg = Segment::open(&dir, id)?;
-            active_segment_id = id;
-            next_segment_id = id + 1;
+            let mut seg = Segment::open(&dir, id)?;
+            let size = seg.size()?;
+            if seg.format == 0 {
+                // written before segment headers, new records go to a segment of their own
+                seg = Segment::create(&dir, id + 1)?;
+                info!(segment = id + 1, "last wal segment has no header (format 0), continuing in a new one");
+            } else if size < HEADER_LEN {
+                // a crash while the segment was created, nothing can have been appended yet
+                if size > 0 {
+                    warn!(segment = id, bytes = size, "torn wal segment header, rewriting it");
+                }
+                seg.write_header()?;
+            }
+            active_segment_id = seg.id;
+            next_segment_id = seg.id + 1;
             active_segment = seg;
         }
 
@@ -71,7 +86,7 @@ impl Wal {
             std::io::Error::new(std::io::ErrorKind::InvalidData, "serialization failed")
         })?;
 
-        let record_size = 4 + payload.len() as u64;
+        let record_size = RECORD_PREFIX_LEN + payload.len() as u64;
 
         let current_size = self.active_segment.size()?;
 
@@ -81,6 +96,8 @@ impl Wal {
 
         // appending to the segment structed linked to wal
         let offset = self.active_segment.append(event)?;
+        METRICS.wal_bytes_appended.fetch_add(record_size, Ordering::Relaxed);
+        debug!(segment = self.active_segment_id, offset, bytes = record_size, "wal append");
         Ok(Lsn {
             segment: self.active_segment_id,
             offset,
@@ -96,10 +113,20 @@ impl Wal {
         self.active_segment = Segment::create(&self.dir, new_id)?;
 
         self.active_segment_id = new_id;
+        METRICS.wal_segment_rotations.fetch_add(1, Ordering::Relaxed);
+        info!(segment = new_id, "wal segment rotated");
 
         Ok(())
     }
 
+    // cuts the segment at `lsn` back to lsn.offset, returns how many bytes went
+    pub fn truncate_tail(&mut self, lsn: Lsn) -> io::Result<u64> {
+        if lsn.segment == self.active_segment_id {
+            return self.active_segment.truncate(lsn.offset);
+        }
+        Segment::open(&self.dir, lsn.segment)?.truncate(lsn.offset)
+    }
+
     pub fn current_lsn(&self) -> io::Result<Lsn> {
         let offset = self.active_segment.size()?;
 
@@ -109,6 +136,20 @@ impl Wal {
         })
     }
 
+    // (segments, bytes) of the wal dir as it is on disk
+    pub fn disk_usage(&self) -> io::Result<(u64, u64)> {
+        let mut segments = 0;
+        let mut bytes = 0;
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().ends_with(".log") {
+                segments += 1;
+                bytes += entry.metadata()?.len();
+            }
+        }
+        Ok((segments, bytes))
+    }
+
     pub fn gc(&mut self, upto: Lsn) -> io::Result<()> {
         // delete all segments with id < upto.segment
         for segment_id in 0..upto.segment {
@@ -0,0 +1,195 @@
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::net::acl::{all_classes, all_keys};
+use fluxdb::net::auth::{hash_secret, AuthConfig, Authenticator, UserEntry};
+use fluxdb::net::{http, limits::ServerLimits, resp, ws::handle_ws_connection};
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+fn authenticator(timeout_secs: u64) -> Arc<Authenticator> {
+    let config = AuthConfig {
+        timeout_secs,
+        default_rate: None,
+        users: vec![UserEntry {
+            name: "alice".to_string(),
+            hash: hash_secret("s3cret").unwrap(),
+            commands: all_classes(),
+            keys: all_keys(),
+            rate: None,
+        }],
+    };
+    Arc::new(Authenticator::from_config(config).unwrap())
+}
+
+#[tokio::test]
+async fn test_websocket_auth_lockout_and_timeout() {
+    let runtime = EngineRuntime::start();
+    let handle = runtime.handle;
+    let auth = authenticator(1);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (handle, auth) = (handle.clone(), auth.clone());
+            tokio::spawn(async move {
+                let _ = handle_ws_connection(
+                    stream,
+                    handle,
+                    Some(auth),
+                    ServerLimits::default(),
+                    Default::default(),
+                )
+                .await;
+            });
+        }
+    });
+
+    let (mut ws, _) = connect_async(format!("ws://{addr}")).await.unwrap();
+    let mut call = async |text: &str| -> Value {
+        ws.send(Message::Text(text.to_string().into())).await.unwrap();
+        serde_json::from_str(ws.next().await.unwrap().unwrap().to_text().unwrap()).unwrap()
+    };
+
+    let reply = call(r#"{"kind":"snapshot"}"#).await;
+    assert_eq!(reply["message"], "authentication required");
+    let reply = call(r#"{"kind":"auth","user":"alice","secret":"wrong"}"#).await;
+    assert_eq!(reply["message"], "invalid user or secret");
+    let reply = call(r#"{"kind":"auth","user":"nobody","secret":"s3cret"}"#).await;
+    assert_eq!(reply["message"], "invalid user or secret");
+    let reply = call(r#"{"kind":"auth","user":"alice","secret":"s3cret"}"#).await;
+    assert_eq!(reply["kind"], "ok");
+    let reply = call(r#"{"kind":"get","key":"auth_missing"}"#).await;
+    assert_eq!(reply["kind"], "value");
+
+    // lockout is per peer ip: after 5 failures in a row even the right secret is refused for a while
+    for _ in 0..4 {
+        call(r#"{"kind":"auth","user":"alice","secret":"wrong"}"#).await;
+    }
+    let reply = call(r#"{"kind":"auth","user":"alice","secret":"wrong"}"#).await;
+    assert_eq!(reply["message"], "invalid user or secret");
+    let reply = call(r#"{"kind":"auth","user":"alice","secret":"s3cret"}"#).await;
+    assert!(reply["message"].as_str().unwrap().starts_with("too many failed auth attempts"));
+    // the failed re-auth didn't drop the existing login
+    let reply = call(r#"{"kind":"get","key":"auth_missing"}"#).await;
+    assert_eq!(reply["kind"], "value");
+
+    // a connection that never authenticates is told so and closed after timeout_secs
+    let (mut idle, _) = connect_async(format!("ws://{addr}")).await.unwrap();
+    let msg = tokio::time::timeout(Duration::from_secs(3), idle.next())
+        .await
+        .expect("server kept the idle connection open")
+        .unwrap()
+        .unwrap();
+    let reply: Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+    assert_eq!(reply["message"], "authentication timeout");
+    assert!(matches!(idle.next().await, None | Some(Ok(Message::Close(_))) | Some(Err(_))));
+}
+
+async fn roundtrip(stream: &mut TcpStream, out: &str) -> String {
+    stream.write_all(out.as_bytes()).await.unwrap();
+    let mut buf = [0u8; 1024];
+    let n = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    String::from_utf8_lossy(&buf[..n]).to_string()
+}
+
+#[tokio::test]
+async fn test_http_basic_and_resp_auth() {
+    let runtime = EngineRuntime::start();
+    let auth = authenticator(10);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let http_addr = listener.local_addr().unwrap();
+    tokio::spawn(http::serve(listener, runtime.handle.clone(), Some(auth.clone())));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let resp_addr = listener.local_addr().unwrap();
+    tokio::spawn(resp::serve(listener, runtime.handle, Some(auth)));
+
+    // base64("alice:s3cret") = YWxpY2U6czNjcmV0
+    for (header, status) in [("", "401"), ("Authorization: Basic YWxpY2U6czNjcmV0\r\n", "404")] {
+        let mut c = TcpStream::connect(http_addr).await.unwrap();
+        let req = format!(
+            "GET /kv/auth_http_missing HTTP/1.1\r\nHost: x\r\nConnection: close\r\n{header}\r\n"
+        );
+        let reply = roundtrip(&mut c, &req).await;
+        assert!(reply.starts_with(&format!("HTTP/1.1 {status}")), "{reply}");
+    }
+
+    let mut c = TcpStream::connect(resp_addr).await.unwrap();
+    let reply = roundtrip(&mut c, "*2\r\n$3\r\nGET\r\n$1\r\na\r\n").await;
+    assert!(reply.starts_with("-NOAUTH"), "{reply}");
+    let reply = roundtrip(&mut c, "*3\r\n$4\r\nAUTH\r\n$5\r\nalice\r\n$3\r\nbad\r\n").await;
+    assert!(reply.starts_with("-WRONGPASS"), "{reply}");
+    let reply = roundtrip(&mut c, "*3\r\n$4\r\nAUTH\r\n$5\r\nalice\r\n$6\r\ns3cret\r\n").await;
+    assert_eq!(reply, "+OK\r\n");
+    let reply = roundtrip(&mut c, "*1\r\n$4\r\nPING\r\n").await;
+    assert_eq!(reply, "+PONG\r\n");
+}
+
+#[tokio::test]
+async fn test_unknown_user_costs_an_argon2_verify() {
+    let auth = authenticator(10);
+    let time = async |peer: [u8; 4], user: &str| {
+        let started = std::time::Instant::now();
+        let result = auth.authenticate(peer.into(), user, "wrong").await;
+        assert_eq!(result.unwrap_err(), "invalid user or secret");
+        started.elapsed()
+    };
+
+    // fastest of three, each attempt from its own peer so none gets near the lockout
+    let (mut wrong_secret, mut unknown_user) = (Duration::MAX, Duration::MAX);
+    for i in 0..3 {
+        wrong_secret = wrong_secret.min(time([10, 0, 1, i], "alice").await);
+        unknown_user = unknown_user.min(time([10, 0, 2, i], "mallory").await);
+    }
+    // both pay the failure delay, the hash makes up the rest
+    let hashing = wrong_secret.saturating_sub(Duration::from_millis(250));
+    assert!(
+        unknown_user >= Duration::from_millis(250) + hashing / 2,
+        "unknown {unknown_user:?} vs wrong secret {wrong_secret:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_verified_secret_is_cached_until_reload() {
+    let auth = authenticator(10);
+    let peer = [10, 0, 3, 1].into();
+    let time = async |secret: &str| {
+        let started = std::time::Instant::now();
+        let result = auth.authenticate(peer, "alice", secret).await;
+        (result, started.elapsed())
+    };
+
+    let (first, hashed) = time("s3cret").await;
+    first.unwrap();
+    // keep-alive traffic sends the same credentials again, that doesn't hash
+    let (again, cached) = time("s3cret").await;
+    again.unwrap();
+    assert!(cached * 10 < hashed, "cached {cached:?} vs hashed {hashed:?}");
+    assert!(time("s3cretX").await.0.is_err());
+
+    // a new secret on reload ends the old one right away
+    auth.replace(AuthConfig {
+        timeout_secs: 10,
+        default_rate: None,
+        users: vec![UserEntry {
+            name: "alice".to_string(),
+            hash: hash_secret("rotated").unwrap(),
+            commands: all_classes(),
+            keys: all_keys(),
+            rate: None,
+        }],
+    })
+    .unwrap();
+    assert_eq!(time("s3cret").await.0.unwrap_err(), "invalid user or secret");
+    time("rotated").await.0.unwrap();
+}
@@ -0,0 +1,190 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use fluxdb::engine::backup;
+use fluxdb::engine::config::{Durability, EngineConfig};
+use fluxdb::engine::db::Database;
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::net::acl::{all_classes, all_keys, AdminPolicy, Principal};
+use fluxdb::net::auth::{hash_secret, AuthConfig, Authenticator, UserEntry};
+use fluxdb::net::dispatch::dispatch;
+use fluxdb::net::protocol::{Request, Response};
+use fluxdb::store::kv::Store;
+use serde_json::json;
+use tokio::sync::{mpsc, RwLock, Semaphore};
+
+fn clean(dirs: &[&str]) {
+    for dir in dirs {
+        if fs::metadata(dir).is_ok() {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
+
+async fn open_keys(data_dir: &str) -> Vec<String> {
+    let store = Arc::new(RwLock::new(Store::new()));
+    let _db = Database::open(data_dir, store.clone()).await.unwrap();
+    let keys = store.read().await.keys(None);
+    keys
+}
+
+#[tokio::test]
+async fn test_backup_while_writing_restores_a_consistent_copy() {
+    let (data_dir, backup_dir, restored) =
+        ("./test_backup_data", "./test_backup_copy", "./test_backup_restored");
+    clean(&[data_dir, backup_dir, restored]);
+    let config = EngineConfig {
+        data_dir: data_dir.into(),
+        durability: Durability::Sync,
+        // small segments so the backup spans several
+        segment_size: 4096,
+        ..EngineConfig::default()
+    };
+    let handle = EngineRuntime::start_with(config).handle;
+
+    for i in 0..100 {
+        handle.set(format!("bk_{i:03}"), json!({"i": i, "pad": "x".repeat(64)})).await.unwrap();
+    }
+    handle.snapshot().await.unwrap();
+    for i in 100..200 {
+        handle.set(format!("bk_{i:03}"), json!({"i": i})).await.unwrap();
+    }
+
+    // writes keep going while the backup is taken
+    let writer = {
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            for i in 200..400 {
+                handle.set(format!("bk_{i:03}"), json!({"i": i})).await.unwrap();
+            }
+        })
+    };
+    let manifest = handle.backup(backup_dir.into()).await.unwrap();
+    writer.await.unwrap();
+
+    let (start, end) = (manifest.snapshot_lsn, manifest.end_lsn);
+    assert!((end.segment, end.offset) >= (start.segment, start.offset));
+    assert!(end.segment > 0, "expected the wal to have rotated");
+    assert_eq!(backup::verify(Path::new(backup_dir)).unwrap(), manifest);
+    // a second backup into the same place is refused
+    assert!(handle.backup(backup_dir.into()).await.unwrap_err().contains("not empty"));
+
+    let (_, previous) = backup::restore(Path::new(backup_dir), Path::new(restored), false).unwrap();
+    assert!(previous.is_none());
+    let keys = open_keys(restored).await;
+    // everything acked before the backup started, then an unbroken run of the concurrent ones
+    assert!(keys.len() >= 200 && keys.len() <= 400, "{}", keys.len());
+    let expected: Vec<String> = (0..keys.len()).map(|i| format!("bk_{i:03}")).collect();
+    assert_eq!(keys, expected);
+
+    // restoring over data needs force, and keeps the old dir around
+    let err = backup::restore(Path::new(backup_dir), Path::new(restored), false).unwrap_err();
+    assert!(err.contains("--force"), "{err}");
+    let (_, previous) = backup::restore(Path::new(backup_dir), Path::new(restored), true).unwrap();
+    let previous = previous.unwrap();
+    assert_eq!(open_keys(restored).await, expected);
+
+    clean(&[data_dir, backup_dir, restored, previous.to_str().unwrap()]);
+}
+
+#[tokio::test]
+async fn test_restore_rejects_a_damaged_backup() {
+    let (data_dir, backup_dir, target) =
+        ("./test_backup_bad_data", "./test_backup_bad_copy", "./test_backup_bad_target");
+    clean(&[data_dir, backup_dir, target]);
+    let config = EngineConfig {
+        data_dir: data_dir.into(),
+        durability: Durability::Sync,
+        ..EngineConfig::default()
+    };
+    let handle = EngineRuntime::start_with(config).handle;
+    handle.set("bad_a".to_string(), json!(1)).await.unwrap();
+    let manifest = handle.backup(backup_dir.into()).await.unwrap();
+    handle.set("bad_b".to_string(), json!(2)).await.unwrap();
+
+    // the target already holds something that must survive a failed restore
+    fs::create_dir_all(target).unwrap();
+    fs::write(Path::new(target).join("keep"), b"mine").unwrap();
+
+    // flip one byte of the wal segment
+    let wal = Path::new(backup_dir).join(&manifest.files.last().unwrap().path);
+    let mut bytes = fs::read(&wal).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    fs::write(&wal, bytes).unwrap();
+
+    let err = backup::verify(Path::new(backup_dir)).unwrap_err();
+    assert!(err.contains("does not match the manifest"), "{err}");
+    assert!(backup::restore(Path::new(backup_dir), Path::new(target), true).is_err());
+    assert_eq!(fs::read(Path::new(target).join("keep")).unwrap(), b"mine");
+
+    // no manifest: an unfinished backup
+    fs::remove_file(Path::new(backup_dir).join(backup::MANIFEST)).unwrap();
+    assert!(backup::verify(Path::new(backup_dir)).unwrap_err().contains("manifest.json"));
+
+    clean(&[data_dir, backup_dir, target]);
+}
+
+#[tokio::test]
+async fn test_backup_requests_stay_below_the_backup_root() {
+    let (data_dir, root) = ("./test_backup_root_data", "./test_backup_root");
+    clean(&[data_dir, root]);
+    let config = EngineConfig {
+        data_dir: data_dir.into(),
+        ..EngineConfig::default()
+    };
+    let handle = EngineRuntime::start_with(config).handle;
+    handle.set("bk_root".to_string(), json!(1)).await.unwrap();
+
+    let auth = Arc::new(
+        Authenticator::from_config(AuthConfig {
+            timeout_secs: 10,
+            default_rate: None,
+            users: vec![UserEntry {
+                name: "admin".to_string(),
+                hash: hash_secret("pw").unwrap(),
+                commands: all_classes(),
+                keys: all_keys(),
+                rate: None,
+            }],
+        })
+        .unwrap(),
+    );
+    let backup = async |admin: &AdminPolicy, dest_dir: &str| {
+        let (out_tx, mut out_rx) = mpsc::channel(4);
+        let req = Request::Backup {
+            dest_dir: dest_dir.to_string(),
+        };
+        let principal = Some(Principal::new(auth.clone(), "admin".to_string()));
+        let subscriptions = Arc::new(Semaphore::new(1));
+        dispatch(&handle, req.into(), &out_tx, principal, &subscriptions, admin).await;
+        out_rx.recv().await.unwrap().resp
+    };
+    let refused = |resp: Response, expected: &str| match resp {
+        Response::Error { message } => assert!(message.contains(expected), "{message}"),
+        other => panic!("unexpected {other:?}"),
+    };
+
+    // no root configured: no backups at all
+    refused(backup(&AdminPolicy::default(), "b1").await, "backups are disabled");
+
+    let admin = AdminPolicy {
+        backup_root: Some(root.into()),
+        ..AdminPolicy::default()
+    };
+    for dest_dir in ["/tmp/test_backup_escape", "../test_backup_escape", "b1/../../x", "", "."] {
+        refused(backup(&admin, dest_dir).await, "below the backup root");
+    }
+    assert!(!Path::new("./test_backup_escape").exists());
+
+    match backup(&admin, "nightly/b1").await {
+        Response::Backup { manifest } => {
+            let on_disk = backup::verify(&Path::new(root).join("nightly/b1")).unwrap();
+            assert_eq!(on_disk, manifest);
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+
+    clean(&[data_dir, root]);
+}
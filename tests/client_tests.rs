@@ -0,0 +1,165 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use fluxdb::client::{blocking, Client, ClientError, ClientOptions};
+use fluxdb::engine::handler::EngineHandle;
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::net::codec::{encode, read_frame};
+use fluxdb::net::dispatch::{dispatch_pipelined, MAX_IN_FLIGHT};
+use fluxdb::net::handshake::welcome;
+use fluxdb::net::limits::ServerLimits;
+use fluxdb::net::protocol::{Capability, Framing, Request, RequestFrame, ResponseFrame};
+use serde_json::json;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::timeout;
+
+// the tcp protocol minus auth and limits, enough to point a Client at. Aborting the returned
+// task closes the listener and every connection it accepted
+fn serve(listener: TcpListener, handle: EngineHandle) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut connections = JoinSet::new();
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            connections.spawn(serve_connection(stream, handle.clone()));
+        }
+    })
+}
+
+async fn serve_connection(stream: TcpStream, handle: EngineHandle) {
+    let (read_half, mut write_half) = stream.into_split();
+    let mut reader = BufReader::new(read_half);
+    let mut buf = Vec::new();
+
+    let Ok(Some(Ok(RequestFrame { id, req: Request::Hello { protocol_version, capabilities, .. }, .. }))) =
+        read_frame(&mut reader, Framing::Json, &mut buf).await
+    else {
+        return;
+    };
+    let offered = [Capability::Pipelining];
+    let (_, resp) = welcome(protocol_version, &capabilities, &offered, ServerLimits::default().wire()).unwrap();
+    write_half.write_all(&encode(Framing::Json, &ResponseFrame { id, resp }).unwrap()).await.unwrap();
+
+    let (out_tx, mut out_rx) = mpsc::channel::<ResponseFrame>(128);
+    let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT));
+    let subscriptions = Arc::new(Semaphore::new(16));
+    let admin = Arc::default();
+    loop {
+        tokio::select! {
+            frame = read_frame::<_, RequestFrame>(&mut reader, Framing::Json, &mut buf) => match frame {
+                Ok(Some(Ok(frame))) => {
+                    dispatch_pipelined(&handle, frame, &out_tx, &in_flight, None, &subscriptions, &admin).await
+                }
+                _ => return,
+            },
+            Some(resp) = out_rx.recv() => {
+                if write_half.write_all(&encode(Framing::Json, &resp).unwrap()).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_client_mirrors_engine_handle() {
+    let handle = EngineRuntime::start().handle;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let _server = serve(listener, handle);
+
+    let client = Client::connect(&addr).await.unwrap();
+    client.set("cl_a".to_string(), json!({"n": 1})).await.unwrap();
+    client.patch("cl_a".to_string(), json!({"m": 2})).await.unwrap();
+    let doc = client.get("cl_a".to_string()).await.unwrap().unwrap();
+    assert_eq!(doc.value, json!({"n": 1, "m": 2}));
+
+    client.delete("cl_a".to_string()).await.unwrap();
+    assert!(client.get("cl_a".to_string()).await.unwrap().is_none());
+
+    // subscriptions have their own method, the connection does hello / auth itself
+    assert!(matches!(
+        client.request(Request::Subscribe { key: "x".to_string() }).await,
+        Err(ClientError::Protocol(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_client_reconnects_and_resubscribes() {
+    let handle = EngineRuntime::start().handle;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = serve(listener, handle.clone());
+
+    let client = Client::connect_with(ClientOptions {
+        pool_size: 2,
+        backoff_initial: Duration::from_millis(20),
+        backoff_max: Duration::from_millis(100),
+        ..ClientOptions::tcp(&addr.to_string())
+    })
+    .await
+    .unwrap();
+
+    client.set("cl_sub".to_string(), json!(1)).await.unwrap();
+    let mut events = client.subscribe("cl_sub".to_string()).await.unwrap();
+    client.set("cl_sub".to_string(), json!(2)).await.unwrap();
+    let event = timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+    assert_eq!(event.new, json!(2));
+
+    // the server goes away, and the key changes twice while the client can't see it
+    server.abort();
+    let _ = server.await;
+    handle.set("cl_sub".to_string(), json!(3)).await.unwrap();
+    handle.set("cl_sub".to_string(), json!(4)).await.unwrap();
+
+    let listener = TcpListener::bind(addr).await.unwrap();
+    let _server = serve(listener, handle.clone());
+
+    // the missed changes arrive as one catch-up event, then live events continue
+    let event = timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+    assert_eq!((event.old, event.new), (json!(2), json!(4)));
+
+    client.set("cl_sub".to_string(), json!(5)).await.unwrap();
+    let event = timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+    assert_eq!(event.new, json!(5));
+    assert_eq!(client.get("cl_sub".to_string()).await.unwrap().unwrap().value, json!(5));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_blocking_client() {
+    let handle = EngineRuntime::start().handle;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let server = serve(listener, handle.clone());
+
+    let events = tokio::task::spawn_blocking(move || {
+        let mut client = blocking::Client::connect(&addr).unwrap();
+        client.set("cl_sync".to_string(), json!({"n": 1})).unwrap();
+        client.patch("cl_sync".to_string(), json!({"m": 2})).unwrap();
+        assert_eq!(client.get("cl_sync".to_string()).unwrap().unwrap().value, json!({"n": 1, "m": 2}));
+        assert!(matches!(
+            client.request(Request::Hello { protocol_version: 1, client_name: None, capabilities: vec![] }),
+            Err(ClientError::Protocol(_))
+        ));
+
+        // the subscription has its own connection, the client keeps answering requests
+        let mut events = client.subscribe("cl_sync".to_string()).unwrap();
+        client.set("cl_sync".to_string(), json!(2)).unwrap();
+        client.delete("cl_sync".to_string()).unwrap();
+        let event = events.next().unwrap().unwrap();
+        assert_eq!(event.new, json!(2));
+        let event = events.next().unwrap().unwrap();
+        assert_eq!(event.new, json!(null));
+        events
+    })
+    .await
+    .unwrap();
+
+    // the iterator ends once the server is gone
+    server.abort();
+    let _ = server.await;
+    let rest = tokio::task::spawn_blocking(move || events.count()).await.unwrap();
+    assert_eq!(rest, 0);
+}
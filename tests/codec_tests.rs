@@ -0,0 +1,59 @@
+use fluxdb::net::codec::{encode, read_frame, MAX_FRAME_LEN};
+use fluxdb::net::protocol::{Framing, Request, RequestFrame};
+use serde_json::json;
+use tokio::io::{AsyncWriteExt, BufReader};
+
+#[tokio::test]
+async fn test_frames_roundtrip_in_every_framing() {
+    for framing in [Framing::Json, Framing::Msgpack, Framing::MsgpackDeflate] {
+        let frames = vec![
+            RequestFrame {
+                id: Some(1),
+                trace_id: Some("req-7f3a".to_string()),
+                req: Request::Set {
+                    key: "a".to_string(),
+                    value: json!({"nested": [1, 2.5, null, "s"], "flag": true}),
+                },
+            },
+            RequestFrame::from(Request::Snapshot),
+        ];
+
+        let mut bytes = Vec::new();
+        for frame in &frames {
+            bytes.extend(encode(framing, frame).unwrap());
+        }
+
+        let mut reader = BufReader::new(&bytes[..]);
+        let mut buf = Vec::new();
+        for expected in &frames {
+            let got: RequestFrame = read_frame(&mut reader, framing, &mut buf)
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                serde_json::to_value(&got).unwrap(),
+                serde_json::to_value(expected).unwrap()
+            );
+        }
+
+        // clean EOF after the last frame
+        let end = read_frame::<_, RequestFrame>(&mut reader, framing, &mut buf).await.unwrap();
+        assert!(end.is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_oversized_binary_frame_is_rejected() {
+    let (mut client, server) = tokio::io::duplex(64);
+    client
+        .write_all(&((MAX_FRAME_LEN as u32) + 1).to_be_bytes())
+        .await
+        .unwrap();
+
+    let mut reader = BufReader::new(server);
+    let mut buf = Vec::new();
+    let res = read_frame::<_, RequestFrame>(&mut reader, Framing::Msgpack, &mut buf).await;
+    assert!(res.is_err());
+    assert!(buf.capacity() < MAX_FRAME_LEN);
+}
@@ -1,3 +1,4 @@
+use fluxdb::engine::config::EngineConfig;
 use fluxdb::engine::runtime::EngineRuntime;
 use serde_json::json;
 use std::sync::Arc;
@@ -64,3 +65,47 @@ async fn test_concurrency_correctness() {
 
     join_all(tasks).await;
 }
+
+#[tokio::test]
+async fn test_conditional_writes_in_one_batch_conflict() {
+    let test_dir = "./test_concurrency_conditional";
+    if std::fs::metadata(test_dir).is_ok() {
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+    let config = EngineConfig { data_dir: test_dir.into(), ..EngineConfig::default() };
+    let handle = Arc::new(EngineRuntime::start_with(config).handle);
+    handle.set("k".to_string(), json!(0)).await.unwrap();
+
+    // both read version 1; sent together they land in one fsync batch, only one may win
+    for round in 1..=20u64 {
+        let writes = (0..4).map(|i| {
+            let h = handle.clone();
+            tokio::spawn(async move { h.set_if_version("k".to_string(), json!(i), Some(round)).await })
+        });
+        let won = join_all(writes).await.into_iter().filter(|r| r.as_ref().unwrap().is_ok()).count();
+        assert_eq!(won, 1, "round {round}");
+        assert_eq!(handle.get("k".to_string()).await.unwrap().unwrap().version, round + 1);
+    }
+
+    // create-if-missing (SET NX): one creator
+    let creates = (0..8).map(|i| {
+        let h = handle.clone();
+        tokio::spawn(async move { h.set_if_version("new".to_string(), json!(i), Some(0)).await })
+    });
+    let won = join_all(creates).await.into_iter().filter(|r| r.as_ref().unwrap().is_ok()).count();
+    assert_eq!(won, 1);
+
+    // patches in one batch build on each other instead of on the store
+    let patches = (0..8).map(|i| {
+        let h = handle.clone();
+        tokio::spawn(async move { h.patch("doc".to_string(), json!({ format!("f{i}"): i })).await })
+    });
+    for r in join_all(patches).await {
+        r.unwrap().unwrap();
+    }
+    let doc = handle.get("doc".to_string()).await.unwrap().unwrap();
+    assert_eq!(doc.value.as_object().unwrap().len(), 8);
+    assert_eq!(doc.version, 8);
+
+    std::fs::remove_dir_all(test_dir).unwrap();
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use fluxdb::config::ServerConfig;
+use fluxdb::engine::config::{Durability, EngineConfig};
+use fluxdb::engine::db::Database;
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::store::kv::Store;
+use serde_json::json;
+use tokio::sync::RwLock;
+
+#[test]
+fn test_config_file_overrides_defaults() {
+    let config: ServerConfig = toml::from_str(
+        r#"
+        addr = "127.0.0.1:7100"
+        data_dir = "/var/lib/fluxdb/b"
+
+        [wal]
+        segment_size = 1048576
+        durability = "periodic"
+        fsync_interval_ms = 20
+
+        [snapshot]
+        every_writes = 50
+
+        [limits]
+        max_connections = 10
+        idle_timeout_secs = 0
+        conn_rate = { write_ops = 5 }
+
+        [admin]
+        allow_anonymous = true
+        "#,
+    )
+    .unwrap();
+    config.validate().unwrap();
+
+    let engine = config.engine();
+    assert_eq!(engine.data_dir, Path::new("/var/lib/fluxdb/b"));
+    assert_eq!(engine.segment_size, 1048576);
+    assert_eq!(engine.durability, Durability::Periodic);
+    assert_eq!(engine.fsync_interval, Duration::from_millis(20));
+    assert_eq!(engine.snapshot_every, 50);
+    // left out = default
+    assert_eq!(engine.snapshot_interval, EngineConfig::default().snapshot_interval);
+
+    let limits = config.limits();
+    assert_eq!(limits.max_connections, 10);
+    assert_eq!(limits.idle_timeout, None);
+    assert_eq!(limits.read_timeout, Some(Duration::from_secs(30)));
+    assert_eq!(limits.conn_rate.write_ops, Some(5));
+    assert_eq!(limits.conn_rate.read_ops, None);
+    assert!(config.admin().allow_anonymous);
+    assert!(!ServerConfig::default().admin().allow_anonymous);
+
+    // typos are errors, not silently ignored settings
+    assert!(toml::from_str::<ServerConfig>("[wal]\ndurabilty = \"off\"").is_err());
+    assert!(toml::from_str::<ServerConfig>("[wal]\ndurability = \"never\"").is_err());
+    assert!(toml::from_str::<ServerConfig>("[log]\nformat = \"xml\"").is_err());
+}
+
+#[test]
+fn test_invalid_config_is_rejected() {
+    let check = |toml: &str| toml::from_str::<ServerConfig>(toml).unwrap().validate().unwrap_err();
+
+    assert!(check(r#"addr = "localhost""#).contains("addr"));
+    assert!(check(r#"ws_addr = "127.0.0.1:99999""#).contains("ws_addr"));
+    assert!(check("[wal]\nsegment_size = 100").contains("segment size"));
+    assert!(check("[wal]\ndurability = \"periodic\"\nfsync_interval_ms = 0").contains("fsync interval"));
+    assert!(check("[snapshot]\ninterval_secs = 0").contains("snapshot interval"));
+    assert!(check("[limits]\nmax_connections = 0").contains("max connections"));
+    assert!(check("[limits]\nconn_rate = { read_ops = 0 }").contains("read_ops"));
+    assert!(check("[tls]\ncert = \"a.pem\"").contains("tls key"));
+    assert!(check(r#"data_dir = "Cargo.toml""#).contains("not a directory"));
+    assert!(check(r#"unix_socket = "./no_such_dir/fluxdb.sock""#).contains("does not exist"));
+    assert!(check("unix_socket = \"fluxdb.sock\"\nunix_socket_mode = 0o1777").contains("mode"));
+    assert!(check("[log]\nlevel = \"fluxdb=loud\"").contains("log level"));
+}
+
+#[tokio::test]
+async fn test_engine_uses_its_own_data_dir() {
+    let test_dir = "./test_config_data_dir";
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    let config = EngineConfig {
+        data_dir: test_dir.into(),
+        durability: Durability::Off,
+        ..EngineConfig::default()
+    };
+    config.validate().unwrap();
+    let runtime = EngineRuntime::start_with(config);
+    let handle = runtime.handle;
+
+    handle.set("cfg_a".to_string(), json!(1)).await.unwrap();
+    handle.snapshot().await.unwrap();
+    handle.set("cfg_b".to_string(), json!(2)).await.unwrap();
+
+    assert!(Path::new(test_dir).join("snapshot.json").is_file());
+    assert!(Path::new(test_dir).join("wal").is_dir());
+
+    // recovery = the snapshot plus whatever the wal has after it
+    let store = Arc::new(RwLock::new(Store::new()));
+    let _db = Database::open(test_dir, store.clone()).await.unwrap();
+    let store = store.read().await;
+    assert_eq!(store.get("cfg_a").unwrap().value, json!(1));
+    assert_eq!(store.get("cfg_b").unwrap().value, json!(2));
+    drop(store);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::Path;
+
+use fluxdb::dump::{self, Format, Record};
+use fluxdb::engine::config::{Durability, EngineConfig, Recovery};
+use fluxdb::engine::db::Database;
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::store::kv::Store;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+fn record(key: &str, value: serde_json::Value, version: u64) -> Record {
+    Record {
+        key: key.to_string(),
+        value,
+        version,
+    }
+}
+
+fn round_trip(format: Format, records: &[Record]) -> Vec<Record> {
+    let mut out = Vec::new();
+    dump::write(&mut out, format, records).unwrap();
+    dump::read(std::io::Cursor::new(out), format)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_dump_a_live_data_dir_and_round_trip_jsonl() {
+    let test_dir = "./test_dump_data_dir";
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+    let config = EngineConfig {
+        data_dir: test_dir.into(),
+        durability: Durability::Sync,
+        ..EngineConfig::default()
+    };
+    let handle = EngineRuntime::start_with(config).handle;
+
+    handle.set("user:1".to_string(), json!({"name": "ana"})).await.unwrap();
+    handle.set("user:2".to_string(), json!({"name": "bo", "tags": ["x"]})).await.unwrap();
+    handle.set("order:1".to_string(), json!(42)).await.unwrap();
+    handle.snapshot().await.unwrap();
+    // after the snapshot, only in the wal
+    handle.set("user:1".to_string(), json!({"name": "ana", "age": 31})).await.unwrap();
+    handle.set("user:3".to_string(), json!({"name": "cy"})).await.unwrap();
+    handle.delete("user:2".to_string()).await.unwrap();
+
+    // read next to the running server
+    let records: Vec<Record> = dump::read_data_dir(Path::new(test_dir), "user:", Recovery::Strict).unwrap().collect();
+    assert_eq!(
+        records,
+        [
+            record("user:1", json!({"name": "ana", "age": 31}), 2),
+            record("user:3", json!({"name": "cy"}), 1),
+        ]
+    );
+    assert_eq!(dump::read_data_dir(Path::new(test_dir), "", Recovery::Strict).unwrap().count(), 3);
+    assert!(dump::read_data_dir(Path::new("./test_dump_missing"), "", Recovery::Strict).is_err());
+
+    let all: Vec<Record> = dump::read_data_dir(Path::new(test_dir), "", Recovery::Strict).unwrap().collect();
+    assert_eq!(round_trip(Format::Jsonl, &all), all);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_dump_judges_wal_damage_like_open() {
+    let test_dir = "./test_dump_damaged";
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+    {
+        let store = Arc::new(RwLock::new(Store::new()));
+        let mut db = Database::open(test_dir, store).await.unwrap();
+        for key in ["d:a", "d:b", "d:c"] {
+            let event = db.put(key.to_string(), json!(1)).await.unwrap();
+            db.fsync_wal().unwrap();
+            db.execute_post_durability(event).await.unwrap();
+        }
+    }
+    let wal_file_path = format!("{test_dir}/wal/0.log");
+    let clean = fs::read(&wal_file_path).unwrap();
+    let read = |recovery| {
+        dump::read_data_dir(Path::new(test_dir), "", recovery).map(Iterator::collect::<Vec<_>>)
+    };
+
+    // an append cut short at the end (a running server mid-write) is left out, like open does
+    let mut torn = clean.clone();
+    torn.extend_from_slice(&clean[16..40]);
+    fs::write(&wal_file_path, &torn).unwrap();
+    assert_eq!(read(Recovery::Strict).unwrap().len(), 3);
+
+    // a checksum mismatch with good records after it is not a torn tail
+    let mut damaged = clean.clone();
+    damaged[21] ^= 1; // in the first record's crc
+    fs::write(&wal_file_path, &damaged).unwrap();
+    let err = read(Recovery::Strict).unwrap_err();
+    assert!(err.contains("wal corruption in segment 0 at offset 16"), "{err}");
+    assert!(err.contains("--recover-best-effort"), "{err}");
+    let salvaged = read(Recovery::BestEffort).unwrap();
+    assert_eq!(salvaged, [record("d:b", json!(1), 1), record("d:c", json!(1), 1)]);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[test]
+fn test_csv_holds_flat_documents() {
+    let records = [
+        record("p:1", json!({"name": "ana", "age": 31, "admin": true}), 3),
+        record("p:2", json!({"name": "bo, jr", "city": null}), 1),
+    ];
+    let mut out = Vec::new();
+    dump::write(&mut out, Format::Csv, &records).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert_eq!(
+        text,
+        "key,version,admin,age,city,name\np:1,3,true,31,,ana\np:2,1,,,null,\"bo, jr\"\n"
+    );
+    // missing fields stay missing, scalars come back typed
+    assert_eq!(round_trip(Format::Csv, &records), records);
+
+    // hand written, without a version column
+    let rows: Vec<Record> = dump::read(std::io::Cursor::new("key,n\na,7\nb,seven\n"), Format::Csv)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(rows, [record("a", json!({"n": 7}), 0), record("b", json!({"n": "seven"}), 0)]);
+
+    // nested values and non objects are refused before anything is written
+    for value in [json!({"a": {"b": 1}}), json!([1]), json!(5)] {
+        let mut out = Vec::new();
+        let err = dump::write(&mut out, Format::Csv, &[record("n", value, 1)]).unwrap_err();
+        assert!(err.contains("not a flat object"), "{err}");
+        assert!(out.is_empty());
+    }
+
+    let bad: Result<Vec<Record>, String> =
+        dump::read(std::io::Cursor::new("{\"key\":\"a\",\"value\":1}\nnot json\n"), Format::Jsonl)
+            .unwrap()
+            .collect();
+    assert!(bad.unwrap_err().starts_with("line 2:"));
+}
@@ -0,0 +1,206 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use fluxdb::engine::config::{EngineConfig, Recovery};
+use fluxdb::engine::db::Database;
+use fluxdb::engine::fsck::{self, Problem};
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::store::kv::Store;
+use fluxdb::store::wal::lsn::Lsn;
+use fluxdb::store::wal::replay::Damage;
+use serde_json::json;
+use tokio::sync::RwLock;
+
+// `writes` puts over a few keys in 4k segments, then a snapshot at the end
+async fn fill(test_dir: &str, writes: usize) -> Arc<RwLock<Store>> {
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+    let store = Arc::new(RwLock::new(Store::new()));
+    let mut db = Database::open_with(Path::new(test_dir), 4096, Recovery::Strict, store.clone()).await.unwrap();
+    for i in 0..writes {
+        let event = db.put(format!("k{}", i % 7), json!({"i": i, "pad": "x".repeat(100)})).await.unwrap();
+        db.execute_post_durability(event).await.unwrap();
+    }
+    db.fsync_wal().unwrap();
+    let snapshot = db.checkpoint_payload().await.unwrap();
+    fs::write(Path::new(test_dir).join("snapshot.json"), serde_json::to_vec(&snapshot).unwrap()).unwrap();
+    store
+}
+
+// a record the way Segment::append frames it: len | crc32c(len + payload) | payload
+fn record(payload: &[u8]) -> Vec<u8> {
+    let len = (payload.len() as u32).to_be_bytes();
+    let crc = crc32c::crc32c_append(crc32c::crc32c(&len), payload);
+    [&len[..], &crc.to_be_bytes(), payload].concat()
+}
+
+fn append(path: &str, bytes: &[u8]) {
+    let mut file = OpenOptions::new().append(true).open(path).unwrap();
+    file.write_all(bytes).unwrap();
+}
+
+#[tokio::test]
+async fn test_fsck_repairs_a_torn_tail_and_stray_files() {
+    let test_dir = "./test_fsck_torn";
+    fill(test_dir, 60).await;
+    let report = fsck::check(Path::new(test_dir)).unwrap();
+    assert!(report.segments.len() > 1);
+    assert_eq!(report.records, 60);
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+
+    // a crash mid-append, and a snapshot that never got renamed into place
+    let last = format!("{test_dir}/wal/{}.log", report.segments.last().unwrap());
+    let size = fs::metadata(&last).unwrap().len();
+    append(&last, &[0, 0, 1, 0, b'{', b'"']);
+    fs::write(format!("{test_dir}/snapshot.json.tmp"), b"{\"data\"").unwrap();
+
+    let report = fsck::check(Path::new(test_dir)).unwrap();
+    let torn = Problem::TornTail { lsn: Lsn::new(*report.segments.last().unwrap(), size), bytes: 6 };
+    assert!(report.problems.contains(&torn), "{:?}", report.problems);
+    assert_eq!(report.problems.len(), 2);
+    assert!(report.problems.iter().all(Problem::repairable));
+
+    let done = fsck::repair(Path::new(test_dir), &report).unwrap();
+    assert_eq!(done.len(), 2);
+    assert_eq!(fs::metadata(&last).unwrap().len(), size);
+    assert!(fsck::check(Path::new(test_dir)).unwrap().problems.is_empty());
+    // the originals are kept
+    let quarantine: Vec<_> = fs::read_dir(Path::new(test_dir).join(fsck::QUARANTINE)).unwrap().collect();
+    let kept = quarantine[0].as_ref().unwrap().path();
+    assert!(kept.join("snapshot.json.tmp").exists());
+    assert!(fs::read_dir(&kept).unwrap().count() == 2);
+
+    // and the data is all there
+    let store = Arc::new(RwLock::new(Store::new()));
+    let _db = Database::open(test_dir, store.clone()).await.unwrap();
+    assert_eq!(store.read().await.get("k3").unwrap().value["i"], 59);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_fsck_wont_repair_a_bad_length_over_committed_records() {
+    let test_dir = "./test_fsck_bad_length";
+    fill(test_dir, 60).await;
+    let segments = fsck::check(Path::new(test_dir)).unwrap().segments;
+
+    // the first length of the last segment runs over every record after it
+    let last = format!("{test_dir}/wal/{}.log", segments.last().unwrap());
+    let mut bytes = fs::read(&last).unwrap();
+    bytes[16] = 0xff;
+    fs::write(&last, &bytes).unwrap();
+
+    let report = fsck::check(Path::new(test_dir)).unwrap();
+    assert!(report.problems.iter().any(|p| matches!(
+        p,
+        Problem::MidLogCorruption { damage: Damage::TooLong { .. }, .. }
+    )), "{:?}", report.problems);
+    assert!(!report.problems.iter().any(Problem::repairable));
+    assert!(fsck::repair(Path::new(test_dir), &report).unwrap().is_empty());
+    assert_eq!(fs::read(&last).unwrap(), bytes);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_fsck_passes_a_dir_written_concurrently() {
+    let test_dir = "./test_fsck_concurrent";
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+    let config = EngineConfig { data_dir: test_dir.into(), ..EngineConfig::default() };
+    let handle = Arc::new(EngineRuntime::start_with(config).handle);
+
+    // writers racing on the same few keys share fsync batches
+    let writers = (0..16).map(|w| {
+        let h = handle.clone();
+        tokio::spawn(async move {
+            for i in 0..20 {
+                h.set(format!("k{}", i % 3), json!({"w": w, "i": i})).await.unwrap();
+                h.patch(format!("k{}", i % 3), json!({"p": w})).await.unwrap();
+            }
+        })
+    });
+    for w in futures::future::join_all(writers).await {
+        w.unwrap();
+    }
+
+    let report = fsck::check(Path::new(test_dir)).unwrap();
+    assert_eq!(report.records, 16 * 20 * 2);
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_fsck_reports_mid_log_damage_without_touching_it() {
+    let test_dir = "./test_fsck_mid_log";
+    fill(test_dir, 80).await;
+    let segments = fsck::check(Path::new(test_dir)).unwrap().segments;
+    assert!(segments.len() >= 3);
+
+    // break the first record of segment 0, good records follow it
+    let first = format!("{test_dir}/wal/0.log");
+    let mut bytes = fs::read(&first).unwrap();
+    bytes[16 + 8] = b'#';
+    fs::write(&first, &bytes).unwrap();
+    // a write that takes a key backwards
+    let last = format!("{test_dir}/wal/{}.log", segments.last().unwrap());
+    append(&last, &record(br#"{"key":"k1","old":null,"new":{"i":0},"version":1}"#));
+    // the snapshot can't be checked against a wal that doesn't replay
+    fs::write(format!("{test_dir}/snapshot.json"), b"garbage").unwrap();
+    // and one segment gone
+    fs::remove_file(format!("{test_dir}/wal/1.log")).unwrap();
+
+    let report = fsck::check(Path::new(test_dir)).unwrap();
+    let problems = &report.problems;
+    assert!(problems.contains(&Problem::MissingSegments { from: 1, to: 1 }), "{problems:?}");
+    assert!(problems.iter().any(|p| matches!(
+        p,
+        Problem::MidLogCorruption { lsn, damage: Damage::Checksum { .. }, following } if *lsn == Lsn::new(0, 16) && *following > 0
+    )));
+    assert!(problems.iter().any(|p| matches!(
+        p,
+        Problem::VersionRegression { key, version: 1, .. } if key == "k1"
+    )));
+    assert!(problems.iter().any(|p| matches!(p, Problem::BadSnapshot { replayable: false, .. })));
+    assert!(!problems.iter().any(Problem::repairable));
+
+    // nothing to do, nothing touched
+    assert!(fsck::repair(Path::new(test_dir), &report).unwrap().is_empty());
+    assert_eq!(fs::read(&first).unwrap(), bytes);
+    assert!(!Path::new(test_dir).join(fsck::QUARANTINE).exists());
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_fsck_quarantines_a_snapshot_the_wal_can_replace() {
+    let test_dir = "./test_fsck_snapshot";
+    let store = fill(test_dir, 20).await;
+    let expected = store.read().await.get("k2").unwrap().clone();
+
+    // a snapshot that disagrees with the wal it claims to summarize
+    let path = format!("{test_dir}/snapshot.json");
+    let mut snapshot: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    snapshot["data"]["k2"]["version"] = json!(99);
+    fs::write(&path, serde_json::to_vec(&snapshot).unwrap()).unwrap();
+
+    let report = fsck::check(Path::new(test_dir)).unwrap();
+    match report.problems.as_slice() {
+        [Problem::BadSnapshot { error, replayable: true }] => assert!(error.contains("k2"), "{error}"),
+        other => panic!("unexpected {other:?}"),
+    }
+    fsck::repair(Path::new(test_dir), &report).unwrap();
+    assert!(!Path::new(&path).exists());
+
+    let store = Arc::new(RwLock::new(Store::new()));
+    let _db = Database::open(test_dir, store.clone()).await.unwrap();
+    let doc = store.read().await.get("k2").unwrap().clone();
+    assert_eq!((doc.value, doc.version), (expected.value, expected.version));
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
@@ -0,0 +1,104 @@
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::net::handshake::{welcome, Negotiated, PROTOCOL_VERSION};
+use fluxdb::net::limits::ServerLimits;
+use fluxdb::net::protocol::{Capability, Framing, Response};
+use fluxdb::net::ws::handle_ws_connection;
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+#[test]
+fn test_welcome_grants_only_offered_capabilities() {
+    let offered = [Capability::Msgpack, Capability::Deflate, Capability::Pipelining];
+    let limits = ServerLimits {
+        max_request_bytes: 4096,
+        ..ServerLimits::default()
+    }
+    .wire();
+
+    // unknown capabilities from a newer client are parsed, never granted
+    let wanted: Vec<Capability> =
+        serde_json::from_str(r#"["msgpack", "deflate", "zstd", "msgpack"]"#).unwrap();
+    let (negotiated, resp) = welcome(PROTOCOL_VERSION, &wanted, &offered, limits.clone()).unwrap();
+    assert_eq!(negotiated.framing, Framing::MsgpackDeflate);
+    assert!(!negotiated.pipelining);
+    match resp {
+        Response::Welcome { capabilities, protocol_version, limits, .. } => {
+            assert_eq!(capabilities, vec![Capability::Msgpack, Capability::Deflate]);
+            assert_eq!(protocol_version, PROTOCOL_VERSION);
+            assert_eq!(limits.max_frame_len, 4096);
+        }
+        other => panic!("unexpected response {other:?}"),
+    }
+
+    // deflate without msgpack is dropped, and a transport that can't do msgpack never grants it
+    let (negotiated, _) = welcome(PROTOCOL_VERSION, &[Capability::Deflate], &offered, limits.clone()).unwrap();
+    assert_eq!(negotiated.framing, Framing::Json);
+    let (negotiated, _) = welcome(
+        PROTOCOL_VERSION,
+        &[Capability::Msgpack, Capability::Pipelining],
+        &[Capability::Pipelining],
+        limits.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        negotiated,
+        Negotiated { framing: Framing::Json, pipelining: true }
+    );
+
+    let err = welcome(PROTOCOL_VERSION + 1, &[], &offered, limits).unwrap_err();
+    assert!(err.contains("unsupported protocol version"));
+}
+
+#[tokio::test]
+async fn test_websocket_hello_and_version_rejection() {
+    let runtime = EngineRuntime::start();
+    let handle = runtime.handle;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let handle = handle.clone();
+            tokio::spawn(async move {
+                let _ = handle_ws_connection(
+                    stream,
+                    handle,
+                    None,
+                    ServerLimits::default(),
+                    Default::default(),
+                )
+                .await;
+            });
+        }
+    });
+
+    let (mut ws, _) = connect_async(format!("ws://{addr}")).await.unwrap();
+    let hello = r#"{"id":1,"kind":"hello","protocol_version":1,"client_name":"test","capabilities":["msgpack","pipelining"]}"#;
+    ws.send(Message::Text(hello.into())).await.unwrap();
+    let reply: serde_json::Value =
+        serde_json::from_str(ws.next().await.unwrap().unwrap().to_text().unwrap()).unwrap();
+    assert_eq!(reply["id"], 1);
+    assert_eq!(reply["kind"], "welcome");
+    assert_eq!(reply["capabilities"], serde_json::json!(["pipelining"]));
+
+    // a second hello is just an error, the connection stays usable
+    ws.send(Message::Text(hello.into())).await.unwrap();
+    let reply: serde_json::Value =
+        serde_json::from_str(ws.next().await.unwrap().unwrap().to_text().unwrap()).unwrap();
+    assert_eq!(reply["kind"], "error");
+    ws.send(Message::Text(r#"{"kind":"get","key":"hs_missing"}"#.into())).await.unwrap();
+    let reply: serde_json::Value =
+        serde_json::from_str(ws.next().await.unwrap().unwrap().to_text().unwrap()).unwrap();
+    assert_eq!(reply["kind"], "value");
+
+    // unsupported version: clear error, then the server closes
+    let (mut ws, _) = connect_async(format!("ws://{addr}")).await.unwrap();
+    ws.send(Message::Text(r#"{"kind":"hello","protocol_version":99}"#.into())).await.unwrap();
+    let reply: serde_json::Value =
+        serde_json::from_str(ws.next().await.unwrap().unwrap().to_text().unwrap()).unwrap();
+    assert_eq!(reply["kind"], "error");
+    assert!(reply["message"].as_str().unwrap().contains("unsupported protocol version 99"));
+    assert!(matches!(ws.next().await, None | Some(Ok(Message::Close(_))) | Some(Err(_))));
+}
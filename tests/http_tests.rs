@@ -0,0 +1,123 @@
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::net::http;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// minimal HTTP/1.1 client: one request per connection, returns (status, raw head, body)
+async fn request(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> (u16, String, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut req = format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n", body.len());
+    for (name, value) in headers {
+        req.push_str(&format!("{name}: {value}\r\n"));
+    }
+    req.push_str("\r\n");
+    req.push_str(body);
+    stream.write_all(req.as_bytes()).await.unwrap();
+
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).await.unwrap();
+    let (head, body) = raw.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, head.to_string(), body.to_string())
+}
+
+#[tokio::test]
+async fn test_http_rest_status_codes_and_watch() {
+    let runtime = EngineRuntime::start();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(http::serve(listener, runtime.handle, None));
+
+    // the engine shares ./fluxdb with other tests, start from a clean key
+    let _ = request(addr, "DELETE", "/kv/http_key", &[], "").await;
+    let (status, _, _) = request(addr, "DELETE", "/kv/http_key", &[], "").await;
+    assert_eq!(status, 404);
+
+    let (status, _, _) = request(addr, "GET", "/kv/http_key", &[], "").await;
+    assert_eq!(status, 404);
+
+    let (status, _, _) = request(addr, "PUT", "/kv/http_key", &[], "{not json").await;
+    assert_eq!(status, 400);
+
+    // create only if missing (version 0)
+    let (status, _, _) = request(
+        addr,
+        "PUT",
+        "/kv/http_key",
+        &[("If-Match", "\"0\"")],
+        r#"{"a":1}"#,
+    )
+    .await;
+    assert_eq!(status, 204);
+
+    let (status, head, body) = request(addr, "GET", "/kv/http_key", &[], "").await;
+    assert_eq!(status, 200);
+    assert!(head.to_ascii_lowercase().contains("etag: \"1\""));
+    let doc: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(doc, json!({"value": {"a": 1}, "version": 1}));
+
+    // stale version -> 409, current version -> 204
+    let (status, _, _) = request(
+        addr,
+        "PATCH",
+        "/kv/http_key",
+        &[("If-Match", "\"7\"")],
+        r#"{"b":2}"#,
+    )
+    .await;
+    assert_eq!(status, 409);
+    let (status, _, _) = request(
+        addr,
+        "PATCH",
+        "/kv/http_key",
+        &[("If-Match", "\"1\"")],
+        r#"{"b":2}"#,
+    )
+    .await;
+    assert_eq!(status, 204);
+
+    // If-Match: * is "the key exists", whatever its version
+    let star = [("If-Match", "*")];
+    let (status, _, _) = request(addr, "PUT", "/kv/http_key", &star, r#"{"c":3}"#).await;
+    assert_eq!(status, 204);
+    let (_, head, _) = request(addr, "GET", "/kv/http_key", &[], "").await;
+    assert!(head.to_ascii_lowercase().contains("etag: \"3\""));
+    let _ = request(addr, "DELETE", "/kv/http_missing", &[], "").await;
+    let (status, _, _) = request(addr, "PUT", "/kv/http_missing", &star, "1").await;
+    assert_eq!(status, 409);
+    let (status, _, _) = request(addr, "GET", "/kv/http_missing", &[], "").await;
+    assert_eq!(status, 404);
+
+    // watch: headers arrive first, then one SSE frame per event
+    let mut watch = TcpStream::connect(addr).await.unwrap();
+    watch
+        .write_all(b"GET /watch/http_key HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![0u8; 4096];
+    let n = watch.read(&mut buf).await.unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("text/event-stream"));
+
+    let (status, _, _) = request(addr, "DELETE", "/kv/http_key", &[], "").await;
+    assert_eq!(status, 204);
+
+    let mut stream_text = String::new();
+    while !stream_text.contains("data:") {
+        let n = watch.read(&mut buf).await.unwrap();
+        assert!(n > 0, "watch stream closed early");
+        stream_text.push_str(&String::from_utf8_lossy(&buf[..n]));
+    }
+    assert!(stream_text.contains("event: event"));
+    assert!(stream_text.contains("\"key\":\"http_key\""));
+
+    let (status, _, _) = request(addr, "POST", "/snapshot", &[], "").await;
+    assert_eq!(status, 204);
+}
@@ -0,0 +1,65 @@
+use std::fs;
+use std::sync::Arc;
+
+use fluxdb::engine::config::{Durability, EngineConfig};
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::net::dispatch::dispatch;
+use fluxdb::net::protocol::{Request, RequestFrame, Response};
+use serde_json::json;
+use tokio::sync::{mpsc, Semaphore};
+
+#[tokio::test]
+async fn test_info_reports_wal_snapshot_and_subscribers() {
+    let test_dir = "./test_info_data_dir";
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+    let config = EngineConfig {
+        data_dir: test_dir.into(),
+        durability: Durability::Sync,
+        ..EngineConfig::default()
+    };
+    let handle = EngineRuntime::start_with(config).handle;
+
+    handle.set("info_a".to_string(), json!(1)).await.unwrap();
+    handle.set("info_b".to_string(), json!(2)).await.unwrap();
+    handle.snapshot().await.unwrap();
+    handle.set("info_c".to_string(), json!(3)).await.unwrap();
+    let _events = handle.subscribe("info_a".to_string()).await.unwrap();
+    let closed = handle.subscribe("info_b".to_string()).await.unwrap();
+    drop(closed);
+    let _pattern = handle.subscribe_pattern("info_*".to_string()).await.unwrap();
+
+    let info = handle.info().await.unwrap();
+    assert_eq!(info.keys, 3);
+    // the snapshot actor's startup tick may still land after info_c
+    let snapshot = info.last_snapshot.expect("a snapshot was taken");
+    if snapshot.lsn == info.lsn {
+        assert_eq!(info.writes_since_snapshot, 0);
+    } else {
+        assert_eq!(info.writes_since_snapshot, 1);
+        assert!(snapshot.lsn.offset > 0 && snapshot.lsn.offset < info.lsn.offset);
+    }
+    assert!(snapshot.unix_ms > 0);
+    assert_eq!(info.active_segment, info.lsn.segment);
+    assert_eq!(info.wal_segments, 1);
+    assert_eq!(info.wal_bytes, info.lsn.offset);
+    assert!(info.fsync.count >= 3); // sync mode, one per batch
+    assert_eq!(info.fsync.failures, 0);
+    assert_eq!(info.subscriptions, 1); // the dropped one doesn't count
+    assert_eq!(info.pattern_subscriptions, 1);
+
+    // over the protocol the engine's fields sit next to `clients`
+    let (out_tx, mut out_rx) = mpsc::channel(4);
+    let frame = RequestFrame::from(Request::Info);
+    let subscriptions = Arc::new(Semaphore::new(1));
+    dispatch(&handle, frame, &out_tx, None, &subscriptions, &Default::default()).await;
+    let resp = out_rx.recv().await.unwrap().resp;
+    let wire = serde_json::to_value(&resp).unwrap();
+    assert_eq!(wire["kind"], "info");
+    assert_eq!(wire["info"]["keys"], 3);
+    assert!(wire["info"]["clients"].is_u64());
+    assert!(matches!(resp, Response::Info { info } if info.engine.keys == 3));
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
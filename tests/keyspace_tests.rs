@@ -0,0 +1,175 @@
+use std::fs;
+use std::sync::Arc;
+
+use fluxdb::engine::config::{Durability, EngineConfig};
+use fluxdb::engine::db::Database;
+use fluxdb::engine::handler::EngineHandle;
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::event::{Event, EventOp};
+use fluxdb::net::acl::{all_classes, all_keys, AdminPolicy, CommandClass, Principal};
+use fluxdb::net::auth::{hash_secret, AuthConfig, Authenticator, UserEntry};
+use fluxdb::net::dispatch::dispatch;
+use fluxdb::net::protocol::{Request, Response};
+use fluxdb::store::kv::Store;
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, RwLock, Semaphore};
+
+fn start(test_dir: &str) -> EngineHandle {
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+    let config = EngineConfig {
+        data_dir: test_dir.into(),
+        durability: Durability::Sync,
+        ..EngineConfig::default()
+    };
+    EngineRuntime::start_with(config).handle
+}
+
+async fn send(handle: &EngineHandle, req: Request, principal: Option<Principal>) -> Response {
+    send_with(handle, req, principal, &AdminPolicy::default()).await
+}
+
+async fn send_with(
+    handle: &EngineHandle,
+    req: Request,
+    principal: Option<Principal>,
+    admin: &AdminPolicy,
+) -> Response {
+    let (out_tx, mut out_rx) = mpsc::channel(4);
+    let subscriptions = Arc::new(Semaphore::new(1));
+    dispatch(handle, req.into(), &out_tx, principal, &subscriptions, admin).await;
+    out_rx.recv().await.unwrap().resp
+}
+
+#[tokio::test]
+async fn test_flush_all_is_one_durable_record_and_notifies() {
+    let test_dir = "./test_keyspace_flush";
+    let handle = start(test_dir);
+
+    handle.set("ks_a".to_string(), json!({"n": 1})).await.unwrap();
+    handle.set("ks_b".to_string(), json!(2)).await.unwrap();
+    handle.set("ks_b".to_string(), json!(3)).await.unwrap();
+    let mut key_events = handle.subscribe("ks_b".to_string()).await.unwrap();
+    let mut pattern_events = handle.subscribe_pattern("ks_*".to_string()).await.unwrap();
+
+    handle.flush_all().await.unwrap();
+    assert_eq!(handle.db_size().await.unwrap(), 0);
+    handle.set("ks_c".to_string(), json!(4)).await.unwrap();
+
+    // a delete per key, shaped like the ones del sends
+    let event = key_events.recv().await.unwrap();
+    assert_eq!((event.key.as_str(), &event.old, &event.new), ("ks_b", &json!(3), &Value::Null));
+    assert_eq!(event.version, 3);
+    let mut flushed = vec![
+        pattern_events.recv().await.unwrap().key,
+        pattern_events.recv().await.unwrap().key,
+    ];
+    flushed.sort();
+    assert_eq!(flushed, ["ks_a", "ks_b"]);
+    assert_eq!(pattern_events.recv().await.unwrap().key, "ks_c");
+
+    // replay: the flush record wipes what came before it, not what came after
+    let store = Arc::new(RwLock::new(Store::new()));
+    let _db = Database::open(test_dir, store.clone()).await.unwrap();
+    assert_eq!(store.read().await.keys(None), ["ks_c"]);
+
+    // wal records written before EventOp existed still read as plain writes
+    let old: Event = serde_json::from_str(r#"{"key":"a","old":null,"new":1,"version":1}"#).unwrap();
+    assert_eq!(old.op, EventOp::Write);
+    assert!(!serde_json::to_string(&old).unwrap().contains("op"));
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_keyspace_requests_respect_acls() {
+    let test_dir = "./test_keyspace_acl";
+    let handle = start(test_dir);
+    let user = |name: &str, commands, keys: Vec<String>| UserEntry {
+        name: name.to_string(),
+        hash: hash_secret("pw").unwrap(),
+        commands,
+        keys,
+        rate: None,
+    };
+    let auth = Arc::new(
+        Authenticator::from_config(AuthConfig {
+            timeout_secs: 10,
+            default_rate: None,
+            users: vec![
+                user("admin", all_classes(), all_keys()),
+                user("tenant", vec![CommandClass::Read], vec!["tenant:42:*".to_string()]),
+            ],
+        })
+        .unwrap(),
+    );
+    let admin = Some(Principal::new(auth.clone(), "admin".to_string()));
+    let tenant = Some(Principal::new(auth, "tenant".to_string()));
+
+    for key in ["tenant:42:a", "tenant:42:b", "tenant:42:c", "tenant:7:a"] {
+        handle.set(key.to_string(), json!(1)).await.unwrap();
+    }
+
+    let keys = |pattern: Option<&str>, limit| Request::Keys {
+        pattern: pattern.map(str::to_string),
+        limit,
+    };
+    match send(&handle, keys(None, Some(2)), tenant.clone()).await {
+        Response::Keys { keys, truncated } => {
+            assert_eq!(keys, ["tenant:42:a", "tenant:42:b"]);
+            assert!(truncated);
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+    match send(&handle, keys(Some("tenant:*:a"), None), admin.clone()).await {
+        Response::Keys { keys, truncated } => {
+            assert_eq!(keys, ["tenant:42:a", "tenant:7:a"]);
+            assert!(!truncated);
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+    assert!(matches!(send(&handle, Request::DbSize, tenant.clone()).await, Response::DbSize { size: 3 }));
+    assert!(matches!(send(&handle, Request::DbSize, admin.clone()).await, Response::DbSize { size: 4 }));
+
+    // flushing is admin only
+    match send(&handle, Request::FlushAll, tenant).await {
+        Response::Error { message } => assert!(message.contains("permission denied")),
+        other => panic!("unexpected {other:?}"),
+    }
+    assert!(matches!(send(&handle, Request::FlushAll, admin.clone()).await, Response::Ok));
+    assert!(matches!(send(&handle, Request::DbSize, admin).await, Response::DbSize { size: 0 }));
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_anonymous_flush_and_backup_need_the_config_flag() {
+    let test_dir = "./test_keyspace_anonymous";
+    let handle = start(test_dir);
+    handle.set("anon_a".to_string(), json!(1)).await.unwrap();
+
+    // no auth file = no principal: reads and writes go through, flush_all and backup don't
+    assert!(matches!(send(&handle, Request::DbSize, None).await, Response::DbSize { size: 1 }));
+    let backup = Request::Backup {
+        dest_dir: format!("{test_dir}_backup"),
+    };
+    for req in [Request::FlushAll, backup] {
+        match send(&handle, req, None).await {
+            Response::Error { message } => {
+                assert!(message.contains("needs an authenticated user"), "{message}")
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+    assert!(matches!(send(&handle, Request::DbSize, None).await, Response::DbSize { size: 1 }));
+
+    let allowed = AdminPolicy {
+        allow_anonymous: true,
+        ..AdminPolicy::default()
+    };
+    assert!(matches!(send_with(&handle, Request::FlushAll, None, &allowed).await, Response::Ok));
+    assert!(matches!(send(&handle, Request::DbSize, None).await, Response::DbSize { size: 0 }));
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
@@ -0,0 +1,323 @@
+use std::time::Duration;
+
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::net::codec::{is_frame_too_large, read_frame_limited, MAX_FRAME_LEN};
+use fluxdb::net::limits::{ConnectionLimiter, ServerLimits, COUNTERS};
+use fluxdb::net::protocol::{Framing, Request, RequestFrame, Response, ResponseFrame};
+use fluxdb::net::resp::{self, value::read_command};
+use fluxdb::net::http;
+use fluxdb::net::ws::handle_ws_connection;
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+#[tokio::test]
+async fn test_oversized_json_line_is_refused() {
+    let mut input = br#"{"kind":"get","key":"a"}"#.to_vec();
+    input.push(b'\n');
+    input.extend(std::iter::repeat_n(b'x', 10_000));
+    input.push(b'\n');
+
+    // small read buffer, so the long line arrives in many pieces
+    let mut reader = BufReader::with_capacity(64, &input[..]);
+    let mut buf = Vec::new();
+
+    let first: Option<Result<RequestFrame, String>> =
+        read_frame_limited(&mut reader, Framing::Json, &mut buf, 1024).await.unwrap();
+    assert!(matches!(first, Some(Ok(RequestFrame { req: Request::Get { .. }, .. }))));
+
+    let err = read_frame_limited::<_, RequestFrame>(&mut reader, Framing::Json, &mut buf, 1024)
+        .await
+        .unwrap_err();
+    assert!(is_frame_too_large(&err));
+    assert!(buf.len() <= 1024 + 64, "buffered {} bytes past the limit", buf.len());
+
+    // msgpack checks the length prefix before allocating
+    let huge = [0xff, 0xff, 0xff, 0xff];
+    let mut reader = BufReader::new(&huge[..]);
+    let err = read_frame_limited::<_, RequestFrame>(&mut reader, Framing::Msgpack, &mut buf, 1024)
+        .await
+        .unwrap_err();
+    assert!(is_frame_too_large(&err));
+}
+
+#[test]
+fn test_connection_limiter_frees_slots() {
+    let limiter = ConnectionLimiter::new(2);
+    let before = COUNTERS.snapshot();
+
+    let a = limiter.try_acquire().unwrap();
+    let _b = limiter.try_acquire().unwrap();
+    assert!(limiter.try_acquire().is_none());
+    drop(a);
+    assert!(limiter.try_acquire().is_some());
+
+    // counters are process wide and other tests run in parallel, so only check they moved
+    let after = COUNTERS.snapshot();
+    assert!(after.connections_total >= before.connections_total + 3);
+    assert!(after.connections_rejected > before.connections_rejected);
+}
+
+async fn ws_server(limits: ServerLimits) -> String {
+    let runtime = EngineRuntime::start();
+    let handle = runtime.handle;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let handle = handle.clone();
+            tokio::spawn(async move {
+                let _ = handle_ws_connection(
+                    stream,
+                    handle,
+                    None,
+                    limits,
+                    Default::default(),
+                )
+                .await;
+            });
+        }
+    });
+    format!("ws://{addr}")
+}
+
+#[tokio::test]
+async fn test_websocket_subscription_cap_and_stats() {
+    let url = ws_server(ServerLimits {
+        max_subscriptions: 1,
+        ..ServerLimits::default()
+    })
+    .await;
+    let (mut ws, _) = connect_async(&url).await.unwrap();
+
+    let mut send = async |req: Request| {
+        let text = serde_json::to_string(&req).unwrap();
+        ws.send(Message::Text(text.into())).await.unwrap();
+        let reply = ws.next().await.unwrap().unwrap();
+        serde_json::from_str::<ResponseFrame>(reply.to_text().unwrap()).unwrap().resp
+    };
+
+    let resp = send(Request::Subscribe { key: "cap_a".to_string() }).await;
+    assert!(matches!(resp, Response::Subscribed { .. }));
+    match send(Request::Subscribe { key: "cap_b".to_string() }).await {
+        Response::Error { message } => assert!(message.contains("subscription limit")),
+        other => panic!("unexpected response {other:?}"),
+    }
+
+    match send(Request::Stats).await {
+        Response::Stats { stats } => assert!(stats.subscriptions_rejected >= 1),
+        other => panic!("unexpected response {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_resp_subscription_cap() {
+    let (resp_addr, _) = resp_and_http(ServerLimits {
+        max_subscriptions: 1,
+        ..ServerLimits::default()
+    })
+    .await;
+    let before = COUNTERS.snapshot().subscriptions_rejected;
+
+    let mut stream = TcpStream::connect(resp_addr).await.unwrap();
+    stream
+        .write_all(b"SUBSCRIBE rcap_a rcap_b\r\nPSUBSCRIBE rcap_*\r\nSUBSCRIBE rcap_a\r\nQUIT\r\n")
+        .await
+        .unwrap();
+    let reply = read_all(&mut stream).await;
+    // channels and patterns share the cap, subscribing again to what is held costs nothing
+    assert_eq!(reply.matches("subscription limit reached for this connection").count(), 2, "{reply}");
+    assert_eq!(reply.matches("$6\r\nrcap_a\r\n:1\r\n").count(), 2, "{reply}");
+    assert!(COUNTERS.snapshot().subscriptions_rejected >= before + 2);
+}
+
+#[tokio::test]
+async fn test_websocket_oversized_message_and_idle_timeout() {
+    let url = ws_server(ServerLimits {
+        max_request_bytes: 256,
+        idle_timeout: Some(Duration::from_millis(200)),
+        ..ServerLimits::default()
+    })
+    .await;
+
+    let (mut ws, _) = connect_async(&url).await.unwrap();
+    let big = format!(r#"{{"kind":"get","key":"{}"}}"#, "k".repeat(1000));
+    ws.send(Message::Text(big.into())).await.unwrap();
+    let reply = ws.next().await.unwrap().unwrap();
+    let resp: Response = serde_json::from_str(reply.to_text().unwrap()).unwrap();
+    assert!(matches!(resp, Response::Error { message } if message.contains("too large")));
+
+    // a quiet connection is told why and closed
+    let (mut ws, _) = connect_async(&url).await.unwrap();
+    let reply = tokio::time::timeout(Duration::from_secs(5), ws.next())
+        .await
+        .expect("idle connection was not closed")
+        .unwrap()
+        .unwrap();
+    let resp: Response = serde_json::from_str(reply.to_text().unwrap()).unwrap();
+    assert!(matches!(resp, Response::Error { message } if message == "idle timeout"));
+}
+
+#[tokio::test]
+async fn test_resp_line_is_bounded_and_count_reserves_nothing() {
+    // a line past the limit, refused after reading one byte more than it
+    let mut input = b"*1\r\n$".to_vec();
+    input.extend(std::iter::repeat_n(b'9', MAX_FRAME_LEN + 10));
+    let mut reader = BufReader::new(&input[..]);
+    let err = read_command(&mut reader, MAX_FRAME_LEN).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    // a huge count with nothing behind it is just a short read
+    let mut reader = BufReader::new(&b"*1048576\r\n"[..]);
+    let err = read_command(&mut reader, MAX_FRAME_LEN).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn test_resp_command_size_counts_every_argument() {
+    // each argument is small, together they are past the limit
+    let mut input = b"*3\r\n$3\r\nSET\r\n".to_vec();
+    for _ in 0..2 {
+        input.extend(b"$40\r\n");
+        input.extend([b'a'; 40]);
+        input.extend(b"\r\n");
+    }
+    let mut reader = BufReader::new(&input[..]);
+    let args = read_command(&mut reader, input.len()).await.unwrap().unwrap();
+    assert_eq!(args.len(), 3);
+
+    let mut reader = BufReader::new(&input[..]);
+    let err = read_command(&mut reader, 64).await.unwrap_err();
+    assert!(is_frame_too_large(&err));
+
+    // and over the wire the client is told, then the connection closes
+    let limits = ServerLimits {
+        max_request_bytes: 64,
+        ..ServerLimits::default()
+    };
+    let (resp_addr, _) = resp_and_http(limits).await;
+    let before = COUNTERS.snapshot().requests_too_large;
+    let mut stream = TcpStream::connect(resp_addr).await.unwrap();
+    stream.write_all(&input).await.unwrap();
+    let reply = read_all(&mut stream).await;
+    assert!(reply.starts_with("-ERR Protocol error: command larger than 64 bytes"), "{reply}");
+    assert!(COUNTERS.snapshot().requests_too_large > before);
+}
+
+// one listener of each, sharing a single connection slot
+async fn resp_and_http(limits: ServerLimits) -> (std::net::SocketAddr, std::net::SocketAddr) {
+    let runtime = EngineRuntime::start();
+    let limiter = ConnectionLimiter::new(limits.max_connections);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let resp_addr = listener.local_addr().unwrap();
+    tokio::spawn(resp::serve_with(listener, runtime.handle.clone(), None, limits, limiter.clone()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let http_addr = listener.local_addr().unwrap();
+    tokio::spawn(http::serve_with(listener, runtime.handle, None, limits, limiter));
+    (resp_addr, http_addr)
+}
+
+async fn read_all(stream: &mut TcpStream) -> String {
+    let mut out = String::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf));
+        match read.await.expect("connection was not closed").unwrap() {
+            0 => return out,
+            n => out.push_str(&String::from_utf8_lossy(&buf[..n])),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_resp_and_http_share_the_connection_cap() {
+    let (resp_addr, http_addr) = resp_and_http(ServerLimits {
+        max_connections: 1,
+        ..ServerLimits::default()
+    })
+    .await;
+
+    let mut first = TcpStream::connect(resp_addr).await.unwrap();
+    first.write_all(b"PING\r\n").await.unwrap();
+    let mut buf = [0u8; 64];
+    let n = first.read(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"+PONG\r\n");
+
+    let mut second = TcpStream::connect(resp_addr).await.unwrap();
+    assert_eq!(read_all(&mut second).await, "-ERR too many connections, try again later\r\n");
+    let mut third = TcpStream::connect(http_addr).await.unwrap();
+    third.write_all(b"GET /kv/x HTTP/1.1\r\nHost: x\r\n\r\n").await.unwrap();
+    let reply = read_all(&mut third).await;
+    assert!(reply.starts_with("HTTP/1.1 503"), "{reply}");
+
+    // the slot comes back when the connection ends
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut fourth = TcpStream::connect(http_addr).await.unwrap();
+    fourth.write_all(b"GET /kv/limits_missing HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n").await.unwrap();
+    assert!(read_all(&mut fourth).await.starts_with("HTTP/1.1 404"));
+}
+
+#[tokio::test]
+async fn test_http_body_follows_max_request_bytes() {
+    // a put whose json body is `len` bytes, answered with the status line
+    async fn put(addr: std::net::SocketAddr, len: usize) -> String {
+        let body = format!("\"{}\"", "b".repeat(len - 2));
+        let head = format!(
+            "PUT /kv/limits_body HTTP/1.1\r\nHost: x\r\nContent-Type: application/json\r\nContent-Length: {len}\r\nConnection: close\r\n\r\n"
+        );
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(head.as_bytes()).await.unwrap();
+        let _ = stream.write_all(body.as_bytes()).await; // may be cut off by the 413
+        let reply = read_all(&mut stream).await;
+        reply.lines().next().unwrap_or_default().to_string()
+    }
+
+    // lowered
+    let (_, http_addr) = resp_and_http(ServerLimits {
+        max_request_bytes: 64,
+        ..ServerLimits::default()
+    })
+    .await;
+    let before = COUNTERS.snapshot().requests_too_large;
+    assert!(put(http_addr, 32).await.starts_with("HTTP/1.1 2"));
+    assert!(put(http_addr, 100).await.starts_with("HTTP/1.1 413"));
+    assert!(COUNTERS.snapshot().requests_too_large > before);
+
+    // and raised past axum's own 2 MiB default
+    let (_, http_addr) = resp_and_http(ServerLimits {
+        max_request_bytes: 4 << 20,
+        ..ServerLimits::default()
+    })
+    .await;
+    assert!(put(http_addr, 3 << 20).await.starts_with("HTTP/1.1 2"));
+}
+
+#[tokio::test]
+async fn test_resp_and_http_timeouts() {
+    let (resp_addr, http_addr) = resp_and_http(ServerLimits {
+        idle_timeout: Some(Duration::from_millis(300)),
+        read_timeout: Some(Duration::from_millis(300)),
+        ..ServerLimits::default()
+    })
+    .await;
+
+    // resp: a quiet connection, and a command that never finishes
+    let mut quiet = TcpStream::connect(resp_addr).await.unwrap();
+    assert_eq!(read_all(&mut quiet).await, "-ERR idle timeout\r\n");
+    let mut slow = TcpStream::connect(resp_addr).await.unwrap();
+    slow.write_all(b"*2\r\n$3\r\nGET\r\n").await.unwrap();
+    assert_eq!(read_all(&mut slow).await, "-ERR read timeout\r\n");
+
+    // http: a head that never finishes is dropped, a body that never finishes gets a 408
+    let mut slow = TcpStream::connect(http_addr).await.unwrap();
+    slow.write_all(b"GET /kv/x HTTP/1.1\r\n").await.unwrap();
+    read_all(&mut slow).await;
+    let mut slow = TcpStream::connect(http_addr).await.unwrap();
+    slow.write_all(b"PUT /kv/x HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\n{").await.unwrap();
+    let reply = read_all(&mut slow).await;
+    assert!(reply.starts_with("HTTP/1.1 408"), "{reply}");
+}
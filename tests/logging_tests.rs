@@ -0,0 +1,69 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::net::dispatch::dispatch;
+use fluxdb::net::protocol::{Request, RequestFrame, Response};
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, Semaphore};
+
+// collects everything the subscriber writes
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_trace_id_follows_the_write_through_the_engine() {
+    // same json layout as logging::init, but into a buffer. Global, the actors log from their
+    // own tasks
+    let captured = Captured::default();
+    let writer = captured.clone();
+    tracing_subscriber::fmt()
+        .with_env_filter(fluxdb::logging::parse_level("debug").unwrap())
+        .with_writer(move || writer.clone())
+        .json()
+        .with_current_span(false)
+        .with_span_list(true)
+        .init();
+
+    let handle = EngineRuntime::start().handle;
+    let (out_tx, mut out_rx) = mpsc::channel(8);
+    let subscriptions = Arc::new(Semaphore::new(4));
+    let frame = RequestFrame {
+        id: Some(1),
+        trace_id: Some("req-7f3a".to_string()),
+        req: Request::Set {
+            key: "log_a".to_string(),
+            value: json!({"n": 1}),
+        },
+    };
+    dispatch(&handle, frame, &out_tx, None, &subscriptions, &Default::default()).await;
+    assert!(matches!(out_rx.recv().await.unwrap().resp, Response::Ok));
+
+    let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let traced = |message: &str| {
+        lines.iter().any(|line| {
+            line["fields"]["message"] == message
+                && line["spans"][0]["name"] == "request"
+                && line["spans"][0]["trace_id"] == "req-7f3a"
+        })
+    };
+    // wal append happens in the writer, applied after the fsync barrier, both behind a channel
+    assert!(traced("wal append"), "{output}");
+    assert!(traced("applied"), "{output}");
+    assert!(traced("done"), "{output}");
+}
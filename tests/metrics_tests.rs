@@ -0,0 +1,64 @@
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::metrics::{self, METRICS};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// the value of an unlabeled sample, or of the first sample whose name + labels start with `series`
+fn sample(text: &str, series: &str) -> f64 {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| {
+            let (name, value) = line.rsplit_once(' ')?;
+            (name == series || name.starts_with(series)).then(|| value.parse().unwrap())
+        })
+        .unwrap_or_else(|| panic!("no sample {series}"))
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint_reports_engine_activity() {
+    // metrics are process wide, so other tests in this binary only ever add to them
+    let handle = EngineRuntime::start().handle;
+    let before = METRICS.render();
+
+    let _events = handle.subscribe("met_a".to_string()).await.unwrap();
+    handle
+        .set("met_a".to_string(), json!({"n": "x".repeat(1000)}))
+        .await
+        .unwrap();
+    handle.snapshot().await.unwrap();
+    drop(METRICS.command_timer("get"));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(metrics::serve(listener));
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).await.unwrap();
+    let (head, after) = raw.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"));
+    assert!(head.contains("text/plain; version=0.0.4"));
+
+    let grew = |series: &str| sample(after, series) > sample(&before, series);
+    assert!(grew("fluxdb_write_batch_size_count"));
+    assert!(grew("fluxdb_wal_fsync_seconds_count"));
+    assert!(grew("fluxdb_wal_appended_bytes_total"));
+    assert!(grew("fluxdb_snapshot_duration_seconds_count"));
+    assert!(grew(
+        "fluxdb_command_duration_seconds_count{command=\"get\"}"
+    ));
+    assert!(sample(after, "fluxdb_snapshot_size_bytes") > 1000.0);
+    assert!(sample(after, "fluxdb_keys") >= 1.0);
+    assert!(sample(after, "fluxdb_memory_bytes") > 1000.0);
+    assert!(sample(after, "fluxdb_subscribers") >= 1.0);
+
+    // histogram buckets are cumulative and end in +Inf = count
+    let inf = sample(after, "fluxdb_write_batch_size_bucket{le=\"+Inf\"}");
+    assert_eq!(inf, sample(after, "fluxdb_write_batch_size_count"));
+    assert!(sample(after, "fluxdb_write_batch_size_bucket{le=\"1\"}") <= inf);
+}
@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::net::acl::{CommandClass, Principal};
+use fluxdb::net::auth::{hash_secret, AuthConfig, Authenticator};
+use fluxdb::net::limits::ServerLimits;
+use fluxdb::net::protocol::{Request, Response};
+use fluxdb::net::ratelimit::{Budget, ConnectionRate};
+use fluxdb::net::ws::handle_ws_connection;
+use fluxdb::net::{http, resp};
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+#[test]
+fn test_connection_budget_separates_reads_and_writes() {
+    let rate = ConnectionRate::new(Budget {
+        write_ops: Some(2),
+        write_bytes: Some(1000),
+        ..Budget::default()
+    });
+
+    assert!(rate.admit(None, CommandClass::Write, 10).is_ok());
+    assert!(rate.admit(None, CommandClass::Write, 10).is_ok());
+    let retry_after_ms = rate.admit(None, CommandClass::Write, 10).unwrap_err();
+    assert!(retry_after_ms > 0 && retry_after_ms <= 1000);
+
+    // reads have their own (here unlimited) budget
+    for _ in 0..100 {
+        assert!(rate.admit(None, CommandClass::Read, 10).is_ok());
+    }
+
+    // a request bigger than the whole byte budget still goes through on a full bucket
+    let big = ConnectionRate::new(Budget {
+        write_bytes: Some(100),
+        ..Budget::default()
+    });
+    assert!(big.admit(None, CommandClass::Write, 5000).is_ok());
+    assert!(big.admit(None, CommandClass::Write, 1).is_err());
+}
+
+#[test]
+fn test_user_budget_is_shared_by_connections() {
+    let config: AuthConfig = toml::from_str(&format!(
+        r#"
+        [default_rate]
+        write_ops = 1
+
+        [[users]]
+        name = "limited"
+        hash = "{hash}"
+
+        [[users]]
+        name = "vip"
+        hash = "{hash}"
+        rate = {{ write_ops = 1000 }}
+        "#,
+        hash = hash_secret("pw").unwrap()
+    ))
+    .unwrap();
+    let auth = Arc::new(Authenticator::from_config(config).unwrap());
+    let limited = Principal::new(auth.clone(), "limited".to_string());
+    let vip = Principal::new(auth, "vip".to_string());
+
+    let a = ConnectionRate::new(Budget::default());
+    let b = ConnectionRate::new(Budget::default());
+    assert!(a.admit(Some(limited.clone()), CommandClass::Write, 1).is_ok());
+    assert!(b.admit(Some(limited), CommandClass::Write, 1).is_err());
+
+    for _ in 0..10 {
+        assert!(b.admit(Some(vip.clone()), CommandClass::Write, 1).is_ok());
+    }
+}
+
+#[tokio::test]
+async fn test_websocket_rate_limited_response() {
+    let runtime = EngineRuntime::start();
+    let handle = runtime.handle;
+
+    let limits = ServerLimits {
+        conn_rate: Budget {
+            write_ops: Some(1),
+            ..Budget::default()
+        },
+        ..ServerLimits::default()
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let _ = handle_ws_connection(stream, handle, None, limits, Default::default()).await;
+    });
+
+    let (mut ws, _) = connect_async(format!("ws://{addr}")).await.unwrap();
+    let mut send = async |req: Request| {
+        let text = serde_json::to_string(&req).unwrap();
+        ws.send(Message::Text(text.into())).await.unwrap();
+        let reply = ws.next().await.unwrap().unwrap();
+        serde_json::from_str::<Response>(reply.to_text().unwrap()).unwrap()
+    };
+
+    let set = |n: u64| Request::Set {
+        key: "rl_key".to_string(),
+        value: json!(n),
+    };
+    assert!(matches!(send(set(1)).await, Response::Ok));
+    match send(set(2)).await {
+        Response::RateLimited { retry_after_ms } => assert!(retry_after_ms > 0),
+        other => panic!("unexpected response {other:?}"),
+    }
+
+    // the rejected write never ran, and reads are not affected
+    let resp = send(Request::Get { key: "rl_key".to_string() }).await;
+    assert!(matches!(resp, Response::Value { doc: Some(doc) } if doc.value == json!(1)));
+}
+
+// one user with a single write per second, logged in over the given transport
+fn one_write_per_second(user: &str) -> Arc<Authenticator> {
+    let config: AuthConfig = toml::from_str(&format!(
+        r#"
+        [[users]]
+        name = "{user}"
+        hash = "{hash}"
+        commands = ["read", "write", "subscribe", "admin"]
+        keys = ["*"]
+        rate = {{ write_ops = 1 }}
+        "#,
+        hash = hash_secret("pw").unwrap()
+    ))
+    .unwrap();
+    Arc::new(Authenticator::from_config(config).unwrap())
+}
+
+async fn roundtrip(stream: &mut TcpStream, out: &str) -> String {
+    stream.write_all(out.as_bytes()).await.unwrap();
+    let mut buf = [0u8; 4096];
+    let n = stream.read(&mut buf).await.unwrap();
+    String::from_utf8_lossy(&buf[..n]).to_string()
+}
+
+#[tokio::test]
+async fn test_resp_user_budget() {
+    let runtime = EngineRuntime::start();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(resp::serve(listener, runtime.handle, Some(one_write_per_second("rl_resp"))));
+
+    // two connections, one user budget
+    let mut a = TcpStream::connect(addr).await.unwrap();
+    let mut b = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(roundtrip(&mut a, "AUTH rl_resp pw\r\n").await, "+OK\r\n");
+    assert_eq!(roundtrip(&mut b, "AUTH rl_resp pw\r\n").await, "+OK\r\n");
+    assert_eq!(roundtrip(&mut a, "SET rl_resp_key 1\r\n").await, "+OK\r\n");
+    let reply = roundtrip(&mut b, "SET rl_resp_key 2\r\n").await;
+    assert!(reply.starts_with("-ERR rate limited, retry after"), "{reply}");
+
+    // the rejected write never ran, and reads are not affected
+    assert_eq!(roundtrip(&mut b, "GET rl_resp_key\r\n").await, "$1\r\n1\r\n");
+}
+
+#[tokio::test]
+async fn test_http_user_budget() {
+    let runtime = EngineRuntime::start();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(http::serve(listener, runtime.handle, Some(one_write_per_second("rl_http"))));
+
+    // base64("rl_http:pw")
+    let request = |method: &str, body: &str| {
+        format!(
+            "{method} /kv/rl_http_key HTTP/1.1\r\nHost: x\r\nAuthorization: Basic cmxfaHR0cDpwdw==\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+    };
+    // separate connections, so only the user's budget is shared
+    let mut a = TcpStream::connect(addr).await.unwrap();
+    let mut b = TcpStream::connect(addr).await.unwrap();
+    assert!(roundtrip(&mut a, &request("PUT", "1")).await.starts_with("HTTP/1.1 204"));
+    let reply = roundtrip(&mut b, &request("PUT", "2")).await;
+    assert!(reply.starts_with("HTTP/1.1 429"), "{reply}");
+    assert!(reply.to_ascii_lowercase().contains("retry-after: 1"), "{reply}");
+
+    let reply = roundtrip(&mut b, &request("GET", "")).await;
+    assert!(reply.starts_with("HTTP/1.1 200") && reply.contains(r#""value":1"#), "{reply}");
+}
@@ -0,0 +1,173 @@
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::net::resp;
+use fluxdb::store::glob::{escape, glob_match};
+use serde_json::json;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+async fn send(stream: &mut TcpStream, args: &[&str]) {
+    let mut out = format!("*{}\r\n", args.len());
+    for a in args {
+        out.push_str(&format!("${}\r\n{a}\r\n", a.len()));
+    }
+    stream.write_all(out.as_bytes()).await.unwrap();
+}
+
+// reads until `expected` has arrived in full (replies are small, no framing needed here)
+async fn expect(stream: &mut TcpStream, expected: &str) {
+    let mut got = Vec::new();
+    let mut buf = [0u8; 1024];
+    while got.len() < expected.len() {
+        let n = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buf))
+            .await
+            .expect("timed out waiting for reply")
+            .unwrap();
+        assert!(n > 0, "connection closed");
+        got.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(String::from_utf8_lossy(&got), expected);
+}
+
+async fn call(stream: &mut TcpStream, args: &[&str], expected: &str) {
+    send(stream, args).await;
+    expect(stream, expected).await;
+}
+
+#[tokio::test]
+async fn test_resp_commands_and_pubsub() {
+    let runtime = EngineRuntime::start();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(resp::serve(listener, runtime.handle, None));
+
+    let mut c = TcpStream::connect(addr).await.unwrap();
+    // ./fluxdb is shared with other tests, drop leftovers (reply is :0, :1 or :2)
+    let mut buf = [0u8; 256];
+    send(&mut c, &["DEL", "resp_a", "resp_b", "resp_doc"]).await;
+    let _ = c.read(&mut buf).await.unwrap();
+
+    call(&mut c, &["PING"], "+PONG\r\n").await;
+    call(&mut c, &["SET", "resp_a", "hello"], "+OK\r\n").await;
+    call(&mut c, &["GET", "resp_a"], "$5\r\nhello\r\n").await;
+    call(&mut c, &["SET", "resp_a", "other", "NX"], "$-1\r\n").await;
+    call(&mut c, &["EXISTS", "resp_a", "resp_missing"], ":1\r\n").await;
+    // a ttl too far out to be a point in time is refused, not a panic
+    call(
+        &mut c,
+        &["EXPIRE", "resp_a", "18446744073709551615"],
+        "-ERR invalid expire time in 'expire' command\r\n",
+    )
+    .await;
+    call(
+        &mut c,
+        &["SET", "resp_b", "x", "EX", "18446744073709551615"],
+        "-ERR invalid expire time in 'set' command\r\n",
+    )
+    .await;
+    call(&mut c, &["GET", "resp_b"], "$-1\r\n").await;
+
+    call(
+        &mut c,
+        &["JSON.SET", "resp_doc", "$", r#"{"user":{"name":"al"}}"#],
+        "+OK\r\n",
+    )
+    .await;
+    call(
+        &mut c,
+        &["JSON.MERGE", "resp_doc", "$.user", r#"{"age":3}"#],
+        "+OK\r\n",
+    )
+    .await;
+    call(
+        &mut c,
+        &["JSON.GET", "resp_doc", ".user.age"],
+        "$1\r\n3\r\n",
+    )
+    .await;
+
+    // pattern subscriber on a second connection sees writes made through the first
+    let mut sub = TcpStream::connect(addr).await.unwrap();
+    call(
+        &mut sub,
+        &["PSUBSCRIBE", "resp_*"],
+        "*3\r\n$10\r\npsubscribe\r\n$6\r\nresp_*\r\n:1\r\n",
+    )
+    .await;
+    call(&mut c, &["DEL", "resp_a"], ":1\r\n").await;
+
+    let mut got = Vec::new();
+    while !String::from_utf8_lossy(&got).contains("\"version\"") {
+        let n = sub.read(&mut buf).await.unwrap();
+        assert!(n > 0);
+        got.extend_from_slice(&buf[..n]);
+    }
+    let text = String::from_utf8_lossy(&got);
+    assert!(text.starts_with("*4\r\n$8\r\npmessage\r\n$6\r\nresp_*\r\n$6\r\nresp_a\r\n"));
+
+    call(&mut c, &["GET", "resp_a"], "$-1\r\n").await;
+}
+
+// reads pushes until one contains `needle`
+async fn read_until(stream: &mut TcpStream, needle: &str) -> String {
+    let mut got = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !String::from_utf8_lossy(&got).contains(needle) {
+        let n = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buf))
+            .await
+            .unwrap_or_else(|_| panic!("no {needle} in {}", String::from_utf8_lossy(&got)))
+            .unwrap();
+        assert!(n > 0, "connection closed");
+        got.extend_from_slice(&buf[..n]);
+    }
+    String::from_utf8_lossy(&got).into_owned()
+}
+
+#[tokio::test]
+async fn test_resp_pattern_subscription_finds_new_keys() {
+    let runtime = EngineRuntime::start();
+    let handle = runtime.handle.clone();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(resp::serve(listener, runtime.handle, None));
+
+    let mut c = TcpStream::connect(addr).await.unwrap();
+    let mut buf = [0u8; 256];
+    send(&mut c, &["DEL", "rpat_local", "rpat_other"]).await;
+    let _ = c.read(&mut buf).await.unwrap();
+
+    let mut sub = TcpStream::connect(addr).await.unwrap();
+    call(
+        &mut sub,
+        &["PSUBSCRIBE", "rpat_*"],
+        "*3\r\n$10\r\npsubscribe\r\n$6\r\nrpat_*\r\n:1\r\n",
+    )
+    .await;
+
+    // created through the listener
+    call(&mut c, &["SET", "rpat_local", "one"], "+OK\r\n").await;
+    let text = read_until(&mut sub, "\"new\":\"one\"").await;
+    assert!(text.contains("$10\r\nrpat_local\r\n"), "{text}");
+
+    // created through another protocol: its first write arrives too
+    handle.set("rpat_other".to_string(), json!(1)).await.unwrap();
+    let text = read_until(&mut sub, "\"new\":1").await;
+    assert!(text.contains("$10\r\nrpat_other\r\n"), "{text}");
+}
+
+#[test]
+fn test_glob_patterns() {
+    assert!(glob_match("user:*", "user:42"));
+    assert!(glob_match("h?llo", "hello"));
+    assert!(glob_match("h[ae]llo", "hallo"));
+    assert!(!glob_match("h[^e]llo", "hello"));
+    assert!(glob_match("h[a-c]*", "hbzz"));
+    assert!(glob_match("a\\*b", "a*b"));
+    assert!(!glob_match("a\\*b", "axb"));
+    assert!(glob_match("*", ""));
+    assert!(!glob_match("tenant:42:*", "tenant:420:x"));
+    // escape + "*" is a prefix match, whatever the prefix holds
+    let prefix = format!("{}*", escape("a[1]*?\\"));
+    assert!(glob_match(&prefix, "a[1]*?\\rest"));
+    assert!(!glob_match(&prefix, "a1xx\\rest"));
+}
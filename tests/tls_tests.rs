@@ -0,0 +1,114 @@
+use fluxdb::net::tls::{self, certificate_user, TlsAcceptor, TlsConnector};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair,
+};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+fn write_pem(dir: &Path, name: &str, pem: String) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, pem).unwrap();
+    path
+}
+
+fn ca() -> (Certificate, KeyPair) {
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.distinguished_name.push(DnType::CommonName, "fluxdb test ca");
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let key = KeyPair::generate().unwrap();
+    (params.self_signed(&key).unwrap(), key)
+}
+
+// leaf certificate signed by `issuer`, for a server (SAN localhost) or a client (CN = user)
+fn leaf(cn: &str, client: bool, issuer: &(Certificate, KeyPair)) -> (Certificate, KeyPair) {
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+    params.distinguished_name.push(DnType::CommonName, cn);
+    params.extended_key_usages = vec![if client {
+        ExtendedKeyUsagePurpose::ClientAuth
+    } else {
+        ExtendedKeyUsagePurpose::ServerAuth
+    }];
+    let key = KeyPair::generate().unwrap();
+    (params.signed_by(&key, &issuer.0, &issuer.1).unwrap(), key)
+}
+
+#[tokio::test]
+async fn test_mutual_tls_maps_client_certificate_to_user() {
+    let dir = std::env::temp_dir().join(format!("fluxdb-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let ca = ca();
+    let server = leaf("localhost", false, &ca);
+    let alice = leaf("alice", true, &ca);
+    let rogue_ca = self::ca();
+    let mallory = leaf("alice", true, &rogue_ca); // right name, wrong issuer
+
+    let ca_pem = write_pem(&dir, "ca.pem", ca.0.pem());
+    let server_cert = write_pem(&dir, "server.pem", server.0.pem());
+    let server_key = write_pem(&dir, "server.key", server.1.serialize_pem());
+    let alice_cert = write_pem(&dir, "alice.pem", alice.0.pem());
+    let alice_key = write_pem(&dir, "alice.key", alice.1.serialize_pem());
+    let mallory_cert = write_pem(&dir, "mallory.pem", mallory.0.pem());
+    let mallory_key = write_pem(&dir, "mallory.key", mallory.1.serialize_pem());
+
+    let acceptor = TlsAcceptor::from(
+        tls::server_config(&server_cert, &server_key, Some(&ca_pem)).unwrap(),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // server: accept two connections, echo one line, report who the certificate said it was
+    let server = tokio::spawn(async move {
+        let mut results = Vec::new();
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().await.unwrap();
+            match acceptor.accept(stream).await {
+                Ok(mut tls_stream) => {
+                    let user = certificate_user(tls_stream.get_ref().1);
+                    let mut buf = [0u8; 5];
+                    tls_stream.read_exact(&mut buf).await.unwrap();
+                    tls_stream.write_all(&buf).await.unwrap();
+                    tls_stream.shutdown().await.unwrap();
+                    results.push(Ok(user));
+                }
+                Err(e) => results.push(Err(e.to_string())),
+            }
+        }
+        results
+    });
+
+    let name = tls::server_name(&format!("localhost:{}", addr.port())).unwrap();
+
+    let connector = TlsConnector::from(
+        tls::client_config(Some(&ca_pem), Some((&alice_cert, &alice_key))).unwrap(),
+    );
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut conn = connector.connect(name.clone(), stream).await.unwrap();
+    conn.write_all(b"hello").await.unwrap();
+    let mut echoed = Vec::new();
+    conn.read_to_end(&mut echoed).await.unwrap();
+    assert_eq!(echoed, b"hello");
+
+    // a certificate from another ca is refused during the handshake
+    let connector = TlsConnector::from(
+        tls::client_config(Some(&ca_pem), Some((&mallory_cert, &mallory_key))).unwrap(),
+    );
+    let stream = TcpStream::connect(addr).await.unwrap();
+    if let Ok(mut conn) = connector.connect(name, stream).await {
+        // tls 1.3 reports the client auth failure on the first read
+        let _ = conn.write_all(b"hello").await;
+        let mut buf = Vec::new();
+        assert!(conn.read_to_end(&mut buf).await.is_err() || buf.is_empty());
+    }
+
+    let results = server.await.unwrap();
+    assert_eq!(results[0], Ok(Some("alice".to_string())));
+    assert!(results[1].is_err());
+
+    // loading errors name the file
+    let err = tls::server_config(&dir.join("missing.pem"), &server_key, None).unwrap_err();
+    assert!(err.contains("missing.pem"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
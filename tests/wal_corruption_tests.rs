@@ -3,8 +3,20 @@ use tokio::sync::RwLock;
 use serde_json::json;
 use std::fs::{self, OpenOptions};
 use std::io::Write;
+use std::path::Path;
+use fluxdb::engine::config::{EngineConfig, Recovery};
 use fluxdb::engine::db::Database;
+use fluxdb::engine::runtime::EngineRuntime;
 use fluxdb::store::kv::Store;
+use fluxdb::store::wal::lsn::Lsn;
+use fluxdb::store::wal::replay::{Damage, WalIterator};
+
+// a record the way Segment::append frames it: len | crc32c(len + payload) | payload
+fn record(payload: &[u8]) -> Vec<u8> {
+    let len = (payload.len() as u32).to_be_bytes();
+    let crc = crc32c::crc32c_append(crc32c::crc32c(&len), payload);
+    [&len[..], &crc.to_be_bytes(), payload].concat()
+}
 
 #[tokio::test]
 async fn test_wal_torn_tail_recovery() {
@@ -26,6 +38,7 @@ async fn test_wal_torn_tail_recovery() {
 
     // 2. Corrupt the WAL file (append partial length prefix)
     let wal_file_path = format!("{}/wal/0.log", test_dir);
+    let good_len = fs::metadata(&wal_file_path).unwrap().len();
     {
         let mut file = OpenOptions::new()
             .append(true)
@@ -42,18 +55,18 @@ async fn test_wal_torn_tail_recovery() {
         let guard = store.read().await;
         assert_eq!(guard.get("key1").unwrap().value, json!({"val": 1}));
         assert_eq!(guard.get("key2").unwrap().value, json!({"val": 2}));
+        // the torn bytes are cut off, the next append must not land behind them
+        assert_eq!(fs::metadata(&wal_file_path).unwrap().len(), good_len);
     }
 
-    // 4. Corrupt further (append valid length but invalid JSON)
+    // 4. Corrupt further (a whole prefix, then the start of the payload)
     {
         let mut file = OpenOptions::new()
             .append(true)
             .open(&wal_file_path)
             .unwrap();
-        let bad_json = b"{\"corrupt\": true"; // missing closing brace
-        let len = bad_json.len() as u32;
-        file.write_all(&len.to_be_bytes()).unwrap();
-        file.write_all(bad_json).unwrap();
+        let cut = &record(br#"{"key":"key3","old":null,"new":3,"version":1}"#)[..20];
+        file.write_all(cut).unwrap();
         file.sync_all().unwrap();
     }
 
@@ -67,3 +80,271 @@ async fn test_wal_torn_tail_recovery() {
 
     fs::remove_dir_all(test_dir).unwrap();
 }
+
+#[tokio::test]
+async fn test_wal_records_report_damage_with_lsns() {
+    let test_dir = "./test_wal_records";
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+    let store = Arc::new(RwLock::new(Store::new()));
+    {
+        let mut db = Database::open(test_dir, store.clone()).await.unwrap();
+        db.put("key1".to_string(), json!(1)).await.unwrap();
+        db.put("key2".to_string(), json!(2)).await.unwrap();
+        db.fsync_wal().unwrap();
+    }
+
+    // a whole record that isn't an event, then a good one, then a cut off one
+    let wal_file_path = format!("{}/wal/0.log", test_dir);
+    let good_len = fs::metadata(&wal_file_path).unwrap().len();
+    {
+        let mut file = OpenOptions::new().append(true).open(&wal_file_path).unwrap();
+        file.write_all(&record(b"not an event")).unwrap();
+        file.write_all(&record(br#"{"key":"key3","old":null,"new":3,"version":1}"#)).unwrap();
+        file.write_all(&record(br#"{"key":"key4","old":null,"new":4,"version":1}"#)[..20]).unwrap();
+    }
+
+    let mut wal = WalIterator::open(Path::new(test_dir), Lsn::ZERO).unwrap();
+    let mut records = vec![];
+    while let Some(record) = wal.next_record().unwrap() {
+        records.push(record);
+    }
+    assert_eq!(records.len(), 5);
+    assert_eq!(records[1].entry.as_ref().unwrap().key, "key2");
+    assert_eq!(records[2].lsn, Lsn::new(0, good_len));
+    assert!(matches!(records[2].entry, Err(Damage::Corrupt { len: 20, .. })));
+    // the framing held, so the record after the corrupt one still reads
+    assert_eq!(records[3].lsn, Lsn::new(0, good_len + 20));
+    assert_eq!(records[3].entry.as_ref().unwrap().key, "key3");
+    assert_eq!(records[4].lsn, Lsn::new(0, good_len + 20 + 8 + 45));
+    assert!(matches!(records[4].entry, Err(Damage::Torn { needed: 53, available: 20 })));
+
+    // lsns print and parse as segment:offset
+    assert_eq!("3:1024".parse::<Lsn>().unwrap(), Lsn::new(3, 1024));
+    assert!("3".parse::<Lsn>().is_err());
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_wal_checksums_catch_flips_and_garbage_lengths() {
+    let test_dir = "./test_wal_checksums";
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+    let store = Arc::new(RwLock::new(Store::new()));
+    {
+        let mut db = Database::open(test_dir, store.clone()).await.unwrap();
+        db.put("key1".to_string(), json!({"val": 1})).await.unwrap();
+        db.put("key2".to_string(), json!({"val": 2})).await.unwrap();
+        db.fsync_wal().unwrap();
+    }
+
+    // the segment starts with its header
+    let wal_file_path = format!("{}/wal/0.log", test_dir);
+    let mut bytes = fs::read(&wal_file_path).unwrap();
+    assert_eq!(&bytes[..4], b"FXWL");
+    assert_eq!(bytes[8..16], 0u64.to_be_bytes());
+
+    // a flip that still parses: {"val":1} becomes {"val":7}
+    let at = bytes.windows(9).position(|w| w == br#"{"val":1}"#).unwrap();
+    bytes[at + 7] = b'7';
+    // and a length no record can have, with plenty of file after it
+    bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+    bytes.extend_from_slice(&[0; 64]);
+    fs::write(&wal_file_path, &bytes).unwrap();
+
+    let mut wal = WalIterator::open(Path::new(test_dir), Lsn::ZERO).unwrap();
+    let mut records = vec![];
+    while let Some(record) = wal.next_record().unwrap() {
+        records.push(record);
+    }
+    assert_eq!(records[0].lsn, Lsn::new(0, 16));
+    assert!(matches!(records[0].entry, Err(Damage::Checksum { .. })));
+    assert_eq!(records[1].entry.as_ref().unwrap().key, "key2");
+    assert!(matches!(records[2].entry, Err(Damage::TooLong { len }) if len == u32::MAX as u64));
+    assert_eq!(records.len(), 3);
+
+    // good records follow the flip, so it is mid-log corruption and replay refuses it
+    assert!(Database::open(test_dir, store.clone()).await.is_err());
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_wal_reads_segments_without_a_header() {
+    let test_dir = "./test_wal_format_0";
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+    // a segment as it was written before headers and checksums: len | json
+    fs::create_dir_all(format!("{test_dir}/wal")).unwrap();
+    let event = br#"{"key":"old","old":null,"new":1,"version":1}"#;
+    fs::write(format!("{test_dir}/wal/0.log"), [&(event.len() as u32).to_be_bytes()[..], event].concat()).unwrap();
+
+    let store = Arc::new(RwLock::new(Store::new()));
+    {
+        let mut db = Database::open(test_dir, store.clone()).await.unwrap();
+        assert_eq!(store.read().await.get("old").unwrap().value, json!(1));
+        db.put("new".to_string(), json!(2)).await.unwrap();
+        db.fsync_wal().unwrap();
+    }
+    // the old segment is left as it was, writes went to a new one
+    assert_eq!(fs::metadata(format!("{test_dir}/wal/0.log")).unwrap().len(), 4 + event.len() as u64);
+    assert_eq!(&fs::read(format!("{test_dir}/wal/1.log")).unwrap()[..4], b"FXWL");
+
+    let _db = Database::open(test_dir, store.clone()).await.unwrap();
+    let guard = store.read().await;
+    assert_eq!(guard.get("old").unwrap().value, json!(1));
+    assert_eq!(guard.get("new").unwrap().value, json!(2));
+    drop(guard);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+// key0..key5 written round after round into 4k segments, so there are several
+async fn fill_segments(test_dir: &str) {
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+    let store = Arc::new(RwLock::new(Store::new()));
+    let mut db = Database::open_with(Path::new(test_dir), 4096, Recovery::Strict, store).await.unwrap();
+    for i in 0..60 {
+        let event = db.put(format!("key{}", i % 6), json!({"i": i, "pad": "x".repeat(100)})).await.unwrap();
+        db.execute_post_durability(event).await.unwrap();
+    }
+    db.fsync_wal().unwrap();
+    assert!(Path::new(&format!("{test_dir}/wal/2.log")).exists());
+}
+
+#[tokio::test]
+async fn test_wal_mid_log_corruption_is_fatal_unless_best_effort() {
+    let test_dir = "./test_wal_mid_log";
+    fill_segments(test_dir).await;
+
+    // flip a byte in the second record of segment 0, which wrote key1 = {"i": 1}
+    let wal_file_path = format!("{}/wal/0.log", test_dir);
+    let mut bytes = fs::read(&wal_file_path).unwrap();
+    let first_len = u32::from_be_bytes(bytes[16..20].try_into().unwrap()) as u64;
+    let second = 16 + 8 + first_len;
+    bytes[second as usize + 8 + 2] ^= 0x20;
+    fs::write(&wal_file_path, &bytes).unwrap();
+
+    let store = Arc::new(RwLock::new(Store::new()));
+    let error = Database::open_with(Path::new(test_dir), 4096, Recovery::Strict, store.clone())
+        .await
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(error.contains(&format!("segment 0 at offset {second}")), "{error}");
+    assert!(error.contains("Last good record at 0:16"), "{error}");
+    assert!(error.contains("--recover-best-effort"), "{error}");
+    // nothing was cut or rewritten
+    assert_eq!(fs::read(&wal_file_path).unwrap(), bytes);
+
+    let db = Database::open_with(Path::new(test_dir), 4096, Recovery::BestEffort, store.clone())
+        .await
+        .unwrap();
+    assert_eq!(db.skipped_on_open, 1);
+    // everything but the damaged write, later segments included
+    let guard = store.read().await;
+    assert_eq!(guard.get("key0").unwrap().value["i"], 54);
+    assert_eq!(guard.get("key1").unwrap().value["i"], 55);
+    assert_eq!(guard.get("key1").unwrap().version, 10);
+    drop(guard);
+    drop(db);
+
+    // the engine snapshots past the damage, so the next strict start works
+    let config = EngineConfig {
+        data_dir: test_dir.into(),
+        segment_size: 4096,
+        recovery: Recovery::BestEffort,
+        ..EngineConfig::default()
+    };
+    let handle = EngineRuntime::open(config).await.unwrap().handle;
+    assert_eq!(handle.get("key5".to_string()).await.unwrap().unwrap().value["i"], 59);
+    let store = Arc::new(RwLock::new(Store::new()));
+    Database::open_with(Path::new(test_dir), 4096, Recovery::Strict, store.clone()).await.unwrap();
+    assert_eq!(store.read().await.get("key1").unwrap().value["i"], 55);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_wal_sealed_segment_cut_short_is_fatal() {
+    let test_dir = "./test_wal_sealed_torn";
+    fill_segments(test_dir).await;
+
+    // segment 0 loses its last few bytes: torn, but segment 1 was written after it
+    let wal_file_path = format!("{}/wal/0.log", test_dir);
+    let len = fs::metadata(&wal_file_path).unwrap().len();
+    OpenOptions::new().write(true).open(&wal_file_path).unwrap().set_len(len - 5).unwrap();
+
+    let config = EngineConfig {
+        data_dir: test_dir.into(),
+        segment_size: 4096,
+        ..EngineConfig::default()
+    };
+    let error = EngineRuntime::open(config).await.err().unwrap();
+    assert!(error.contains("wal corruption in segment 0"), "{error}");
+    assert_eq!(fs::metadata(&wal_file_path).unwrap().len(), len - 5);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+// 100 records, each fsynced, in one segment
+async fn write_fsynced(test_dir: &str) -> String {
+    if fs::metadata(test_dir).is_ok() {
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+    let store = Arc::new(RwLock::new(Store::new()));
+    let mut db = Database::open(test_dir, store).await.unwrap();
+    for i in 0..100 {
+        let event = db.put(format!("key{}", i % 10), json!({"i": i})).await.unwrap();
+        db.fsync_wal().unwrap();
+        db.execute_post_durability(event).await.unwrap();
+    }
+    format!("{test_dir}/wal/0.log")
+}
+
+#[tokio::test]
+async fn test_wal_bad_length_on_committed_records_is_not_a_torn_tail() {
+    // the high byte of the first length: a length running past everything after it
+    let test_dir = "./test_wal_bad_length";
+    let wal_file_path = write_fsynced(test_dir).await;
+    let mut bytes = fs::read(&wal_file_path).unwrap();
+    bytes[16] = 0xff;
+    fs::write(&wal_file_path, &bytes).unwrap();
+
+    let mut iter = WalIterator::segment(Path::new(test_dir), 0).unwrap();
+    assert!(matches!(iter.next_record().unwrap().unwrap().entry, Err(Damage::TooLong { .. })));
+
+    let store = Arc::new(RwLock::new(Store::new()));
+    let error = Database::open(test_dir, store).await.err().unwrap().to_string();
+    assert!(error.contains("segment 0 at offset 16"), "{error}");
+    // the 100 writes are all still there
+    assert_eq!(fs::read(&wal_file_path).unwrap(), bytes);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_wal_checksum_with_misframed_records_after_it_is_not_a_torn_tail() {
+    // the low byte of the first length: the checksum fails and every record after it misframes
+    let test_dir = "./test_wal_misframed";
+    let wal_file_path = write_fsynced(test_dir).await;
+    let mut bytes = fs::read(&wal_file_path).unwrap();
+    bytes[19] ^= 0x01;
+    fs::write(&wal_file_path, &bytes).unwrap();
+
+    let mut iter = WalIterator::segment(Path::new(test_dir), 0).unwrap();
+    assert!(matches!(iter.next_record().unwrap().unwrap().entry, Err(Damage::Checksum { .. })));
+
+    let store = Arc::new(RwLock::new(Store::new()));
+    let error = Database::open(test_dir, store).await.err().unwrap().to_string();
+    assert!(error.contains("segment 0 at offset 16"), "{error}");
+    assert_eq!(fs::read(&wal_file_path).unwrap(), bytes);
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
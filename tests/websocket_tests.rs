@@ -0,0 +1,112 @@
+use fluxdb::engine::runtime::EngineRuntime;
+use fluxdb::net::protocol::{Request, RequestFrame, Response, ResponseFrame};
+use fluxdb::net::limits::ServerLimits;
+use fluxdb::net::ws::handle_ws_connection;
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use std::collections::HashSet;
+use tokio::net::TcpListener;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+#[tokio::test]
+async fn test_websocket_request_response_and_subscription() {
+    let runtime = EngineRuntime::start();
+    let handle = runtime.handle;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        handle_ws_connection(
+            stream,
+            handle,
+            None,
+            ServerLimits::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+    });
+
+    let (mut ws, _) = connect_async(format!("ws://{addr}")).await.unwrap();
+
+    let mut send = async |req: Request| {
+        let text = serde_json::to_string(&req).unwrap();
+        ws.send(Message::Text(text.into())).await.unwrap();
+        match ws.next().await.unwrap().unwrap() {
+            Message::Text(text) => serde_json::from_str::<Response>(&text).unwrap(),
+            other => panic!("unexpected frame {other:?}"),
+        }
+    };
+
+    let resp = send(Request::Subscribe { key: "ws_key".to_string() }).await;
+    assert!(matches!(resp, Response::Subscribed { .. }));
+
+    // set answers Ok, and the subscription streams the event on the same socket
+    let resp = send(Request::Set { key: "ws_key".to_string(), value: json!({"n": 1}) }).await;
+    let mut frames = vec![resp];
+    let next = ws.next().await.unwrap().unwrap();
+    frames.push(serde_json::from_str(next.to_text().unwrap()).unwrap());
+
+    assert!(frames.iter().any(|r| matches!(r, Response::Ok)));
+    assert!(frames.iter().any(|r| matches!(
+        r,
+        Response::Event { event } if event.key == "ws_key" && event.new == json!({"n": 1})
+    )));
+
+    ws.send(Message::Text(r#"{"kind":"get","key":"ws_key"}"#.into())).await.unwrap();
+    let resp: Response = serde_json::from_str(ws.next().await.unwrap().unwrap().to_text().unwrap()).unwrap();
+    match resp {
+        Response::Value { doc: Some(doc) } => assert_eq!(doc.value, json!({"n": 1})),
+        other => panic!("unexpected response {other:?}"),
+    }
+
+    ws.send(Message::Text("not json".into())).await.unwrap();
+    let resp: Response = serde_json::from_str(ws.next().await.unwrap().unwrap().to_text().unwrap()).unwrap();
+    assert!(matches!(resp, Response::Error { .. }));
+}
+
+#[tokio::test]
+async fn test_pipelined_requests_echo_ids() {
+    let runtime = EngineRuntime::start();
+    let handle = runtime.handle;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        handle_ws_connection(
+            stream,
+            handle,
+            None,
+            ServerLimits::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+    });
+
+    let (mut ws, _) = connect_async(format!("ws://{addr}")).await.unwrap();
+
+    // fire everything before reading anything back
+    for id in 0..20u64 {
+        let req = if id % 2 == 0 {
+            Request::Set { key: format!("pipe_{id}"), value: json!(id) }
+        } else {
+            Request::Get { key: format!("pipe_{}", id - 1) }
+        };
+        let frame = RequestFrame { id: Some(id), trace_id: None, req };
+        ws.send(Message::Text(serde_json::to_string(&frame).unwrap().into())).await.unwrap();
+    }
+
+    // replies may come back in any order, but each id exactly once
+    let mut seen = HashSet::new();
+    for _ in 0..20 {
+        let msg = ws.next().await.unwrap().unwrap();
+        let frame: ResponseFrame = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+        let id = frame.id.expect("response must echo the request id");
+        assert!(!matches!(frame.resp, Response::Error { .. }));
+        assert!(seen.insert(id));
+    }
+    assert_eq!(seen, (0..20).collect::<HashSet<_>>());
+}